@@ -1,5 +1,12 @@
-use core::lang::Language;
+use core::{
+    config::DetectionConfig,
+    evidence::EvidenceKind,
+    lang::Language,
+    matching::Rename,
+    pipeline::{Severity, SourceFile, Stage},
+};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LoginRequest {
@@ -10,7 +17,7 @@ pub struct LoginRequest {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Submission {
     pub name: String,
-    pub code: String,
+    pub files: Vec<SourceFile>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -18,5 +25,283 @@ pub struct SubmitRequest {
     pub login: Option<LoginRequest>,
     pub language: Language,
     pub template: Option<String>,
+    // files of the template, subtracted along with `template` but each matched on its own
+    #[serde(default)]
+    pub template_files: Vec<SourceFile>,
+    // ids of templates in the library of the user, subtracted along with `template`
+    #[serde(default)]
+    pub templates: Vec<i32>,
     pub submissions: Vec<Submission>,
+    #[serde(default)]
+    pub config: DetectionConfig,
+    // add submissions to archive under this label, e.g. semester,
+    // so that later jobs are compared against them
+    #[serde(default)]
+    pub archive: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SubmitResponse {
+    pub slug: String,
+    // results page
+    pub url: String,
+}
+
+/// Store a named template of one or more files in the library of the user
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreateTemplateRequest {
+    pub login: Option<LoginRequest>,
+    pub name: String,
+    pub language: Language,
+    pub files: Vec<SourceFile>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ListTemplatesRequest {
+    pub login: Option<LoginRequest>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TemplateInfo {
+    // to be referenced in `SubmitRequest::templates`
+    pub id: i32,
+    pub name: String,
+    pub language: Language,
+    // file names
+    pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the job has stopped, successfully or not
+    pub fn finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Done | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+impl FromStr for JobState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "queued" => JobState::Queued,
+            "running" => JobState::Running,
+            "done" => JobState::Done,
+            "failed" => JobState::Failed,
+            "cancelled" => JobState::Cancelled,
+            _ => return Err(format!("Unknown job state: {}", s)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobStatus {
+    pub slug: String,
+    pub state: JobState,
+    // current stage of a running job
+    pub stage: Option<Stage>,
+    // candidate pairs aligned so far
+    pub pairs_done: i32,
+    pub pairs_total: i32,
+    // reason of failure
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CancelRequest {
+    pub login: Option<LoginRequest>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileInfo {
+    pub id: i32,
+    pub name: String,
+    pub lines: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SubmissionInfo {
+    pub id: i32,
+    pub name: String,
+    // label of archive, if submission comes from an earlier job
+    pub archive: Option<String>,
+    pub files: Vec<FileInfo>,
+    // problems found while tokenizing, the rest of the submission is still checked
+    pub diagnostics: Vec<DiagnosticInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiagnosticInfo {
+    // file name
+    pub file: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchInfo {
+    // index of match in job, same as in `/results/{slug}/{index}/`
+    pub index: usize,
+    pub left_submission_id: i32,
+    pub left_submission_name: String,
+    pub left_match_rate: i32,
+    pub right_submission_id: i32,
+    pub right_submission_name: String,
+    pub right_match_rate: i32,
+    pub lines_matched: i32,
+    // percentage of lines copied with the same spellings, 0 unless enabled
+    pub left_verbatim_rate: i32,
+    pub right_verbatim_rate: i32,
+    // percentage of matched tokens
+    pub left_token_rate: i32,
+    pub right_token_rate: i32,
+    // percentage of fingerprints shared among those of either side
+    pub similarity: i32,
+    // percentage of fingerprints of one side found in the other
+    pub left_containment: i32,
+    pub right_containment: i32,
+    // matched tokens of the longest block
+    pub longest_block: i32,
+    // sum of rarity weights of shared fingerprints in thousandths, matches are indexed by it
+    pub score: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockInfo {
+    pub left_file_id: i32,
+    pub right_file_id: i32,
+    // 0-based, `line_to` is inclusive
+    pub left_line_from: i32,
+    pub left_line_to: i32,
+    pub right_line_from: i32,
+    pub right_line_to: i32,
+    // 0-based character columns, `column_to` is exclusive
+    pub left_column_from: i32,
+    pub left_column_to: i32,
+    pub right_column_from: i32,
+    pub right_column_to: i32,
+    // unmatched tokens within the block, e.g. inserted statements
+    pub left_gap: i32,
+    pub right_gap: i32,
+    // identifiers renamed between the sides, most used first
+    pub renames: Vec<Rename>,
+    // percentage of aligned identifiers following the renames
+    pub rename_consistency: i32,
+}
+
+/// Rare comment or literal found on both sides of a match
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EvidenceInfo {
+    pub kind: EvidenceKind,
+    pub text: String,
+    // 0-based lines of the first occurrence on each side
+    pub left_file_id: i32,
+    pub left_line: i32,
+    pub right_file_id: i32,
+    pub right_line: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchDetail {
+    #[serde(flatten)]
+    pub info: MatchInfo,
+    pub blocks: Vec<BlockInfo>,
+    pub evidence: Vec<EvidenceInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchSort {
+    #[default]
+    Index,
+    LinesMatched,
+    // higher of both match rates
+    MatchRate,
+    LeftMatchRate,
+    RightMatchRate,
+    // higher of both verbatim rates
+    VerbatimRate,
+    // higher of both token rates
+    TokenRate,
+    LeftTokenRate,
+    RightTokenRate,
+    Similarity,
+    // higher of both containments
+    Containment,
+    LeftContainment,
+    RightContainment,
+    LongestBlock,
+    Score,
+}
+
+/// Query string of match list
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MatchQuery {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub sort: MatchSort,
+    // descending order
+    #[serde(default)]
+    pub desc: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchList {
+    // number of matches in job
+    pub total: usize,
+    pub matches: Vec<MatchInfo>,
+}
+
+/// Share job with a user or with everyone of a role
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShareRequest {
+    pub login: Option<LoginRequest>,
+    pub user_name: Option<String>,
+    pub role: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShareLinkRequest {
+    pub login: Option<LoginRequest>,
+    // lifetime of link
+    pub hours: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShareLinkResponse {
+    pub token: String,
+    // results page with token
+    pub url: String,
+    // RFC 3339, UTC
+    pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RevokeLinkRequest {
+    pub login: Option<LoginRequest>,
+    pub token: String,
 }
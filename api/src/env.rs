@@ -5,6 +5,8 @@ pub struct Env {
     pub database_url: String,
     pub cookie_secret: String,
     pub public_url: String,
+    // number of jobs processed at the same time
+    pub workers: usize,
 }
 
 fn get_env() -> Env {
@@ -12,6 +14,9 @@ fn get_env() -> Env {
         database_url: var("DATABASE_URL").expect("DATABASE_URL"),
         cookie_secret: var("COOKIE_SECRET").expect("COOKIE_SECRET"),
         public_url: var("PUBLIC_URL").expect("PUBLIC_URL"),
+        workers: var("WORKERS")
+            .map(|w| w.parse().expect("WORKERS"))
+            .unwrap_or(2),
     }
 }
 
@@ -1,14 +1,22 @@
 use api::{
-    def::{LoginRequest, Submission, SubmitRequest},
+    def::{
+        CancelRequest, CreateTemplateRequest, JobState, JobStatus, ListTemplatesRequest,
+        LoginRequest, RevokeLinkRequest, ShareLinkRequest, ShareLinkResponse, Submission,
+        SubmissionInfo, SubmitRequest, SubmitResponse, TemplateInfo,
+    },
     env::ENV,
 };
 use clap::Parser;
-use core::lang::Language;
+use core::{
+    config::DetectionConfig,
+    lang::Language,
+    pipeline::{SourceFile, Stage},
+};
 use dotenv::dotenv;
 use encoding::{DecoderTrap, Encoding};
 use log::{info, warn};
 use regex::Regex;
-use std::{ffi::OsString, path::Path, path::PathBuf};
+use std::{ffi::OsString, path::Path, path::PathBuf, time::Duration};
 use walkdir::WalkDir;
 
 #[derive(Parser)]
@@ -22,19 +30,54 @@ struct Args {
     password: String,
 
     /// Language
-    #[arg(short, long)]
-    language: String,
+    #[arg(short, long, required_unless_present_any = ["cancel", "share", "revoke_link", "list_templates"])]
+    language: Option<String>,
+
+    /// Cancel the job with this slug instead of submitting
+    #[arg(long)]
+    cancel: Option<String>,
+
+    /// Create a share link of the job with this slug instead of submitting
+    #[arg(long)]
+    share: Option<String>,
+
+    /// Revoke a share link instead of submitting
+    #[arg(long)]
+    revoke_link: Option<String>,
+
+    /// Hours until share links expire, the link printed after submission included
+    #[arg(long, default_value_t = 168)]
+    link_hours: u32,
 
     /// Path to template file
     #[arg(short = 'b', long)]
     template: Option<PathBuf>,
 
+    /// Id of template in the library on server, may be given multiple times
+    #[arg(long)]
+    template_id: Vec<i32>,
+
+    /// Store the template given by --template in the library under this name instead of submitting
+    #[arg(long, requires = "template")]
+    save_template: Option<String>,
+
+    /// List templates in the library instead of submitting
+    #[arg(long)]
+    list_templates: bool,
+
     /// Regex to filter file name when paths are directories
     #[arg(short = 'r', long)]
     regex: Option<Regex>,
 
+    /// Archive submissions under this label, so that later jobs are compared against them
+    #[arg(short, long)]
+    archive: Option<String>,
+
     /// Paths to source code
     code: Vec<PathBuf>,
+
+    #[command(flatten)]
+    config: DetectionConfig,
 }
 
 fn read_file(path: &Path) -> String {
@@ -63,16 +106,7 @@ fn read_file(path: &Path) -> String {
     }
 }
 
-fn collect(language: &Language, path: &Path, regex: &Option<Regex>) -> String {
-    let comment = match &language {
-        Language::Cpp => "//",
-        Language::Rust => "//",
-        Language::Python => "#",
-        Language::Verilog => "//",
-        Language::SQL => "--",
-        Language::JavaScript => "//",
-        Language::Lua => "--",
-    };
+fn collect(language: &Language, path: &Path, regex: &Option<Regex>) -> Vec<SourceFile> {
     let extensions = match &language {
         Language::Cpp => ["cpp", "h"].to_vec(),
         Language::Rust => ["rs"].to_vec(),
@@ -85,10 +119,17 @@ fn collect(language: &Language, path: &Path, regex: &Option<Regex>) -> String {
 
     if std::path::Path::new(path).is_file() {
         // one file
-        read_file(path)
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        vec![SourceFile {
+            name,
+            code: read_file(path),
+        }]
     } else {
-        // find all sources and concat
-        let mut source_code = String::new();
+        // find all sources, named by path relative to the directory
+        let mut files = vec![];
         for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
             for ext in &extensions {
                 if entry.path().extension() == Some(&OsString::from(ext)) {
@@ -97,55 +138,253 @@ fn collect(language: &Language, path: &Path, regex: &Option<Regex>) -> String {
                             continue;
                         }
                     }
-                    source_code += &format!("{} {} \n", comment, entry.path().display());
-                    source_code += &read_file(entry.path());
-                    source_code += "\n";
+                    let name = entry.path().strip_prefix(path).unwrap_or(entry.path());
+                    files.push(SourceFile {
+                        name: format!("{}", name.display()),
+                        code: read_file(entry.path()),
+                    });
                     break;
                 }
             }
         }
-        source_code
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        files
+    }
+}
+
+/// Poll status of job via share link token until it finishes, printing progress
+fn wait(client: &reqwest::blocking::Client, slug: &str, token: &str) -> anyhow::Result<JobStatus> {
+    let mut last = String::new();
+    loop {
+        let status: JobStatus = client
+            .get(format!("{}/api/jobs/{}", ENV.public_url, slug))
+            .query(&[("token", token)])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let line = match status.stage {
+            Some(Stage::Align) if status.state == JobState::Running => format!(
+                "{}: {:?} {}/{} pairs",
+                status.state.as_str(),
+                Stage::Align,
+                status.pairs_done,
+                status.pairs_total
+            ),
+            Some(stage) if status.state == JobState::Running => {
+                format!("{}: {:?}", status.state.as_str(), stage)
+            }
+            _ => status.state.as_str().to_string(),
+        };
+        if line != last {
+            eprintln!("{}", line);
+            last = line;
+        }
+        if status.state.finished() {
+            return Ok(status);
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Create share link of job
+fn create_link(
+    client: &reqwest::blocking::Client,
+    login: LoginRequest,
+    slug: &str,
+    hours: u32,
+) -> anyhow::Result<ShareLinkResponse> {
+    let body = client
+        .post(format!("{}/api/jobs/{}/links", ENV.public_url, slug))
+        .json(&ShareLinkRequest {
+            login: Some(login),
+            hours,
+        })
+        .send()?
+        .text()?;
+    match serde_json::from_str::<ShareLinkResponse>(&body) {
+        Ok(link) => {
+            eprintln!("Share link expires at {}", link.expires_at);
+            Ok(link)
+        }
+        Err(_) => Err(anyhow::anyhow!("Failed to create share link: {}", body)),
     }
 }
 
+/// Print files of finished job that could only be checked in part
+fn print_diagnostics(
+    client: &reqwest::blocking::Client,
+    slug: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let submissions: Vec<SubmissionInfo> = client
+        .get(format!("{}/api/jobs/{}/submissions", ENV.public_url, slug))
+        .query(&[("token", token)])
+        .send()?
+        .error_for_status()?
+        .json()?;
+    for submission in submissions {
+        for d in submission.diagnostics {
+            eprintln!(
+                "{:?}: {}/{}: {}",
+                d.severity, submission.name, d.file, d.message
+            );
+        }
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     dotenv().ok();
     let opts = Args::parse();
     env_logger::init();
 
-    let language = match opts.language.as_str() {
+    let client = reqwest::blocking::Client::new();
+    let login = LoginRequest {
+        user_name: opts.user_name,
+        password: opts.password,
+    };
+    if let Some(slug) = &opts.cancel {
+        let cancelled: bool = client
+            .post(format!("{}/api/jobs/{}/cancel", ENV.public_url, slug))
+            .json(&CancelRequest { login: Some(login) })
+            .send()?
+            .error_for_status()?
+            .json()?;
+        if !cancelled {
+            return Err(anyhow::anyhow!("Failed to cancel job {}", slug));
+        }
+        println!("Cancelled job {}", slug);
+        return Ok(());
+    }
+    if let Some(slug) = &opts.share {
+        println!(
+            "{}",
+            create_link(&client, login, slug, opts.link_hours)?.url
+        );
+        return Ok(());
+    }
+    if let Some(url) = &opts.revoke_link {
+        // url looks like {public_url}/results/{slug}/?token={token}
+        let re = Regex::new(r"/results/([^/]+)/\?token=([^&]+)").unwrap();
+        let captures = re
+            .captures(url)
+            .ok_or_else(|| anyhow::anyhow!("Not a share link: {}", url))?;
+        let revoked: bool = client
+            .post(format!(
+                "{}/api/jobs/{}/links/revoke",
+                ENV.public_url, &captures[1]
+            ))
+            .json(&RevokeLinkRequest {
+                login: Some(login),
+                token: captures[2].to_string(),
+            })
+            .send()?
+            .error_for_status()?
+            .json()?;
+        if !revoked {
+            return Err(anyhow::anyhow!("Failed to revoke share link {}", url));
+        }
+        println!("Revoked share link {}", url);
+        return Ok(());
+    }
+
+    if opts.list_templates {
+        let body = client
+            .post(format!("{}/api/templates/list", ENV.public_url))
+            .json(&ListTemplatesRequest { login: Some(login) })
+            .send()?
+            .text()?;
+        let templates: Vec<TemplateInfo> = match serde_json::from_str(&body) {
+            Ok(templates) => templates,
+            Err(_) => return Err(anyhow::anyhow!("Failed to list templates: {}", body)),
+        };
+        for template in templates {
+            println!(
+                "{}\t{:?}\t{}\t{}",
+                template.id,
+                template.language,
+                template.name,
+                template.files.join(", ")
+            );
+        }
+        return Ok(());
+    }
+
+    let language = opts.language.unwrap_or_default();
+    let language = match language.as_str() {
         "c++" | "cpp" | "cc" => Language::Cpp,
         "python" | "py" => Language::Python,
         "rust" => Language::Rust,
-        _ => unimplemented!("Language: {}", opts.language),
+        _ => unimplemented!("Language: {}", language),
     };
 
-    let client = reqwest::blocking::Client::new();
-    let template = opts
+    if let (Some(name), Some(template)) = (&opts.save_template, &opts.template) {
+        let body = client
+            .post(format!("{}/api/templates", ENV.public_url))
+            .json(&CreateTemplateRequest {
+                login: Some(login),
+                name: name.clone(),
+                language,
+                files: collect(&language, template, &opts.regex),
+            })
+            .send()?
+            .text()?;
+        let template: TemplateInfo = match serde_json::from_str(&body) {
+            Ok(template) => template,
+            Err(_) => return Err(anyhow::anyhow!("Failed to save template: {}", body)),
+        };
+        println!("{}", template.id);
+        return Ok(());
+    }
+
+    // files are sent apart, so that no template match spans two of them
+    let template_files = opts
         .template
         .as_ref()
-        .map(|template| collect(&language, template, &opts.regex));
+        .map(|template| collect(&language, template, &opts.regex))
+        .unwrap_or_default();
     let body = client
         .post(format!("{}/api/submit", ENV.public_url))
         .json(&SubmitRequest {
-            login: Some(LoginRequest {
-                user_name: opts.user_name,
-                password: opts.password,
-            }),
+            login: Some(login.clone()),
             language,
-            template,
+            template: None,
+            template_files,
+            templates: opts.template_id,
             submissions: opts
                 .code
                 .iter()
                 .map(|code| Submission {
                     name: format!("{}", code.display()),
-                    code: collect(&language, code, &opts.regex),
+                    files: collect(&language, code, &opts.regex),
                 })
                 .collect::<Vec<_>>(),
+            config: opts.config,
+            archive: opts.archive,
         })
         .send()?
         .text()?;
-    println!("{}", body);
+    let res: SubmitResponse = match serde_json::from_str(&body) {
+        Ok(res) => res,
+        Err(_) => return Err(anyhow::anyhow!("Submission rejected: {}", body)),
+    };
+    eprintln!("Submitted job {}", res.slug);
+    // status and results are only visible to owner, so use and hand out a link
+    let link = create_link(&client, login, &res.slug, opts.link_hours)?;
+    let status = wait(&client, &res.slug, &link.token)?;
+    match status.state {
+        JobState::Done => {
+            print_diagnostics(&client, &res.slug, &link.token)?;
+            println!("{}", link.url);
+        }
+        JobState::Failed => {
+            return Err(anyhow::anyhow!(
+                "Job failed: {}",
+                status.error.unwrap_or_default()
+            ))
+        }
+        _ => return Err(anyhow::anyhow!("Job {}", status.state.as_str())),
+    }
 
     Ok(())
 }
@@ -1,11 +1,11 @@
 use clap::Parser;
-use core::{common::gen_svg, lang::tokenize, matching::compute_matches_from_token};
-use rkr_gst::Match;
-use std::{
-    fs::File,
-    io::{Read, Write},
-    path::{Path, PathBuf},
+use core::{
+    common::{gen_svg, highlight_html, Highlight},
+    config::DetectionConfig,
+    lang::{language_of, normalize::normalize, tokenize},
+    matching::{compute_matches_from_token, template_kinds, TokenMatch},
 };
+use std::{fs::File, io::Write, path::PathBuf};
 
 #[derive(Parser)]
 struct Args {
@@ -17,46 +17,46 @@ struct Args {
     #[arg(short, long)]
     right: PathBuf,
 
-    /// Path to template source
+    /// Paths to template sources, may be given multiple times
     #[arg(short, long)]
-    template: Option<PathBuf>,
-}
+    template: Vec<PathBuf>,
 
-fn read_file_lines(s: &Path) -> anyhow::Result<Vec<String>> {
-    let mut file = File::open(s)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
-    Ok(s.lines().map(String::from).collect::<Vec<String>>())
+    #[command(flatten)]
+    config: DetectionConfig,
 }
 
 fn main() -> anyhow::Result<()> {
     let opts = Args::parse();
     env_logger::init();
+    opts.config.validate()?;
+
+    let language = language_of(&opts.left);
+    let normalized = |path: &PathBuf| {
+        let mut tokens = tokenize(path).unwrap();
+        if let Some(language) = language {
+            normalize(&mut tokens, language, &opts.config);
+        }
+        tokens
+    };
 
-    let token_left = core::lang::tokenize(&opts.left).unwrap();
-    let token_kind_left: Vec<u8> = token_left.iter().map(|t| t.kind).collect();
-    let lines_left = read_file_lines(&opts.left)?;
+    let token_left = normalized(&opts.left);
+    let token_kind_left: Vec<u16> = token_left.iter().map(|t| t.kind).collect();
+    let content_left = std::fs::read_to_string(&opts.left)?;
 
-    let token_right: Vec<core::token::Token> = core::lang::tokenize(&opts.right).unwrap();
-    let token_kind_right: Vec<u8> = token_right.iter().map(|t| t.kind).collect();
-    let lines_right = read_file_lines(&opts.right)?;
+    let token_right: Vec<core::token::Token> = normalized(&opts.right);
+    let token_kind_right: Vec<u16> = token_right.iter().map(|t| t.kind).collect();
+    let content_right = std::fs::read_to_string(&opts.right)?;
 
-    let template_kind: Option<Vec<u8>> = opts.template.as_ref().map(|t| {
-        let token_template = tokenize(t).unwrap();
-        token_template.iter().map(|t| t.kind).collect()
-    });
+    let token_templates: Vec<Vec<core::token::Token>> =
+        opts.template.iter().map(normalized).collect();
 
     let matches = compute_matches_from_token(
         &token_left,
         &token_kind_left,
-        &lines_left.iter().map(|l| l.as_str()).collect::<Vec<&str>>(),
         &token_right,
         &token_kind_right,
-        &lines_right
-            .iter()
-            .map(|l| l.as_str())
-            .collect::<Vec<&str>>(),
-        template_kind.as_deref(),
+        &template_kinds(&token_templates),
+        &opts.config,
     );
 
     for is_left in [true, false] {
@@ -67,63 +67,49 @@ fn main() -> anyhow::Result<()> {
             "<html><head><meta charset=\"UTF-8\"></head><body><pre>"
         )?;
 
-        let mut last_line = 0;
-
         let colors = ["#FF0000", "#00FF00", "#0000FF", "#00FFFF", "#FF00FF"];
-        let mut matches: Vec<(usize, &Match)> = matches.iter().enumerate().collect();
+        let mut matches: Vec<(usize, &TokenMatch)> = matches.iter().enumerate().collect();
 
-        // sort by line_from
-        matches.sort_by_key(|m| {
+        // sort by position
+        let token = if is_left { &token_left } else { &token_right };
+        let range = |m: &TokenMatch| {
             if is_left {
-                token_left[m.1.pattern_index].line as usize - 1
+                m.left.clone()
             } else {
-                token_right[m.1.text_index].line as usize - 1
+                m.right.clone()
             }
-        });
-        let token = if is_left { &token_left } else { &token_right };
-        let lines = if is_left { &lines_left } else { &lines_right };
+        };
+        matches.sort_by_key(|m| token[range(m.1).start].span.start);
+
+        let content = if is_left {
+            &content_left
+        } else {
+            &content_right
+        };
+        let mut highlights = vec![];
         for (idx, m) in matches.iter() {
-            let index = if is_left {
-                m.pattern_index
-            } else {
-                m.text_index
-            };
-            let line_from = token[index].line as usize - 1;
-            let line_to = token[index + m.length - 1].line as usize - 1;
+            let first = &token[range(m).start];
+            let last = &token[range(m).end - 1];
+            let span = first.span.start..last.span.end;
 
             println!("Match #{}:", idx + 1);
-            println!("L{}-L{}:", line_from, line_to);
-            println!("{}", lines[line_from..=line_to].join("\n"));
-
-            assert!(last_line <= line_from);
-            assert!(line_from <= line_to);
-            if last_line < line_from {
-                writeln!(
-                    file,
-                    "{}",
-                    html_escape::encode_text(&lines[last_line..=(line_from - 1)].join("\n"))
-                )?;
-            }
-            last_line = line_to + 1;
+            println!(
+                "L{}:{}-L{}:{}:",
+                first.line - 1,
+                first.column - 1,
+                last.end_line - 1,
+                last.end_column - 1
+            );
+            println!("{}", &content[span.clone()]);
 
             let color = colors[idx % 5];
-            write!(file, "<font color=\"{}\">", color)?;
-            writeln!(file, "{}", gen_svg(color, 0))?;
-            writeln!(
-                file,
-                "{}",
-                html_escape::encode_text(&lines[line_from..=line_to].join("\n"))
-            )?;
-            write!(file, "</font>")?;
-        }
-
-        if last_line < lines.len() {
-            writeln!(
-                file,
-                "{}",
-                html_escape::encode_text(&lines[last_line..].join("\n"))
-            )?;
+            highlights.push(Highlight {
+                span,
+                color,
+                prefix: gen_svg(color, 0),
+            });
         }
+        writeln!(file, "{}", highlight_html(content, &highlights))?;
 
         writeln!(file, "</pre></body></html>")?;
     }
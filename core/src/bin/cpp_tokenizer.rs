@@ -0,0 +1,8 @@
+//! Worker process of the C/C++ tokenizer, see `core::lang::tokenizer::cpp::libclang`
+use core::lang::tokenizer::cpp::libclang::serve;
+use std::io::{stdin, stdout, BufWriter};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    serve(stdin().lock(), BufWriter::new(stdout().lock()))
+}
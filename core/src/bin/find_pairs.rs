@@ -1,13 +1,17 @@
-use clap::Parser;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser};
 use core::{
-    common::{all_fingerprint, fingerprint, Fingerprint},
-    token::Token,
+    config::DetectionConfig,
+    lang::language_of,
+    pipeline::{run, Source, SourceFile},
 };
 use log::*;
 use regex::Regex;
-use std::{collections::HashMap, fs::read_dir, path::PathBuf};
+use std::{collections::HashMap, fs::read_dir, path::Path, path::PathBuf};
 use walkdir::WalkDir;
 
+// number of pairs reported per file unless `--max-pairs` is given, as before it was configurable
+const DEFAULT_MAX_PAIRS: usize = 20;
+
 #[derive(Parser)]
 struct Args {
     /// Path to source directory
@@ -21,164 +25,144 @@ struct Args {
     /// Regex patterns for files to include
     #[arg(short, long)]
     include: Vec<Regex>,
-}
 
-fn main() -> anyhow::Result<()> {
-    let opts = Args::parse();
-    env_logger::init();
+    #[command(flatten)]
+    config: DetectionConfig,
+}
 
-    // walk template directory
-    info!("Processing template directory");
-    let mut template_tokens = HashMap::new();
-    for entry in WalkDir::new(&opts.template_directory) {
+/// Read included files under directory, keyed by relative path
+fn read_included(directory: &Path, include: &[Regex]) -> anyhow::Result<HashMap<PathBuf, String>> {
+    let mut res = HashMap::new();
+    for entry in WalkDir::new(directory) {
         let entry = entry?;
         let path = entry.path();
-        let relative_path = path.strip_prefix(&opts.template_directory)?;
-        let mut include = false;
-        for pattern in &opts.include {
+        let relative_path = path.strip_prefix(directory)?;
+        let mut included = false;
+        for pattern in include {
             if pattern.is_match(&relative_path.display().to_string()) {
-                include = true;
+                included = true;
                 break;
             }
         }
-        if include {
-            match core::lang::tokenize(path) {
-                Ok(tokens) => {
-                    template_tokens.insert(relative_path.to_path_buf(), tokens);
+        if included {
+            match std::fs::read_to_string(path) {
+                Ok(content) => {
+                    res.insert(relative_path.to_path_buf(), content);
                 }
                 Err(err) => {
-                    warn!("Tokenize {} failed with {}", path.display(), err);
+                    warn!("Read {} failed with {}", path.display(), err);
                 }
             }
         }
     }
+    Ok(res)
+}
+
+fn main() -> anyhow::Result<()> {
+    let matches = Args::command().get_matches();
+    let mut opts = Args::from_arg_matches(&matches)?;
+    if matches.value_source("max_pairs") == Some(ValueSource::DefaultValue) {
+        opts.config.max_pairs = DEFAULT_MAX_PAIRS;
+    }
+    env_logger::init();
+    let config = &opts.config;
+    config.validate()?;
+
+    // walk template directory
+    info!("Processing template directory");
+    let template_files = read_included(&opts.template_directory, &opts.include)?;
 
     // walk source directory
     info!("Processing source directory");
     let submissions = read_dir(&opts.source_directory).unwrap();
-    // map: file => submission => tokens
-    let mut all_tokens: HashMap<PathBuf, HashMap<PathBuf, Vec<Token>>> = HashMap::new();
+    // map: file => sources of submissions
+    let mut all_sources: HashMap<PathBuf, Vec<Source>> = HashMap::new();
     for submission in submissions {
         let submission = submission?;
         if !submission.file_type()?.is_dir() {
             continue;
         }
-        let submission_directory = submission.path();
-        for entry in WalkDir::new(&submission_directory) {
-            let entry = entry?;
-            let path = entry.path();
-            let relative_path = path.strip_prefix(&submission_directory)?;
-            let mut include = false;
-            for pattern in &opts.include {
-                if pattern.is_match(&relative_path.display().to_string()) {
-                    include = true;
-                    break;
-                }
-            }
-            if include {
-                match core::lang::tokenize(path) {
-                    Ok(tokens) => {
-                        all_tokens
-                            .entry(relative_path.to_path_buf())
-                            .or_default()
-                            .insert(submission.path(), tokens);
-                    }
-                    Err(err) => {
-                        warn!("Tokenize {} failed with {}", path.display(), err);
-                    }
-                }
-            }
+        for (relative_path, code) in read_included(&submission.path(), &opts.include)? {
+            let name = relative_path.display().to_string();
+            all_sources.entry(relative_path).or_default().push(Source {
+                name: submission.path().display().to_string(),
+                files: vec![SourceFile { name, code }],
+            });
         }
     }
 
-    info!("Tokenized {} files in source directory", all_tokens.len());
-    for submission in all_tokens.keys() {
-        info!("Processing file {}", submission.display());
-        let keys: Vec<&PathBuf> = all_tokens[submission].keys().collect();
-
-        if !template_tokens.contains_key(submission) {
-            warn!("No template found for {}!", submission.display());
+    info!("Found {} files in source directory", all_sources.len());
+    for (file, sources) in &all_sources {
+        info!("Processing file {}", file.display());
+        let Some(language) = language_of(file) else {
+            warn!("Unsupported file {}, skipping", file.display());
             continue;
-        }
-
-        // https://theory.stanford.edu/~aiken/publications/papers/sigmod03.pdf
-        let template_token = &template_tokens[submission];
-        let template_fingerprint = all_fingerprint(template_token.iter().map(|t| t.kind), 40);
-        let mut local_tokens = vec![];
-        let mut local_fingerprints = vec![];
-        let mut index: HashMap<u64, Vec<(Fingerprint, usize)>> = HashMap::new();
-        for (i, key) in keys.iter().enumerate() {
-            let token = all_tokens[submission][*key].clone();
-            let fingerprint = fingerprint(token.iter().map(|t| t.kind), 40, 80);
-            info!(
-                "{}: {} tokens, {} fingerprints",
-                keys[i].display(),
-                token.len(),
-                fingerprint.len()
-            );
-            // insert to index: fingerprint => f
-            for f in &fingerprint {
-                index.entry(f.hash).or_default().push((*f, i));
-            }
-            local_fingerprints.push(fingerprint);
-            local_tokens.push(token);
-        }
-
-        // exclude fingerprints in template
-        for f in &template_fingerprint {
-            index.remove(&f.hash);
-        }
-
-        // create two dimensional matrix
-        let mut m = vec![0; keys.len() * keys.len()];
-        for hash in index.keys() {
-            let v = &index[hash];
-            if v.len() > 10 {
-                // too common, skip
-                continue;
-            }
+        };
 
-            if v.len() > 5 {
-                info!("Found {} entries:", v.len());
-                for (f, i) in v {
-                    info!(
-                        "{} offset {} L{} C{}",
-                        keys[*i].display(),
-                        f.offset,
-                        local_tokens[*i][f.offset].line,
-                        local_tokens[*i][f.offset].column,
-                    );
-                }
-            }
-            // add to matrix
-            for i in 0..v.len() {
-                for j in (i + 1)..v.len() {
-                    if v[i].1 == v[j].1 {
-                        continue;
-                    }
-                    m[v[i].1 * keys.len() + v[j].1] += 1;
-                    m[v[j].1 * keys.len() + v[i].1] += 1;
-                }
-            }
+        let template = template_files.get(file);
+        if template.is_none() {
+            warn!("No template found for {}!", file.display());
         }
 
-        let mut sorted_m: Vec<_> = m.iter().enumerate().collect();
-        sorted_m.sort_by_key(|(_, val)| **val);
-        for (i, matches) in sorted_m.iter().rev().take(40) {
-            let left = i % keys.len();
-            let right = i / keys.len();
-            if left < right {
-                // skip duplicatie
+        let templates: Vec<Source> = template
+            .map(|code| Source {
+                name: "template".to_string(),
+                files: vec![SourceFile {
+                    name: file.display().to_string(),
+                    code: code.clone(),
+                }],
+            })
+            .into_iter()
+            .collect();
+        let pairs = match run(sources, &templates, language, config) {
+            Ok(pairs) => pairs,
+            Err(err) => {
+                warn!("Processing {} failed with {:?}", file.display(), err);
                 continue;
             }
-            let matches = **matches;
+        };
+        for pair in &pairs {
             // show info
             info!(
-                "Possible plagarism: {} and {}: {} matches",
-                keys[left].display(),
-                keys[right].display(),
-                matches,
+                "Possible plagarism: {} ({}%) and {} ({}%): {} matches scoring {:.3}, {} lines matched",
+                sources[pair.left].name,
+                pair.left_match_rate,
+                sources[pair.right].name,
+                pair.right_match_rate,
+                pair.fingerprints,
+                pair.score as f64 / 1000.0,
+                pair.lines_matched,
             );
+            info!(
+                "Tokens matched: {}% and {}%, similarity {}%, containment {}% and {}%, longest block {} tokens",
+                pair.left_token_rate,
+                pair.right_token_rate,
+                pair.similarity,
+                pair.left_containment,
+                pair.right_containment,
+                pair.longest_block,
+            );
+            if config.verbatim {
+                info!(
+                    "Copied verbatim: {}% and {}%",
+                    pair.left_verbatim_rate, pair.right_verbatim_rate
+                );
+            }
+            for block in &pair.blocks {
+                info!(
+                    "L{}-L{} and L{}-L{}",
+                    block.left_line_from,
+                    block.left_line_to,
+                    block.right_line_from,
+                    block.right_line_to
+                );
+            }
+            for evidence in &pair.evidence {
+                info!(
+                    "Shared {:?} {} at L{} and L{}",
+                    evidence.kind, evidence.text, evidence.left_line, evidence.right_line
+                );
+            }
         }
     }
     Ok(())
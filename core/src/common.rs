@@ -1,8 +1,10 @@
-use crate::token::Token;
+use crate::{config::DetectionConfig, token::Token};
 use adler32::RollingAdler32;
+use log::*;
 use std::{
-    collections::VecDeque,
-    hash::{Hash, Hasher},
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    ops::Range,
 };
 
 #[derive(Copy, Clone)]
@@ -13,11 +15,17 @@ pub struct LineMatch {
     pub right_to: u32,
 }
 
-pub fn find_matches(left: &[Token], right: &[Token]) -> Vec<LineMatch> {
+pub fn find_matches(left: &[Token], right: &[Token], config: &DetectionConfig) -> Vec<LineMatch> {
     let mut line_matches = vec![];
-    let left_kind: Vec<u8> = left.iter().map(|t| t.kind).collect();
-    let right_kind: Vec<u8> = right.iter().map(|t| t.kind).collect();
-    let mut matches = rkr_gst::run(&left_kind, &right_kind, 40, 20);
+    let left_kind: Vec<u16> = left.iter().map(|t| t.kind).collect();
+    let right_kind: Vec<u16> = right.iter().map(|t| t.kind).collect();
+    let kinds = compact_kinds(&[&left_kind, &right_kind]);
+    let mut matches = rkr_gst::run(
+        &kinds[0],
+        &kinds[1],
+        config.initial_search_length,
+        config.minimum_match_length,
+    );
     matches.sort_by_key(|m| m.pattern_index);
 
     for m in &matches {
@@ -45,17 +53,123 @@ pub fn find_matches(left: &[Token], right: &[Token]) -> Vec<LineMatch> {
     line_matches
 }
 
+/// Version of the fingerprint format
+///
+/// Fingerprints computed with different versions are not comparable.
+/// Bump this whenever `fingerprint_hash`, the rolling hash or token kinds change,
+/// so that stored fingerprints can be told apart and recomputed.
+pub const FINGERPRINT_VERSION: u32 = 2;
+
+/// Spread the Adler32 hash of a `noise`-gram over 64 bits
+///
+/// Each token kind is fed to Adler32 as two bytes in little endian.
+///
+/// Adler32 alone is not random enough for winnowing, so it is passed through
+/// the SplitMix64 finalizer. Unlike `DefaultHasher`, the result is fixed
+/// and does not depend on Rust version or platform.
+pub fn fingerprint_hash(adler32: u32) -> u64 {
+    let mut z = (adler32 as u64).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Map token kinds to byte ids for RKR-GST, which compares bytes
+///
+/// RKR-GST tells at most 256 ids apart. Kinds are numbered in order of first
+/// appearance across all sequences, which keeps them apart as long as the
+/// sequences use at most 256 distinct kinds. Vocabularies may be larger, e.g. for
+/// SQL, but two submissions seldom use that many. Beyond the limit, the 255 most
+/// frequent kinds keep their own ids and the rarer ones share the last id, so that
+/// collisions are confined to rare kinds, and a warning is logged.
+pub fn compact_kinds(sequences: &[&[u16]]) -> Vec<Vec<u8>> {
+    // distinct kinds in order of first appearance, and their counts
+    let mut order: Vec<u16> = vec![];
+    let mut counts: HashMap<u16, usize> = HashMap::new();
+    for kind in sequences.iter().copied().flatten() {
+        let count = counts.entry(*kind).or_default();
+        if *count == 0 {
+            order.push(*kind);
+        }
+        *count += 1;
+    }
+    if order.len() > 256 {
+        warn!(
+            "{} distinct token kinds exceed the 256 told apart by RKR-GST, the {} rarest share one id",
+            order.len(),
+            order.len() - 255
+        );
+        // stable, equal counts stay in order of first appearance
+        order.sort_by_key(|kind| Reverse(counts[kind]));
+    }
+    let ids: HashMap<u16, u8> = order
+        .iter()
+        .enumerate()
+        .map(|(i, kind)| (*kind, i.min(255) as u8))
+        .collect();
+    sequences
+        .iter()
+        .map(|sequence| sequence.iter().map(|kind| ids[kind]).collect())
+        .collect()
+}
+
+/// Hashes of all `noise`-grams of tokens by kind and spelling, which only match verbatim copies
+///
+/// Whitespace within spellings is collapsed. Each token is hashed on its own,
+/// then windows of token hashes are hashed like windows of kinds.
+pub fn verbatim_fingerprints(tokens: &[Token], noise: usize) -> Vec<Fingerprint> {
+    let tokens: Vec<u32> = tokens
+        .iter()
+        .map(|token| {
+            let mut hasher = RollingAdler32::new();
+            update(&mut hasher, token.kind);
+            for word in token.spelling.split_whitespace() {
+                hasher.update(b' ');
+                hasher.update_buffer(word.as_bytes());
+            }
+            hasher.hash()
+        })
+        .collect();
+    tokens
+        .windows(noise)
+        .enumerate()
+        .map(|(offset, window)| {
+            let mut hasher = RollingAdler32::new();
+            for token in window {
+                hasher.update_buffer(&token.to_le_bytes());
+            }
+            Fingerprint {
+                hash: fingerprint_hash(hasher.hash()),
+                offset,
+            }
+        })
+        .collect()
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Fingerprint {
     pub hash: u64,
     pub offset: usize,
 }
 
+fn update(hasher: &mut RollingAdler32, kind: u16) {
+    for b in kind.to_le_bytes() {
+        hasher.update(b);
+    }
+}
+
+// remove oldest kind from a window of `size` kinds
+fn remove(hasher: &mut RollingAdler32, size: usize, kind: u16) {
+    let [lo, hi] = kind.to_le_bytes();
+    hasher.remove(size * 2, lo);
+    hasher.remove(size * 2 - 1, hi);
+}
+
 // https://theory.stanford.edu/~aiken/publications/papers/sigmod03.pdf
 // void winnow(int w)
 pub fn fingerprint<I>(mut iter: I, noise: usize, guarantee: usize) -> Vec<Fingerprint>
 where
-    I: Iterator<Item = u8>,
+    I: Iterator<Item = u16>,
 {
     let mut res = vec![];
     // initial rolling `noise`-gram hashes
@@ -64,7 +178,7 @@ where
     for _ in 0..noise {
         if let Some(e) = iter.next() {
             items.push_back(e);
-            hasher.update(e);
+            update(&mut hasher, e);
         } else {
             // too short
             return res;
@@ -80,10 +194,7 @@ where
 
     let mut min_hash_index = 0;
     for (window_offset, e) in iter.enumerate() {
-        // alder32 is not random enough!
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        hasher.hash().hash(&mut h);
-        let new_hash = h.finish();
+        let new_hash = fingerprint_hash(hasher.hash());
 
         if new_hash < hashes[min_hash_index] {
             // a new minimum
@@ -115,16 +226,16 @@ where
         }
 
         // update rolling hash
-        hasher.remove(noise, items.pop_front().unwrap());
+        remove(&mut hasher, noise, items.pop_front().unwrap());
         items.push_back(e);
-        hasher.update(e);
+        update(&mut hasher, e);
     }
     res
 }
 
 pub fn all_fingerprint<I>(mut iter: I, noise: usize) -> Vec<Fingerprint>
 where
-    I: Iterator<Item = u8>,
+    I: Iterator<Item = u16>,
 {
     let mut res = vec![];
     // initial rolling `noise`-gram hashes
@@ -133,7 +244,7 @@ where
     for _ in 0..noise {
         if let Some(e) = iter.next() {
             items.push_back(e);
-            hasher.update(e);
+            update(&mut hasher, e);
         } else {
             // too short
             return res;
@@ -141,10 +252,7 @@ where
     }
 
     for (window_offset, e) in iter.enumerate() {
-        // alder32 is not random enough!
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        hasher.hash().hash(&mut h);
-        let new_hash = h.finish();
+        let new_hash = fingerprint_hash(hasher.hash());
 
         res.push(Fingerprint {
             hash: new_hash,
@@ -152,9 +260,9 @@ where
         });
 
         // update rolling hash
-        hasher.remove(noise, items.pop_front().unwrap());
+        remove(&mut hasher, noise, items.pop_front().unwrap());
         items.push_back(e);
-        hasher.update(e);
+        update(&mut hasher, e);
     }
     res
 }
@@ -163,12 +271,14 @@ where
 mod tests {
     use super::all_fingerprint;
     use super::fingerprint;
+    use super::LineStarts;
+    use super::{compact_kinds, fingerprint_hash, highlight_html, Highlight};
 
     #[test]
     fn test_all_fingerprint() {
         // example taken from paper
         let text = "adorunrunrunadorunrun";
-        let fingerprints = all_fingerprint(text.bytes(), 5);
+        let fingerprints = all_fingerprint(text.bytes().map(|b| b as u16), 5);
         eprintln!("{:?}", fingerprints);
 
         // adoru @ 0, 12
@@ -186,11 +296,11 @@ mod tests {
     fn test_fingerprint() {
         // example taken from paper
         let text = "adorunrunrunadorunrun";
-        let all_fingerprints = all_fingerprint(text.bytes(), 5);
+        let all_fingerprints = all_fingerprint(text.bytes().map(|b| b as u16), 5);
         eprintln!("{:?}", all_fingerprints);
 
         // windows size of hashes = 2
-        let fingerprints = fingerprint(text.bytes(), 5, 6);
+        let fingerprints = fingerprint(text.bytes().map(|b| b as u16), 5, 6);
         eprintln!("{:?}", fingerprints);
 
         // check if subset
@@ -198,6 +308,140 @@ mod tests {
             assert!(all_fingerprints.contains(f), "{:?} not found", f);
         }
     }
+
+    #[test]
+    fn test_stable_hash() {
+        // values are part of FINGERPRINT_VERSION, they must never change
+        assert_eq!(fingerprint_hash(0), 0xe220a8397b1dcdaf);
+        assert_eq!(fingerprint_hash(1), 0x910a2dec89025cc1);
+
+        let fingerprints = all_fingerprint("adorunrunrunadorunrun".bytes().map(|b| b as u16), 5);
+        // adler32 of "adoru" in 16-bit kinds is 0x0c40021c
+        assert_eq!(fingerprints[0].hash, 0xcf859c99be7cbe1c);
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        let code = "ab\nçd\n";
+        let lines = LineStarts::new(code);
+        assert_eq!(lines.byte_offset(code, 0, 1), 1);
+        assert_eq!(lines.byte_offset(code, 1, 0), 3);
+        // ç takes two bytes
+        assert_eq!(lines.byte_offset(code, 1, 1), 5);
+        // clamped to end of line and code
+        assert_eq!(lines.byte_offset(code, 0, 10), 2);
+        assert_eq!(lines.byte_offset(code, 2, 0), code.len());
+        assert_eq!(lines.byte_offset(code, 5, 0), code.len());
+
+        assert_eq!(lines.char_column(code, 1, 2), 1);
+        assert_eq!(lines.char_column(code, 1, 3), 2);
+    }
+
+    #[test]
+    fn test_highlight_html() {
+        let code = "a < b; c";
+        let highlights = [
+            Highlight {
+                span: 0..5,
+                color: "red",
+                prefix: "<a name=\"0\"></a>".to_string(),
+            },
+            // overlapping
+            Highlight {
+                span: 4..6,
+                color: "blue",
+                prefix: String::new(),
+            },
+        ];
+        assert_eq!(
+            highlight_html(code, &highlights),
+            "<a name=\"0\"></a><font color=\"red\">a &lt; b</font>; c"
+        );
+    }
+
+    #[test]
+    fn test_compact_kinds() {
+        let kinds = compact_kinds(&[&[300, 5, 300], &[5, 1000]]);
+        assert_eq!(kinds, vec![vec![0, 1, 0], vec![1, 2]]);
+
+        // beyond 256 kinds, the rarest share the last id
+        let many: Vec<u16> = (0..300).chain([299, 299, 0]).collect();
+        let kinds = compact_kinds(&[&many]);
+        assert_eq!(&kinds[0][..2], [1, 2]);
+        assert_eq!(kinds[0][299], 0);
+        assert_eq!(kinds[0][253], 254);
+        assert_eq!(&kinds[0][254..256], [255, 255]);
+        assert_eq!(kinds[0][298], 255);
+    }
+}
+
+/// Byte offsets of the starts of lines in code, computed once so that
+/// positions are converted without scanning the code from its start
+pub struct LineStarts(Vec<usize>);
+
+impl LineStarts {
+    pub fn new(code: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(code.match_indices('\n').map(|(i, _)| i + 1));
+        Self(starts)
+    }
+
+    /// Byte offset of 0-based line and character column in code
+    ///
+    /// Positions beyond the end of a line or of the code are clamped.
+    pub fn byte_offset(&self, code: &str, line: usize, column: usize) -> usize {
+        let line_start = match self.0.get(line) {
+            Some(start) => *start,
+            None => return code.len(),
+        };
+        let line_end = code[line_start..]
+            .find('\n')
+            .map_or(code.len(), |i| line_start + i);
+        code[line_start..line_end]
+            .char_indices()
+            .nth(column)
+            .map_or(line_end, |(i, _)| line_start + i)
+    }
+
+    /// Character column of 0-based line and byte column in code, the inverse of `byte_offset`
+    pub fn char_column(&self, code: &str, line: usize, byte_column: usize) -> usize {
+        let line_start = self.0.get(line).copied().unwrap_or(code.len());
+        let end = (line_start + byte_column).min(code.len());
+        code.get(line_start..end)
+            .map_or(byte_column, |prefix| prefix.chars().count())
+    }
+}
+
+/// A span of code to highlight
+pub struct Highlight {
+    // byte range in code
+    pub span: Range<usize>,
+    pub color: &'static str,
+    // raw html inserted before the span, e.g. anchors
+    pub prefix: String,
+}
+
+/// Render code as escaped html with highlighted spans
+///
+/// Highlights should be sorted by start, those overlapping previous ones are skipped.
+pub fn highlight_html(code: &str, highlights: &[Highlight]) -> String {
+    let mut res = String::new();
+    let mut last = 0;
+    for highlight in highlights {
+        let from = highlight.span.start.min(code.len());
+        let to = highlight.span.end.min(code.len());
+        if from < last || to < from {
+            continue;
+        }
+        res.push_str(&html_escape::encode_text(&code[last..from]));
+        res.push_str(&highlight.prefix);
+        res.push_str(&format!("<font color=\"{}\">", highlight.color));
+        res.push_str(&html_escape::encode_text(&code[from..to]));
+        res.push_str("</font>");
+        last = to;
+    }
+    res.push_str(&html_escape::encode_text(&code[last..]));
+    res
 }
 
 pub fn gen_svg(color: &str, ratio: i32) -> String {
@@ -0,0 +1,209 @@
+use crate::common::fingerprint_hash;
+use adler32::RollingAdler32;
+use anyhow::anyhow;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+/// Tunable parameters of the detection pipeline
+///
+/// Missing fields are filled with defaults when deserializing,
+/// so that configs stored by older versions can still be loaded.
+#[derive(Serialize, Deserialize, Args, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct DetectionConfig {
+    /// Initial search length of RKR-GST, in tokens
+    #[arg(long, default_value_t = 40)]
+    pub initial_search_length: usize,
+
+    /// Minimum match length of RKR-GST, in tokens
+    #[arg(long, default_value_t = 20)]
+    pub minimum_match_length: usize,
+
+    /// Winnowing noise threshold: matches shorter than this are ignored
+    #[arg(long, default_value_t = 40)]
+    pub noise: usize,
+
+    /// Winnowing guarantee threshold: matches at least this long are detected
+    #[arg(long, default_value_t = 80)]
+    pub guarantee: usize,
+
+    /// Fingerprints occurring more often than this are considered too common and skipped
+    #[arg(long, default_value_t = 10)]
+    pub max_fingerprint_occurrences: usize,
+
+    /// Number of most similar pairs to compute matches for
+    #[arg(long, default_value_t = 100)]
+    pub max_pairs: usize,
+
+    /// Code whose fingerprints are in more than this percentage of submissions
+    /// is treated as part of the template, 0 to disable
+    #[arg(long, default_value_t = 0)]
+    pub common_code_percent: usize,
+
+    /// Matches separated by at most this many tokens on both sides, e.g. by inserted
+    /// statements, are merged into one block, 0 to disable
+    ///
+    /// Matches down to half of the minimum match length are then found and merged,
+    /// and merged blocks need the minimum match length in total.
+    #[arg(long, default_value_t = 8)]
+    pub max_gap: usize,
+
+    /// Comments and literals in at most this many submissions, and not in templates,
+    /// are reported when shared by a pair, 0 to disable
+    #[arg(long, default_value_t = 3)]
+    pub max_evidence_occurrences: usize,
+
+    /// Rewrite all loops into `for` loops before fingerprinting
+    #[arg(long)]
+    pub normalize_loops: bool,
+
+    /// Rewrite `i++`, `++i` and `i = i + n` into `i += n` before fingerprinting
+    #[arg(long)]
+    pub normalize_increments: bool,
+
+    /// Order operands of commutative operators like `==` and `+` before fingerprinting
+    #[arg(long)]
+    pub normalize_operands: bool,
+
+    /// Drop redundant parentheses before fingerprinting
+    #[arg(long)]
+    pub normalize_parentheses: bool,
+
+    /// Also fingerprint spellings of tokens, reporting how much of each pair is copied verbatim
+    #[arg(long)]
+    pub verbatim: bool,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            initial_search_length: 40,
+            minimum_match_length: 20,
+            noise: 40,
+            guarantee: 80,
+            max_fingerprint_occurrences: 10,
+            max_pairs: 100,
+            common_code_percent: 0,
+            max_gap: 8,
+            max_evidence_occurrences: 3,
+            normalize_loops: false,
+            normalize_increments: false,
+            normalize_operands: false,
+            normalize_parentheses: false,
+            verbatim: false,
+        }
+    }
+}
+
+impl DetectionConfig {
+    /// Check that parameters are consistent with each other
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.minimum_match_length == 0 {
+            return Err(anyhow!("minimum_match_length must be positive"));
+        }
+        if self.initial_search_length < self.minimum_match_length {
+            return Err(anyhow!(
+                "initial_search_length ({}) must not be less than minimum_match_length ({})",
+                self.initial_search_length,
+                self.minimum_match_length
+            ));
+        }
+        if self.noise == 0 {
+            return Err(anyhow!("noise must be positive"));
+        }
+        if self.guarantee < self.noise {
+            return Err(anyhow!(
+                "guarantee ({}) must not be less than noise ({})",
+                self.guarantee,
+                self.noise
+            ));
+        }
+        if self.common_code_percent > 100 {
+            return Err(anyhow!(
+                "common_code_percent ({}) must not be greater than 100",
+                self.common_code_percent
+            ));
+        }
+        Ok(())
+    }
+
+    /// Digest of the parameters that fingerprint hashes depend on
+    ///
+    /// Fingerprints are only comparable to those computed under the same digest,
+    /// e.g. fingerprints archived by earlier jobs. Normalization passes rewrite token
+    /// kinds before fingerprinting, so they are included. Templates and common code
+    /// only drop fingerprints without changing the others, so they are left out.
+    pub fn fingerprint_digest(&self) -> u64 {
+        let params = format!(
+            "noise={} guarantee={} loops={} increments={} operands={} parentheses={}",
+            self.noise,
+            self.guarantee,
+            self.normalize_loops,
+            self.normalize_increments,
+            self.normalize_operands,
+            self.normalize_parentheses
+        );
+        fingerprint_hash(RollingAdler32::from_buffer(params.as_bytes()).hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DetectionConfig;
+
+    #[test]
+    fn test_default() {
+        let config = DetectionConfig::default();
+        assert!(config.validate().is_ok());
+
+        // missing fields fall back to defaults
+        let config: DetectionConfig = serde_json::from_str(r#"{"noise": 30}"#).unwrap();
+        assert_eq!(config.noise, 30);
+        assert_eq!(config.guarantee, 80);
+
+        let config = DetectionConfig {
+            noise: 50,
+            guarantee: 40,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = DetectionConfig {
+            common_code_percent: 101,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_digest() {
+        let config = DetectionConfig::default();
+        let digest = config.fingerprint_digest();
+        let other = DetectionConfig {
+            max_pairs: 10,
+            common_code_percent: 50,
+            ..Default::default()
+        };
+        assert_eq!(other.fingerprint_digest(), digest);
+        for other in [
+            DetectionConfig {
+                noise: 30,
+                ..Default::default()
+            },
+            DetectionConfig {
+                guarantee: 100,
+                ..Default::default()
+            },
+            DetectionConfig {
+                normalize_loops: true,
+                ..Default::default()
+            },
+            DetectionConfig {
+                normalize_operands: true,
+                ..Default::default()
+            },
+        ] {
+            assert_ne!(other.fingerprint_digest(), digest);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Comments and literals shared by a pair of submissions
+//!
+//! Tokenizers drop comments and collapse literals into a few kinds, so neither
+//! takes part in fingerprinting. An identical typo in a comment or an odd format
+//! string is still convincing, so they are collected separately: comments are
+//! found in the code between tokens, and literals are spellings of tokens. Those
+//! in only a few submissions and not in the templates are reported per pair.
+use crate::{
+    lang::{literal_kinds, Language},
+    pipeline::Source,
+    token::Token,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// shorter comments and literals, like `0` or `// ok`, are too common to be evidence
+const MIN_LENGTH: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EvidenceKind {
+    Comment,
+    Literal,
+}
+
+/// Comment or literal of a submission
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mark {
+    pub kind: EvidenceKind,
+    // comments without delimiters and with whitespace collapsed, literals as spelled
+    pub text: String,
+    // index of file in submission
+    pub file: usize,
+    // 0-based
+    pub line: usize,
+}
+
+/// Rare comment or literal found in both submissions of a pair
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Evidence {
+    pub kind: EvidenceKind,
+    pub text: String,
+    // first occurrence on each side, index of file and 0-based line
+    pub left_file: usize,
+    pub left_line: usize,
+    pub right_file: usize,
+    pub right_line: usize,
+}
+
+// line comment starts and block comment delimiters of language
+fn comment_syntax(
+    language: Language,
+) -> (
+    &'static [&'static str],
+    &'static [(&'static str, &'static str)],
+) {
+    match language {
+        Language::Python => (&["#"], &[]),
+        Language::SQL => (&["--"], &[("/*", "*/")]),
+        // `--[[` also starts a line comment, block comments win at the same position
+        Language::Lua => (&["--"], &[("--[[", "]]")]),
+        Language::Cpp | Language::Rust | Language::Verilog | Language::JavaScript => {
+            (&["//"], &[("/*", "*/")])
+        }
+    }
+}
+
+// comment text without delimiters, whitespace collapsed
+fn clean(comment: &str) -> String {
+    comment.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Find comments in code between tokens, which holds no literals to confuse them with
+fn find_comments(
+    code: &str,
+    gap: std::ops::Range<usize>,
+    language: Language,
+    file: usize,
+    res: &mut Vec<Mark>,
+) {
+    let (line_starts, blocks) = comment_syntax(language);
+    let mut offset = gap.start;
+    while offset < gap.end {
+        let rest = &code[offset..gap.end];
+        let block = blocks
+            .iter()
+            .filter_map(|(open, close)| rest.find(open).map(|i| (i, *open, Some(*close))))
+            .min_by_key(|(i, _, _)| *i);
+        let line = line_starts
+            .iter()
+            .filter_map(|start| rest.find(start).map(|i| (i, *start, None)))
+            .min_by_key(|(i, _, _)| *i);
+        // a block comment wins over a line comment at the same position
+        let (start, open, close) = match (block, line) {
+            (Some(b), Some(l)) if l.0 < b.0 => l,
+            (Some(b), _) => b,
+            (None, Some(l)) => l,
+            (None, None) => return,
+        };
+        let body = &rest[start + open.len()..];
+        let (text, len) = match close {
+            Some(close) => match body.find(close) {
+                Some(i) => (&body[..i], i + close.len()),
+                None => (body, body.len()),
+            },
+            None => {
+                let i = body.find('\n').unwrap_or(body.len());
+                (&body[..i], i)
+            }
+        };
+        let text = clean(text);
+        if text.chars().count() >= MIN_LENGTH {
+            res.push(Mark {
+                kind: EvidenceKind::Comment,
+                text,
+                file,
+                line: code[..offset + start].matches('\n').count(),
+            });
+        }
+        offset += start + open.len() + len;
+    }
+}
+
+/// Comments and literals of a submission, first occurrence of each in the order of files,
+/// including files without any token
+pub fn extract_marks(source: &Source, tokens: &[Token], language: Language) -> Vec<Mark> {
+    let literals = literal_kinds(language);
+    let mut res = vec![];
+    for (file, source_file) in source.files.iter().enumerate() {
+        let code = &source_file.code;
+        let mut from = 0;
+        for token in tokens.iter().filter(|t| t.file as usize == file) {
+            // tokens of normalization may cover the same code
+            if token.span.start >= from {
+                find_comments(code, from..token.span.start, language, file, &mut res);
+            }
+            from = from.max(token.span.end);
+            if literals.contains(&token.kind) && token.spelling.chars().count() >= MIN_LENGTH {
+                res.push(Mark {
+                    kind: EvidenceKind::Literal,
+                    text: token.spelling.clone(),
+                    file,
+                    line: token.line as usize - 1,
+                });
+            }
+        }
+        find_comments(code, from..code.len(), language, file, &mut res);
+    }
+    let mut seen = HashSet::new();
+    res.retain(|m| seen.insert((m.kind, m.text.clone())));
+    res
+}
+
+/// Comments and literals, counted by the number of submissions they are in
+pub fn count_marks(marks: &[Vec<Mark>]) -> HashMap<(EvidenceKind, &str), usize> {
+    let mut res = HashMap::new();
+    for mark in marks.iter().flatten() {
+        *res.entry((mark.kind, mark.text.as_str())).or_default() += 1;
+    }
+    res
+}
+
+/// Comments and literals in both sides of a pair that `is_rare` accepts,
+/// in the order of the left side
+pub fn shared_evidence(
+    left: &[Mark],
+    right: &[Mark],
+    is_rare: impl Fn(&Mark) -> bool,
+) -> Vec<Evidence> {
+    let right: HashMap<(EvidenceKind, &str), &Mark> = right
+        .iter()
+        .map(|m| ((m.kind, m.text.as_str()), m))
+        .collect();
+    left.iter()
+        .filter(|m| is_rare(m))
+        .filter_map(|l| {
+            right.get(&(l.kind, l.text.as_str())).map(|r| Evidence {
+                kind: l.kind,
+                text: l.text.clone(),
+                left_file: l.file,
+                left_line: l.line,
+                right_file: r.file,
+                right_line: r.line,
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod tests {
+    use super::{count_marks, extract_marks, shared_evidence, EvidenceKind, Mark};
+    use crate::{fixtures::source, lang::Language, pipeline::tokenize_source};
+
+    fn marks(code: &str) -> Vec<Mark> {
+        let source = source("a", &[("main.rs", code)]);
+        let tokens = tokenize_source(&source, Language::Rust).unwrap();
+        extract_marks(&source, &tokens, Language::Rust)
+    }
+
+    #[test]
+    fn test_evidence() {
+        let left = marks("// compute teh sum\nfn main() {\n    /* ok */ println!(\"sum: %d\\n\");\n    let url = \"http://example.com\"; // same\n}\n");
+        let texts: Vec<(EvidenceKind, &str, usize)> = left
+            .iter()
+            .map(|m| (m.kind, m.text.as_str(), m.line))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (EvidenceKind::Comment, "compute teh sum", 0),
+                (EvidenceKind::Literal, "\"sum: %d\\n\"", 2),
+                (EvidenceKind::Literal, "\"http://example.com\"", 3),
+            ]
+        );
+
+        let right =
+            marks("fn f() {\n    //   compute teh   sum\n    let s = \"http://example.com\";\n}\n");
+        let all = [left.clone(), right.clone()];
+        let counts = count_marks(&all);
+        assert_eq!(counts[&(EvidenceKind::Comment, "compute teh sum")], 2);
+
+        let evidence = shared_evidence(&left, &right, |_| true);
+        assert_eq!(evidence.len(), 2);
+        assert_eq!((evidence[0].left_line, evidence[0].right_line), (0, 1));
+        let evidence = shared_evidence(&left, &right, |m| m.kind == EvidenceKind::Literal);
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].right_line, 2);
+    }
+
+    #[test]
+    fn test_comment_only_file() {
+        let source = source(
+            "a",
+            &[
+                ("notes.rs", "// solved with teh help of bob\n"),
+                ("main.rs", "fn main() {}\n// the very end\n"),
+            ],
+        );
+        let tokens = tokenize_source(&source, Language::Rust).unwrap();
+        let marks = extract_marks(&source, &tokens, Language::Rust);
+        let texts: Vec<(usize, &str)> = marks.iter().map(|m| (m.file, m.text.as_str())).collect();
+        // the file of notes has no tokens at all
+        assert_eq!(
+            texts,
+            [(0, "solved with teh help of bob"), (1, "the very end")]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by tests of the pipeline and its parts
+use crate::{
+    config::DetectionConfig,
+    pipeline::{Source, SourceFile},
+};
+
+/// A small program, long enough to be matched under `config`
+pub const CODE: &str = "fn main() {\n    let mut sum = 0;\n    for i in 0..10 {\n        if i % 2 == 0 {\n            sum += i * i;\n        } else {\n            sum -= i;\n        }\n    }\n    println!(\"{}\", sum);\n}\n";
+
+/// A program sharing nothing with `CODE`
+pub const OTHER: &str = "struct Point { x: i32, y: i32 }\nimpl Point {\n    fn norm(&self) -> i32 { self.x.abs() + self.y.abs() }\n}\n";
+
+/// A function given in lecture, found in many submissions
+pub const COMMON: &str = "fn read() -> Vec<i64> {\n    let mut line = String::new();\n    std::io::stdin().read_line(&mut line).unwrap();\n    line.split_whitespace().map(|x| x.parse().unwrap()).collect()\n}\n";
+
+/// `CODE` with a variable renamed, the usual copy
+pub fn renamed() -> String {
+    CODE.replace("sum", "total")
+}
+
+pub fn source(name: &str, files: &[(&str, &str)]) -> Source {
+    Source {
+        name: name.to_string(),
+        files: files
+            .iter()
+            .map(|(name, code)| SourceFile {
+                name: name.to_string(),
+                code: code.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Parameters scaled down to the size of the fixtures
+pub fn config() -> DetectionConfig {
+    DetectionConfig {
+        noise: 10,
+        guarantee: 20,
+        initial_search_length: 20,
+        minimum_match_length: 10,
+        ..Default::default()
+    }
+}
@@ -1,8 +1,10 @@
-use crate::token::Token;
+use crate::common::LineStarts;
+use crate::token::{Token, Vocabulary};
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+pub mod normalize;
 pub mod tokenizer;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
@@ -21,8 +23,29 @@ pub trait Tokenize {
         self.tokenize_str(&std::fs::read_to_string(path)?)
     }
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>>;
+    /// Token kinds produced by this tokenizer
+    fn vocabulary(&self) -> &'static Vocabulary;
 }
 
+/// Error of a tokenizer at a known line, recoverable by skipping the line
+#[derive(Debug)]
+pub struct SyntaxError {
+    // 1-based
+    pub line: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+// most lines skipped in one file before giving up
+const MAX_SKIPPED_LINES: usize = 20;
+
 struct LangInfo {
     name: Language,
     extensions: Vec<&'static str>,
@@ -31,7 +54,7 @@ struct LangInfo {
 
 fn get_lang_info() -> Vec<LangInfo> {
     vec![
-        #[cfg(feature = "cpp")]
+        #[cfg(feature = "cpp_lexer")]
         LangInfo {
             name: Language::Cpp,
             extensions: vec!["cpp", "cc", "cxx", "c++", "c", "cu"],
@@ -76,28 +99,207 @@ fn get_lang_info() -> Vec<LangInfo> {
     ]
 }
 
-pub fn tokenize(path: &Path) -> anyhow::Result<Vec<Token>> {
+/// Find language by file extension
+pub fn language_of(path: &Path) -> Option<Language> {
     let extension = path
         .extension()
         .and_then(|s| s.to_str())
         .unwrap_or_default()
         .to_ascii_lowercase();
 
-    for lang in get_lang_info() {
-        if lang.extensions.contains(&extension.as_str()) {
-            return lang.tokenizer.tokenize(path);
+    get_lang_info()
+        .into_iter()
+        .find(|lang| lang.extensions.contains(&extension.as_str()))
+        .map(|lang| lang.name)
+}
+
+/// Compute byte spans of tokens from their positions
+fn fill_spans(content: &str, tokens: &mut [Token]) {
+    let lines = LineStarts::new(content);
+    for token in tokens {
+        let from = lines.byte_offset(content, token.line as usize - 1, token.column as usize - 1);
+        let to = lines.byte_offset(
+            content,
+            token.end_line as usize - 1,
+            token.end_column as usize - 1,
+        );
+        token.span = from..to.max(from);
+    }
+}
+
+pub fn tokenize(path: &Path) -> anyhow::Result<Vec<Token>> {
+    if let Some(language) = language_of(path) {
+        for lang in get_lang_info() {
+            if lang.name == language {
+                let mut tokens = lang.tokenizer.tokenize(path)?;
+                fill_spans(&std::fs::read_to_string(path)?, &mut tokens);
+                return Ok(tokens);
+            }
         }
     }
-    Err(anyhow!("Unsupported file extension: {:?}. \
-    Did you enable a corresponding feature?", path))
+    Err(anyhow!(
+        "Unsupported file extension: {:?}. \
+    Did you enable a corresponding feature?",
+        path
+    ))
 }
 
 pub fn tokenize_str(content: &str, language: Language) -> anyhow::Result<Vec<Token>> {
     for lang in get_lang_info() {
         if lang.name == language {
-            return lang.tokenizer.tokenize_str(content);
+            let mut tokens = lang.tokenizer.tokenize_str(content)?;
+            fill_spans(content, &mut tokens);
+            return Ok(tokens);
+        }
+    }
+    Err(anyhow!(
+        "Unsupported language: {:?}. \
+    Did you enable a corresponding feature?",
+        language
+    ))
+}
+
+/// Token kinds of language, for introspection and debugging
+pub fn vocabulary(language: Language) -> anyhow::Result<&'static Vocabulary> {
+    for lang in get_lang_info() {
+        if lang.name == language {
+            return Ok(lang.tokenizer.vocabulary());
         }
     }
-    Err(anyhow!("Unsupported language: {:?}. \
-    Did you enable a corresponding feature?", language))
+    Err(anyhow!(
+        "Unsupported language: {:?}. \
+    Did you enable a corresponding feature?",
+        language
+    ))
+}
+
+/// Kinds of identifiers in language, whose spellings are chosen by the author
+pub fn identifier_kinds(language: Language) -> Vec<u16> {
+    let names: &[&str] = match language {
+        Language::Python => &["<name>"],
+        Language::SQL => &["<word>"],
+        Language::JavaScript => &["<identifier>", "<private identifier>"],
+        _ => &["<identifier>"],
+    };
+    match vocabulary(language) {
+        Ok(vocab) => names.iter().filter_map(|name| vocab.get(name)).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Kinds of literals in language, whose spellings are values chosen by the author
+pub fn literal_kinds(language: Language) -> Vec<u16> {
+    let names: &[&str] = match language {
+        Language::Cpp | Language::Rust => &["<literal>"],
+        Language::Verilog | Language::Lua => &["<number>", "<string>"],
+        Language::Python => &["<int>", "<float>", "<complex>", "<string>"],
+        Language::SQL => &[
+            "<number>",
+            "<single quoted string>",
+            "<double quoted string>",
+            "<dollar quoted string>",
+            "<single quoted byte string>",
+            "<double quoted byte string>",
+            "<raw string>",
+            "<national string>",
+            "<escaped string>",
+            "<hex string>",
+        ],
+        Language::JavaScript => &[
+            "<number>",
+            "<string>",
+            "<template>",
+            "<template middle>",
+            "<regexp>",
+        ],
+    };
+    match vocabulary(language) {
+        Ok(vocab) => names.iter().filter_map(|name| vocab.get(name)).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Replace a 1-based line by spaces of the same length in bytes, so that byte
+/// offsets of other lines stay the same. Returns false if the line is missing
+/// or already blank.
+fn blank_line(code: &mut String, line: u32) -> bool {
+    let start = match line {
+        0 => return false,
+        1 => 0,
+        _ => match code.match_indices('\n').nth(line as usize - 2) {
+            Some((i, _)) => i + 1,
+            None => return false,
+        },
+    };
+    let end = code[start..].find('\n').map_or(code.len(), |i| start + i);
+    if code[start..end].trim().is_empty() {
+        return false;
+    }
+    code.replace_range(start..end, &" ".repeat(end - start));
+    true
+}
+
+/// Tokenize code, skipping lines with syntax errors
+///
+/// Returns the tokens and a warning for each skipped line. Fails if the
+/// tokenizer does not report the line of an error, or too many lines fail.
+pub fn tokenize_str_lossy(
+    content: &str,
+    language: Language,
+) -> anyhow::Result<(Vec<Token>, Vec<String>)> {
+    let mut code = content.to_string();
+    let mut warnings = vec![];
+    loop {
+        let err = match tokenize_str(&code, language) {
+            Ok(tokens) => return Ok((tokens, warnings)),
+            Err(err) => err,
+        };
+        match err.downcast_ref::<SyntaxError>() {
+            Some(syntax_error)
+                if warnings.len() < MAX_SKIPPED_LINES
+                    && blank_line(&mut code, syntax_error.line) =>
+            {
+                warnings.push(format!("{}, line skipped", syntax_error));
+            }
+            _ => return Err(err),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod tests {
+    use super::{blank_line, tokenize_str, tokenize_str_lossy, Language};
+
+    #[test]
+    fn test_blank_line() {
+        let mut code = "a\nbé c\n\nd".to_string();
+        assert!(blank_line(&mut code, 2));
+        assert_eq!(code, "a\n     \n\nd");
+        assert!(!blank_line(&mut code, 2));
+        assert!(!blank_line(&mut code, 3));
+        assert!(blank_line(&mut code, 4));
+        assert!(!blank_line(&mut code, 5));
+        assert!(!blank_line(&mut code, 0));
+    }
+
+    #[test]
+    fn test_tokenize_lossy() {
+        let code = "fn main() {\n    let c = 1 ¤ 2;\n    let d = 1;\n}\n";
+        assert!(tokenize_str(code, Language::Rust).is_err());
+        let (tokens, warnings) = tokenize_str_lossy(code, Language::Rust).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("line 2: "), "{}", warnings[0]);
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(
+            spellings,
+            ["fn", "main", "(", ")", "{", "let", "d", "=", "1", "}"]
+        );
+        // spans refer to the original code
+        assert_eq!(&code[tokens[6].span.clone()], "d");
+
+        // the line of an unclosed delimiter is skipped as a whole
+        let (tokens, warnings) = tokenize_str_lossy("fn main() {\n", Language::Rust).unwrap();
+        assert!(tokens.is_empty());
+        assert_eq!(warnings.len(), 1);
+    }
 }
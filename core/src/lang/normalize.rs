@@ -0,0 +1,669 @@
+//! Normalization of token streams against common obfuscations
+//!
+//! Passes rewrite the tokens of a submission into a canonical form between
+//! tokenizing and fingerprinting, e.g. `while` into `for` and `i++` into `i += 1`,
+//! so that such edits no longer break matches. Each pass is enabled in
+//! `DetectionConfig`, and what it rewrites is given per language by names of
+//! token kinds. Rewritten tokens keep the positions of the code they replace,
+//! in order, so that matches still map to the original lines and columns.
+//! Reordered operands are the exception, they take their positions along.
+use crate::{
+    config::DetectionConfig,
+    lang::{vocabulary, Language},
+    token::{Token, Vocabulary},
+};
+
+/// One rewrite of the tokens of a submission
+pub trait Pass: Send + Sync {
+    fn apply(&self, tokens: Vec<Token>) -> Vec<Token>;
+}
+
+// what passes rewrite in one language, by names of token kinds
+struct Rules {
+    // loop keywords, all rewritten to the first one
+    loops: &'static [&'static str],
+    // identifiers and literals
+    operands: &'static [&'static str],
+    identifier: &'static str,
+    // kind of the literal `1` added by rewriting increments
+    one: &'static str,
+    // increment operator and the compound assignment it is rewritten to
+    increments: &'static [(&'static str, &'static [&'static str])],
+    // binary operator and its compound assignment
+    compound: &'static [(&'static str, &'static [&'static str])],
+    assign: &'static str,
+    // commutative binary operators, longer ones first when sharing a prefix
+    commutative: &'static [&'static [&'static str]],
+    // tokens that end an operand on either side
+    boundaries: &'static [&'static str],
+    parentheses: (&'static str, &'static str),
+    // tokens before parentheses that make them a call
+    callees: &'static [&'static str],
+}
+
+const ASSIGNMENTS: [(&str, &[&str]); 10] = [
+    ("+", &["+="]),
+    ("-", &["-="]),
+    ("*", &["*="]),
+    ("/", &["/="]),
+    ("%", &["%="]),
+    ("&", &["&="]),
+    ("|", &["|="]),
+    ("^", &["^="]),
+    ("<<", &["<<="]),
+    (">>", &[">>="]),
+];
+
+const INCREMENTS: [(&str, &[&str]); 2] = [("++", &["+="]), ("--", &["-="])];
+
+const BOUNDARIES: [&str; 11] = ["(", ")", "[", "]", "{", "}", ";", ",", "=", ":", "return"];
+
+fn rules(language: Language) -> Rules {
+    match language {
+        Language::Cpp => Rules {
+            loops: &["for", "while"],
+            operands: &["<identifier>", "<literal>"],
+            identifier: "<identifier>",
+            one: "<literal>",
+            increments: &INCREMENTS,
+            compound: &ASSIGNMENTS,
+            assign: "=",
+            commutative: &[
+                &["=="],
+                &["!="],
+                &["+"],
+                &["*"],
+                &["&&"],
+                &["||"],
+                &["&"],
+                &["|"],
+                &["^"],
+            ],
+            boundaries: &BOUNDARIES,
+            parentheses: ("(", ")"),
+            callees: &["<identifier>", ")", "]"],
+        },
+        // punctuation is split into single characters, and semicolons are skipped
+        Language::Rust => Rules {
+            loops: &["for", "while", "loop"],
+            operands: &["<identifier>", "<literal>"],
+            identifier: "<identifier>",
+            one: "<literal>",
+            increments: &[],
+            compound: &[
+                ("+", &["+", "="]),
+                ("-", &["-", "="]),
+                ("*", &["*", "="]),
+                ("/", &["/", "="]),
+                ("%", &["%", "="]),
+            ],
+            assign: "=",
+            commutative: &[
+                &["=", "="],
+                &["!", "="],
+                &["&", "&"],
+                &["|", "|"],
+                &["+"],
+                &["*"],
+                &["&"],
+                &["|"],
+                &["^"],
+            ],
+            boundaries: &[
+                "(", ")", "[", "]", "{", "}", ",", "=", ":", "return", "let", "if", "while",
+            ],
+            parentheses: ("(", ")"),
+            callees: &["<identifier>", ")", "]", "!"],
+        },
+        Language::Verilog => Rules {
+            loops: &["for", "while", "repeat", "forever"],
+            operands: &["<identifier>", "<number>"],
+            identifier: "<identifier>",
+            one: "<number>",
+            increments: &[],
+            compound: &[],
+            assign: "=",
+            commutative: &[
+                &["=="],
+                &["!="],
+                &["+"],
+                &["*"],
+                &["&&"],
+                &["||"],
+                &["&"],
+                &["|"],
+                &["^"],
+            ],
+            boundaries: &["(", ")", "[", "]", "{", "}", ";", ",", "=", "<=", ":"],
+            parentheses: ("(", ")"),
+            callees: &["<identifier>", "<system identifier>", ")", "]"],
+        },
+        // newlines are skipped, so statements have no boundary
+        Language::Python => Rules {
+            loops: &["for", "while"],
+            operands: &["<name>", "<int>", "<float>", "<string>"],
+            identifier: "<name>",
+            one: "<int>",
+            increments: &[],
+            compound: &[
+                ("+", &["+="]),
+                ("-", &["-="]),
+                ("*", &["*="]),
+                ("/", &["/="]),
+                ("//", &["//="]),
+                ("%", &["%="]),
+                ("**", &["**="]),
+                ("&", &["&="]),
+                ("|", &["|="]),
+                ("^", &["^="]),
+                ("<<", &["<<="]),
+                (">>", &[">>="]),
+            ],
+            assign: "=",
+            commutative: &[
+                &["=="],
+                &["!="],
+                &["+"],
+                &["*"],
+                &["and"],
+                &["or"],
+                &["&"],
+                &["|"],
+                &["^"],
+            ],
+            boundaries: &[
+                "(", ")", "[", "]", "{", "}", ";", ",", "=", ":", "return", "if", "elif", "while",
+            ],
+            parentheses: ("(", ")"),
+            callees: &["<name>", ")", "]"],
+        },
+        // only parentheses, since `=` both assigns and compares
+        Language::SQL => Rules {
+            loops: &[],
+            operands: &[],
+            identifier: "<word>",
+            one: "<number>",
+            increments: &[],
+            compound: &[],
+            assign: "=",
+            commutative: &[],
+            boundaries: &[],
+            parentheses: ("(", ")"),
+            callees: &["<word>", ")"],
+        },
+        Language::JavaScript => Rules {
+            loops: &["for", "while"],
+            operands: &[
+                "<identifier>",
+                "<number>",
+                "<string>",
+                "<boolean>",
+                "<null>",
+            ],
+            identifier: "<identifier>",
+            one: "<number>",
+            increments: &INCREMENTS,
+            compound: &[
+                ("+", &["+="]),
+                ("-", &["-="]),
+                ("*", &["*="]),
+                ("/", &["/="]),
+                ("%", &["%="]),
+                ("**", &["**="]),
+                ("&", &["&="]),
+                ("|", &["|="]),
+                ("^", &["^="]),
+                ("<<", &["<<="]),
+                (">>", &[">>="]),
+                (">>>", &[">>>="]),
+            ],
+            assign: "=",
+            commutative: &[
+                &["==="],
+                &["!=="],
+                &["=="],
+                &["!="],
+                &["+"],
+                &["*"],
+                &["&&"],
+                &["||"],
+                &["&"],
+                &["|"],
+                &["^"],
+            ],
+            boundaries: &BOUNDARIES,
+            parentheses: ("(", ")"),
+            callees: &["<identifier>", ")", "]"],
+        },
+        Language::Lua => Rules {
+            loops: &["for", "while", "repeat"],
+            operands: &["<identifier>", "<number>", "<string>"],
+            identifier: "<identifier>",
+            one: "<number>",
+            increments: &[],
+            compound: &[],
+            assign: "=",
+            commutative: &[
+                &["=="],
+                &["~="],
+                &["+"],
+                &["*"],
+                &["and"],
+                &["or"],
+                &["&"],
+                &["|"],
+            ],
+            boundaries: &[
+                "(", ")", "[", "]", "{", "}", ";", ",", "=", "return", "if", "while", "then", "do",
+            ],
+            parentheses: ("(", ")"),
+            callees: &["<identifier>", ")", "]"],
+        },
+    }
+}
+
+// token of kind covering the code from the start of first to the end of last
+fn spanning(first: &Token, last: &Token, kind: u16, spelling: &str) -> Token {
+    Token {
+        kind,
+        spelling: spelling.to_string(),
+        file: first.file,
+        line: first.line,
+        column: first.column,
+        end_line: last.end_line,
+        end_column: last.end_column,
+        span: first.span.start..last.span.end,
+    }
+}
+
+// kinds with their names, for synthesized tokens
+fn kinds(vocab: &Vocabulary, names: &[&str]) -> Vec<(u16, &'static str)> {
+    names
+        .iter()
+        .map(|name| {
+            let id = vocab.id(name);
+            (id, vocab.name(id).unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Rewrite all loop keywords into one
+struct Loops {
+    kinds: Vec<u16>,
+}
+
+impl Pass for Loops {
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        for token in &mut tokens {
+            if self.kinds[1..].contains(&token.kind) {
+                token.kind = self.kinds[0];
+            }
+        }
+        tokens
+    }
+}
+
+/// Rewrite `i++`, `++i` and `i = i + n` into `i += n`
+///
+/// Prefix increments are only rewritten when they are not part of a larger
+/// expression, since `++i` would move after the identifier.
+struct Increments {
+    identifier: u16,
+    one: u16,
+    assign: u16,
+    increments: Vec<(u16, Vec<(u16, &'static str)>)>,
+    compound: Vec<(u16, Vec<(u16, &'static str)>)>,
+    boundaries: Vec<u16>,
+}
+
+impl Increments {
+    fn find<'a>(
+        list: &'a [(u16, Vec<(u16, &'static str)>)],
+        kind: u16,
+    ) -> Option<&'a [(u16, &'static str)]> {
+        list.iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, replacement)| replacement.as_slice())
+    }
+
+    // `+= 1` in place of the code from first to last
+    fn add_one(
+        &self,
+        res: &mut Vec<Token>,
+        replacement: &[(u16, &'static str)],
+        first: &Token,
+        last: &Token,
+    ) {
+        for (kind, spelling) in replacement {
+            res.push(spanning(first, last, *kind, spelling));
+        }
+        res.push(spanning(first, last, self.one, "1"));
+    }
+}
+
+impl Pass for Increments {
+    fn apply(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut res = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if token.kind == self.identifier {
+                // `i++`
+                if let Some(replacement) = tokens
+                    .get(i + 1)
+                    .and_then(|t| Self::find(&self.increments, t.kind))
+                {
+                    res.push(token.clone());
+                    self.add_one(&mut res, replacement, &tokens[i + 1], &tokens[i + 1]);
+                    i += 2;
+                    continue;
+                }
+                // `i = i + n`
+                if i + 3 < tokens.len()
+                    && tokens[i + 1].kind == self.assign
+                    && tokens[i + 2].kind == token.kind
+                    && tokens[i + 2].spelling == token.spelling
+                {
+                    if let Some(replacement) = Self::find(&self.compound, tokens[i + 3].kind) {
+                        res.push(token.clone());
+                        for (kind, spelling) in replacement {
+                            res.push(spanning(&tokens[i + 1], &tokens[i + 3], *kind, spelling));
+                        }
+                        i += 4;
+                        continue;
+                    }
+                }
+            }
+            // `++i`
+            if let Some(replacement) = Self::find(&self.increments, token.kind) {
+                if tokens.get(i + 1).is_some_and(|t| t.kind == self.identifier)
+                    && tokens
+                        .get(i + 2)
+                        .is_none_or(|t| self.boundaries.contains(&t.kind))
+                {
+                    let target = &tokens[i + 1];
+                    res.push(spanning(token, target, target.kind, &target.spelling));
+                    self.add_one(&mut res, replacement, token, target);
+                    i += 2;
+                    continue;
+                }
+            }
+            res.push(token.clone());
+            i += 1;
+        }
+        res
+    }
+}
+
+/// Order the operands of commutative operators by kind, e.g. `1 == x` and `x == 1`
+///
+/// Only operands of a single token between boundaries are ordered. Operands
+/// are swapped along with their positions, so that evidence and highlighting
+/// still point at the code of each operand.
+struct Operands {
+    operands: Vec<u16>,
+    operators: Vec<Vec<u16>>,
+    boundaries: Vec<u16>,
+}
+
+impl Operands {
+    // length of the operator at i, if any
+    fn operator(&self, tokens: &[Token], i: usize) -> Option<usize> {
+        self.operators
+            .iter()
+            .find(|op| {
+                tokens.len() >= i + op.len()
+                    && op.iter().zip(&tokens[i..]).all(|(kind, t)| t.kind == *kind)
+            })
+            .map(|op| op.len())
+    }
+}
+
+impl Pass for Operands {
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        let mut i = 0;
+        while i < tokens.len() {
+            let after_boundary = i == 0 || self.boundaries.contains(&tokens[i - 1].kind);
+            if after_boundary && self.operands.contains(&tokens[i].kind) {
+                if let Some(len) = self.operator(&tokens, i + 1) {
+                    let j = i + 1 + len;
+                    if j < tokens.len()
+                        && self.operands.contains(&tokens[j].kind)
+                        && tokens
+                            .get(j + 1)
+                            .is_none_or(|t| self.boundaries.contains(&t.kind))
+                    {
+                        if tokens[i].kind > tokens[j].kind {
+                            tokens.swap(i, j);
+                        }
+                        i = j + 1;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+        tokens
+    }
+}
+
+/// Drop doubled parentheses and parentheses around a single operand, e.g. `((a + b))`
+/// and `(x) * 2`, keeping those of calls
+struct Parentheses {
+    open: u16,
+    close: u16,
+    operands: Vec<u16>,
+    callees: Vec<u16>,
+}
+
+impl Pass for Parentheses {
+    fn apply(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut dropped = vec![false; tokens.len()];
+        // closing parentheses pop inner pairs before outer ones
+        let mut stack = vec![];
+        let mut pairs = vec![None; tokens.len()];
+        for (j, token) in tokens.iter().enumerate() {
+            if token.kind == self.open {
+                stack.push(j);
+            } else if token.kind == self.close {
+                let i = match stack.pop() {
+                    Some(i) => i,
+                    None => continue,
+                };
+                pairs[i] = Some(j);
+                // first and last tokens inside that are kept
+                let first = (i + 1..j).find(|k| !dropped[*k]);
+                let last = (i + 1..j).rev().find(|k| !dropped[*k]);
+                let (first, last) = match (first, last) {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => continue,
+                };
+                if pairs[first] == Some(last) {
+                    dropped[first] = true;
+                    dropped[last] = true;
+                } else if first == last && self.operands.contains(&tokens[first].kind) {
+                    let before = (0..i).rev().find(|k| !dropped[*k]);
+                    if before.is_none_or(|k| !self.callees.contains(&tokens[k].kind)) {
+                        dropped[i] = true;
+                        dropped[j] = true;
+                    }
+                }
+            }
+        }
+        tokens
+            .into_iter()
+            .zip(dropped)
+            .filter(|(_, dropped)| !dropped)
+            .map(|(token, _)| token)
+            .collect()
+    }
+}
+
+/// Passes of language enabled in config, in the order they are applied
+pub fn passes(language: Language, config: &DetectionConfig) -> Vec<Box<dyn Pass>> {
+    let vocab = match vocabulary(language) {
+        Ok(vocab) => vocab,
+        Err(_) => return vec![],
+    };
+    let rules = rules(language);
+    let ids = |names: &[&str]| -> Vec<u16> { names.iter().map(|name| vocab.id(name)).collect() };
+    let mut res: Vec<Box<dyn Pass>> = vec![];
+    if config.normalize_loops && !rules.loops.is_empty() {
+        res.push(Box::new(Loops {
+            kinds: ids(rules.loops),
+        }));
+    }
+    if config.normalize_increments {
+        let replacements = |list: &[(&str, &[&str])]| {
+            list.iter()
+                .map(|(op, replacement)| (vocab.id(op), kinds(vocab, replacement)))
+                .collect()
+        };
+        res.push(Box::new(Increments {
+            identifier: vocab.id(rules.identifier),
+            one: vocab.id(rules.one),
+            assign: vocab.id(rules.assign),
+            increments: replacements(rules.increments),
+            compound: replacements(rules.compound),
+            boundaries: ids(rules.boundaries),
+        }));
+    }
+    // parentheses first, so that `(1) == x` is ordered as well
+    if config.normalize_parentheses {
+        res.push(Box::new(Parentheses {
+            open: vocab.id(rules.parentheses.0),
+            close: vocab.id(rules.parentheses.1),
+            operands: ids(rules.operands),
+            callees: ids(rules.callees),
+        }));
+    }
+    if config.normalize_operands {
+        res.push(Box::new(Operands {
+            operands: ids(rules.operands),
+            operators: rules.commutative.iter().map(|op| ids(op)).collect(),
+            boundaries: ids(rules.boundaries),
+        }));
+    }
+    res
+}
+
+/// Rewrite tokens of a submission by the passes enabled in config
+pub fn normalize(tokens: &mut Vec<Token>, language: Language, config: &DetectionConfig) {
+    for pass in passes(language, config) {
+        *tokens = pass.apply(std::mem::take(tokens));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, passes};
+    use crate::{
+        config::DetectionConfig,
+        lang::{tokenize_str, Language},
+    };
+
+    fn all() -> DetectionConfig {
+        DetectionConfig {
+            normalize_loops: true,
+            normalize_increments: true,
+            normalize_operands: true,
+            normalize_parentheses: true,
+            ..Default::default()
+        }
+    }
+
+    // kinds and spellings after normalization
+    fn normalized(code: &str, language: Language) -> (Vec<u16>, Vec<String>) {
+        let mut tokens = tokenize_str(code, language).unwrap();
+        normalize(&mut tokens, language, &all());
+        (
+            tokens.iter().map(|t| t.kind).collect(),
+            tokens.iter().map(|t| t.spelling.clone()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_rules() {
+        // kind names of all languages are in their vocabularies
+        for language in [
+            #[cfg(feature = "cpp_lexer")]
+            Language::Cpp,
+            #[cfg(feature = "rust")]
+            Language::Rust,
+            #[cfg(feature = "verilog")]
+            Language::Verilog,
+            #[cfg(feature = "python")]
+            Language::Python,
+            #[cfg(feature = "sql")]
+            Language::SQL,
+            #[cfg(feature = "javascript")]
+            Language::JavaScript,
+            #[cfg(feature = "lua")]
+            Language::Lua,
+        ] {
+            assert!(!passes(language, &all()).is_empty());
+        }
+        assert!(passes(Language::Rust, &DetectionConfig::default()).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "javascript")]
+    fn test_normalize_javascript() {
+        let (kinds, spellings) = normalized(
+            "for (let i = 0; (1) == i; i++) { s = s + ((i * 2)); }",
+            Language::JavaScript,
+        );
+        let (other, _) = normalized(
+            "while (let i = 0; i == 1; i += 1) { s += (i * 2); }",
+            Language::JavaScript,
+        );
+        assert_eq!(kinds, other);
+        assert_eq!(spellings[7..10], ["i", "==", "1"]);
+
+        // the prefix increment is moved after the identifier
+        let code = "++i;";
+        let mut tokens = tokenize_str(code, Language::JavaScript).unwrap();
+        normalize(&mut tokens, Language::JavaScript, &all());
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(spellings, ["i", "+=", "1", ";"]);
+        // positions still cover the original code, in order
+        assert_eq!(&code[tokens[0].span.clone()], "++i");
+        assert_eq!(&code[tokens[2].span.clone()], "++i");
+        assert_eq!((tokens[3].line, tokens[3].column), (1, 4));
+    }
+
+    #[test]
+    #[cfg(feature = "rust")]
+    fn test_normalize_rust() {
+        let (kinds, _) = normalized(
+            "fn f(mut x: i32) { loop { if 0 == x { x = x - 1; } g((x), (1)); } }",
+            Language::Rust,
+        );
+        let (other, _) = normalized(
+            "fn f(mut x: i32) { while { if x == 0 { x -= 1; } g(x, 1); } }",
+            Language::Rust,
+        );
+        assert_eq!(kinds, other);
+
+        // swapped operands keep their own positions
+        let code = "if x == 1 {}";
+        let mut tokens = tokenize_str(code, Language::Rust).unwrap();
+        normalize(&mut tokens, Language::Rust, &all());
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(spellings[1..5], ["1", "=", "=", "x"]);
+        assert_eq!(&code[tokens[1].span.clone()], "1");
+        assert_eq!(&code[tokens[4].span.clone()], "x");
+        assert_eq!((tokens[1].column, tokens[4].column), (9, 4));
+
+        // calls keep their parentheses
+        let (kinds, _) = normalized("f(x)", Language::Rust);
+        assert_eq!(kinds.len(), 4);
+
+        // disabled passes leave tokens as they are
+        let mut tokens = tokenize_str("while (x) {}", Language::Rust).unwrap();
+        let before: Vec<u16> = tokens.iter().map(|t| t.kind).collect();
+        normalize(&mut tokens, Language::Rust, &DetectionConfig::default());
+        let after: Vec<u16> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(before, after);
+    }
+}
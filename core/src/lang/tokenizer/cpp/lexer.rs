@@ -0,0 +1,397 @@
+//! Pure-Rust lexer for C, C++ and CUDA
+//!
+//! Like the libclang backend, it lexes raw source without preprocessing:
+//! comments are skipped and directives are tokenized like code. Keywords of
+//! all dialects are recognized, so `restrict` is a keyword in C++ code too.
+use super::{PUNCTUATIONS, VOCABULARY};
+use crate::token::Token;
+use once_cell::sync::Lazy;
+
+// punctuators by decreasing length for longest match, including those missing
+// from the vocabulary
+static PUNCTUATORS: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    let mut punctuators: Vec<&str> = PUNCTUATIONS.to_vec();
+    punctuators.extend(["##", "%:", "%:%:"]);
+    punctuators.sort_by_key(|p| std::cmp::Reverse(p.len()));
+    punctuators
+});
+
+// encoding prefixes of string and character literals, longest first
+const LITERAL_PREFIXES: [&str; 9] = ["u8R", "LR", "uR", "UR", "u8", "R", "L", "u", "U"];
+
+// longest delimiter of raw string literals
+const MAX_RAW_DELIMITER: usize = 16;
+
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || c == '$' || c.is_alphabetic()
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c == '_' || c == '$' || c.is_alphanumeric()
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    // 1-based position of `pos`
+    line: u32,
+    column: u32,
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek(i) == Some(c))
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek(0) {
+            self.pos += 1;
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn bump_n(&mut self, n: usize) {
+        for _ in 0..n {
+            self.bump();
+        }
+    }
+
+    fn bump_while(&mut self, f: impl Fn(char) -> bool) {
+        while self.peek(0).is_some_and(&f) {
+            self.bump();
+        }
+    }
+
+    /// Skip whitespace, line splices and comments, returns whether anything was skipped
+    fn skip_trivia(&mut self) -> bool {
+        let start = self.pos;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(c), _) if c.is_whitespace() => self.bump(),
+                (Some('\\'), Some('\n')) => self.bump_n(2),
+                (Some('\\'), Some('\r')) if self.peek(2) == Some('\n') => self.bump_n(3),
+                (Some('/'), Some('/')) => {
+                    // a line splice continues the comment
+                    while let Some(c) = self.peek(0) {
+                        if c == '\n' {
+                            break;
+                        }
+                        if c == '\\' {
+                            self.bump();
+                        }
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    self.bump_n(2);
+                    while self.peek(0).is_some() && !self.starts_with("*/") {
+                        self.bump();
+                    }
+                    self.bump_n(2);
+                }
+                _ => break,
+            }
+        }
+        self.pos != start
+    }
+
+    /// Length of encoding prefix and whether the literal starting here is raw
+    fn literal_prefix(&self) -> Option<(usize, bool)> {
+        LITERAL_PREFIXES.iter().find_map(|prefix| {
+            let raw = prefix.ends_with('R');
+            let len = prefix.len();
+            let quote = self.peek(len);
+            let quoted = quote == Some('"') || (!raw && quote == Some('\''));
+            (self.starts_with(prefix) && quoted).then_some((len, raw))
+        })
+    }
+
+    /// Lex a quoted literal up to the closing quote or end of line
+    fn quoted(&mut self) {
+        let quote = self.peek(0);
+        self.bump();
+        while let Some(c) = self.peek(0) {
+            if c == '\n' {
+                break;
+            }
+            self.bump();
+            if c == '\\' {
+                self.bump();
+            } else if Some(c) == quote {
+                break;
+            }
+        }
+    }
+
+    /// Lex a raw string literal `R"delim(...)delim"` at the quote, returns false if malformed
+    fn raw_string(&mut self) -> bool {
+        let delimiter: String = self.chars[self.pos + 1..]
+            .iter()
+            .take_while(|c| !matches!(c, '(' | ')' | '\\' | '"') && !c.is_whitespace())
+            .take(MAX_RAW_DELIMITER + 1)
+            .collect();
+        if delimiter.chars().count() > MAX_RAW_DELIMITER
+            || self.peek(1 + delimiter.chars().count()) != Some('(')
+        {
+            return false;
+        }
+        let end = format!("){}\"", delimiter);
+        self.bump_n(2 + delimiter.chars().count());
+        while self.peek(0).is_some() && !self.starts_with(&end) {
+            self.bump();
+        }
+        self.bump_n(end.chars().count());
+        true
+    }
+
+    /// Lex a preprocessing number, which also covers malformed numbers
+    fn number(&mut self) {
+        self.bump();
+        while let Some(c) = self.peek(0) {
+            match (c, self.peek(1)) {
+                ('e' | 'E' | 'p' | 'P', Some('+' | '-')) => self.bump_n(2),
+                // digit separator
+                ('\'', Some(next)) if next.is_alphanumeric() => self.bump_n(2),
+                (c, _) if c == '.' || is_identifier_continue(c) => self.bump(),
+                _ => break,
+            }
+        }
+    }
+
+    /// Lex one token, returning its kind
+    fn token(&mut self) -> u16 {
+        let c = self.peek(0).unwrap();
+        if let Some((len, raw)) = self.literal_prefix() {
+            self.bump_n(len);
+            if !raw || !self.raw_string() {
+                self.quoted();
+            }
+            // user-defined literal suffix
+            self.bump_while(is_identifier_continue);
+            return VOCABULARY.id("<literal>");
+        }
+        if c == '"' || c == '\'' {
+            self.quoted();
+            self.bump_while(is_identifier_continue);
+            return VOCABULARY.id("<literal>");
+        }
+        if c.is_ascii_digit() || (c == '.' && self.peek(1).is_some_and(|c| c.is_ascii_digit())) {
+            self.number();
+            return VOCABULARY.id("<literal>");
+        }
+        if is_identifier_start(c) {
+            let start = self.pos;
+            self.bump_while(is_identifier_continue);
+            let word: String = self.chars[start..self.pos].iter().collect();
+            // only keywords in the vocabulary look like identifiers
+            return VOCABULARY
+                .get(&word)
+                .unwrap_or_else(|| VOCABULARY.id("<identifier>"));
+        }
+        // C++11: `<::` is `<` followed by `::`, unless it starts `<:::` or `<::>`
+        if self.starts_with("<::") && !matches!(self.peek(3), Some(':' | '>')) {
+            self.bump();
+            return VOCABULARY.id("<");
+        }
+        for punctuator in PUNCTUATORS.iter() {
+            if self.starts_with(punctuator) {
+                self.bump_n(punctuator.len());
+                return VOCABULARY
+                    .get(punctuator)
+                    .unwrap_or_else(|| VOCABULARY.id("<punctuation>"));
+            }
+        }
+        self.bump();
+        VOCABULARY.id("<punctuation>")
+    }
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let mut lexer = Lexer {
+        chars: content.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+    };
+    let mut tokens = vec![];
+    loop {
+        while lexer.skip_trivia() {}
+        if lexer.peek(0).is_none() {
+            break;
+        }
+        let (start, line, column) = (lexer.pos, lexer.line, lexer.column);
+        let kind = lexer.token();
+        tokens.push(Token {
+            spelling: lexer.chars[start..lexer.pos].iter().collect(),
+            kind,
+            file: 0,
+            line,
+            column,
+            end_line: lexer.line,
+            end_column: lexer.column,
+            span: 0..0,
+        });
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_str, VOCABULARY};
+
+    fn spellings(code: &str) -> Vec<String> {
+        tokenize_str(code)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.spelling)
+            .collect()
+    }
+
+    fn kinds(code: &str) -> Vec<&'static str> {
+        tokenize_str(code)
+            .unwrap()
+            .iter()
+            .map(|t| VOCABULARY.name(t.kind).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let code = "int main() {\n  /* entry\n point */ return 0; // done\n}";
+        let tokens = tokenize_str(code).unwrap();
+        assert_eq!(
+            kinds(code),
+            [
+                "int",
+                "<identifier>",
+                "(",
+                ")",
+                "{",
+                "return",
+                "<literal>",
+                ";",
+                "}"
+            ]
+        );
+        assert_eq!(tokens[1].spelling, "main");
+        assert_eq!((tokens[1].line, tokens[1].column), (1, 5));
+        assert_eq!((tokens[1].end_line, tokens[1].end_column), (1, 9));
+        assert_eq!(tokens[5].spelling, "return");
+        assert_eq!((tokens[5].line, tokens[5].column), (3, 11));
+        assert_eq!((tokens[8].line, tokens[8].column), (4, 1));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(
+            spellings(r#"x = 1'000'000 + 0x1p-3 + 1.5e+10f + .5 + 42_km;"#),
+            [
+                "x",
+                "=",
+                "1'000'000",
+                "+",
+                "0x1p-3",
+                "+",
+                "1.5e+10f",
+                "+",
+                ".5",
+                "+",
+                "42_km",
+                ";"
+            ]
+        );
+        assert_eq!(
+            spellings(r#"s = u8"a\"b" L'c' '\'' "x"s;"#),
+            ["s", "=", r#"u8"a\"b""#, "L'c'", r"'\''", r#""x"s"#, ";"]
+        );
+        let code = "auto s = R\"delim(a)\"\n)delim\";";
+        let tokens = tokenize_str(code).unwrap();
+        assert_eq!(tokens[3].spelling, "R\"delim(a)\"\n)delim\"");
+        assert_eq!(VOCABULARY.name(tokens[3].kind), Some("<literal>"));
+        assert_eq!((tokens[4].line, tokens[4].column), (2, 8));
+        // not a literal prefix
+        assert_eq!(spellings("LR + u8"), ["LR", "+", "u8"]);
+    }
+
+    #[test]
+    fn test_punctuations() {
+        assert_eq!(
+            kinds("a->*b <=> c <<= d ## e"),
+            [
+                "<identifier>",
+                "->*",
+                "<identifier>",
+                "<=>",
+                "<identifier>",
+                "<<=",
+                "<identifier>",
+                "<punctuation>",
+                "<identifier>"
+            ]
+        );
+        assert_eq!(
+            spellings("std::vector<::std::string> v;"),
+            ["std", "::", "vector", "<", "::", "std", "::", "string", ">", "v", ";"]
+        );
+        // CUDA kernel launch
+        assert_eq!(
+            spellings("kernel<<<grid, block>>>(x);"),
+            ["kernel", "<<", "<", "grid", ",", "block", ">>", ">", "(", "x", ")", ";"]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let code = "#include <stdio.h>\n#define MAX(a, b) \\\n  ((a) > (b) ? (a) : (b))\n";
+        assert_eq!(
+            spellings(code)[..8],
+            ["#", "include", "<", "stdio", ".", "h", ">", "#"]
+        );
+        assert_eq!(kinds("#include <stdio.h>")[..2], ["#", "<identifier>"]);
+        assert_eq!(spellings("a // comment \\\n continued\nb"), ["a", "b"]);
+    }
+
+    #[cfg(feature = "cpp")]
+    #[test]
+    fn test_same_as_libclang() {
+        let code = r#"#include <cstdio>
+template <typename T>
+struct Point {
+    T x, y;
+    T dot(const Point &other) const { return x * other.x + y * other.y; }
+};
+
+int main(int argc, char **argv) {
+    Point<double> p{1.0, 2.5e-3};
+    unsigned long n = 0x10UL;
+    for (int i = 0; i < argc && n >= 1; ++i) {
+        printf("%s %c 你好\n", argv[i], 'a'); /* 中文 */ --n;
+    }
+    return p.dot(p) > 0 ? 0 : 1;
+}
+"#;
+        let lexed = tokenize_str(code).unwrap();
+        let expected = super::super::libclang::tokenize_str(code).unwrap();
+        assert_eq!(lexed.len(), expected.len());
+        for (token, expected) in lexed.iter().zip(&expected) {
+            assert_eq!(
+                (&token.spelling, token.kind, token.line, token.column),
+                (
+                    &expected.spelling,
+                    expected.kind,
+                    expected.line,
+                    expected.column
+                )
+            );
+        }
+    }
+}
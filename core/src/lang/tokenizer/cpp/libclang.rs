@@ -0,0 +1,453 @@
+//! C/C++ tokenizer backed by libclang
+//!
+//! clang-rs allows only one `Clang` instance per process, so tokenization in
+//! one process is serialized. To scale across cores, files are handed to a pool
+//! of `cpp_tokenizer` worker processes, one per concurrently tokenizing thread.
+//! If the worker executable cannot be found, tokenization falls back to the
+//! current process with a warning. Workers taking longer than `WORKER_TIMEOUT`
+//! for a file are killed.
+use super::VOCABULARY;
+use crate::{common::LineStarts, token::Token};
+use anyhow::anyhow;
+use clang::token::TokenKind;
+use log::*;
+use once_cell::sync::Lazy;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::Duration,
+};
+use tempfile::tempdir;
+
+static CLANG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// longest time a worker may take to tokenize one file
+const WORKER_TIMEOUT: Duration = Duration::from_secs(60);
+
+static WORKERS: Lazy<WorkerPool> = Lazy::new(|| {
+    let path = worker_path();
+    if path.is_none() {
+        warn!("cpp_tokenizer not found, C/C++ files are tokenized one at a time in process");
+    }
+    WorkerPool {
+        path,
+        timeout: WORKER_TIMEOUT,
+        idle: Mutex::new(vec![]),
+    }
+});
+
+/// Path of worker executable: `CPP_TOKENIZER` if set, otherwise `cpp_tokenizer`
+/// next to the current executable or its parent directory (for test executables)
+fn worker_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("CPP_TOKENIZER") {
+        return Some(path.into());
+    }
+    let exe = std::env::current_exe().ok()?;
+    let name = format!("cpp_tokenizer{}", std::env::consts::EXE_SUFFIX);
+    std::iter::successors(exe.parent(), |dir| dir.parent())
+        .take(2)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+type Response = io::Result<anyhow::Result<Vec<Token>>>;
+
+/// A running worker process
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    // responses read by a thread, so that waiting for them can time out
+    responses: Receiver<Response>,
+}
+
+impl Worker {
+    fn spawn(path: &Path) -> io::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, responses) = mpsc::channel();
+        // ends when the worker exits or is killed
+        std::thread::spawn(move || loop {
+            let response = read_response(&mut stdout);
+            let failed = response.is_err();
+            if sender.send(response).is_err() || failed {
+                break;
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    /// Tokenize file in worker, the outer error means the worker is unusable
+    fn tokenize(&mut self, path: &str, timeout: Duration) -> Response {
+        writeln!(self.stdin, "{}", path)?;
+        self.stdin.flush()?;
+        match self.responses.recv_timeout(timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no response within {:?}", timeout),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+struct WorkerPool {
+    path: Option<PathBuf>,
+    timeout: Duration,
+    idle: Mutex<Vec<Worker>>,
+}
+
+impl WorkerPool {
+    /// Tokenize file in an idle or new worker, `None` if no worker is available
+    fn tokenize(&self, path: &Path) -> Option<anyhow::Result<Vec<Token>>> {
+        let exe = self.path.as_ref()?;
+        // the protocol is line based
+        let path = path.to_str().filter(|path| !path.contains('\n'))?;
+        let idle = self.idle.lock().unwrap().pop();
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => match Worker::spawn(exe) {
+                Ok(worker) => worker,
+                Err(err) => {
+                    warn!("Failed to start {}: {}", exe.display(), err);
+                    return None;
+                }
+            },
+        };
+        match worker.tokenize(path, self.timeout) {
+            Ok(res) => {
+                self.idle.lock().unwrap().push(worker);
+                Some(res)
+            }
+            // the worker may have crashed or hung on this file, it is killed when dropped
+            Err(err) => Some(Err(anyhow!("C++ tokenizer worker failed: {}", err))),
+        }
+    }
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected response {:?}", line),
+    )
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    line.pop();
+    Ok(line)
+}
+
+/// Read response of worker: `ok <count>` followed by tokens, or `err <message>`
+///
+/// Each token is a line `<kind> <line> <column> <end line> <end column> <length>`
+/// followed by the spelling of `<length>` bytes.
+fn read_response(reader: &mut impl BufRead) -> Response {
+    let line = read_line(reader)?;
+    if let Some(message) = line.strip_prefix("err ") {
+        return Ok(Err(anyhow!("{}", message)));
+    }
+    let count: usize = line
+        .strip_prefix("ok ")
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| invalid_data(&line))?;
+    let mut tokens = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_line(reader)?;
+        let fields = line
+            .split(' ')
+            .map(|field| field.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid_data(&line))?;
+        let [kind, line, column, end_line, end_column, len] = fields[..] else {
+            return Err(invalid_data(&line));
+        };
+        let mut spelling = vec![0; len as usize];
+        reader.read_exact(&mut spelling)?;
+        tokens.push(Token {
+            spelling: String::from_utf8(spelling)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            kind: u16::try_from(kind)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            file: 0,
+            line,
+            column,
+            end_line,
+            end_column,
+            span: 0..0,
+        });
+    }
+    Ok(Ok(tokens))
+}
+
+/// Serve tokenization requests of paths, one per line, until input is closed
+///
+/// This is the main loop of the `cpp_tokenizer` worker executable.
+pub fn serve(input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+    for path in input.lines() {
+        match tokenize_in_process(Path::new(&path?)) {
+            Ok(tokens) => {
+                writeln!(output, "ok {}", tokens.len())?;
+                for token in tokens {
+                    writeln!(
+                        output,
+                        "{} {} {} {} {} {}",
+                        token.kind,
+                        token.line,
+                        token.column,
+                        token.end_line,
+                        token.end_column,
+                        token.spelling.len()
+                    )?;
+                    output.write_all(token.spelling.as_bytes())?;
+                }
+            }
+            Err(err) => writeln!(output, "err {}", format!("{:#}", err).replace('\n', " "))?,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+pub fn tokenize(path: &Path) -> anyhow::Result<Vec<Token>> {
+    match WORKERS.tokenize(path) {
+        Some(res) => res,
+        None => tokenize_in_process(path),
+    }
+}
+
+fn tokenize_in_process(path: &Path) -> anyhow::Result<Vec<Token>> {
+    // clang-rs only allows single thread usage
+    let _guard = CLANG_LOCK.lock().unwrap();
+    let clang = clang::Clang::new().map_err(|err| anyhow!("{}", err))?;
+    let index = clang::Index::new(&clang, true, false);
+    let tu = index.parser(path).parse()?;
+    // clang counts columns in bytes, tokens in characters
+    let code = std::fs::read_to_string(path)?;
+    let lines = LineStarts::new(&code);
+    let position = |location: clang::source::Location| {
+        let column = lines.char_column(
+            &code,
+            location.line as usize - 1,
+            location.column as usize - 1,
+        );
+        (location.line, column as u32 + 1)
+    };
+    let mut vector = vec![];
+    if let Some(range) = tu.get_entity().get_range() {
+        for token in range.tokenize() {
+            let kind = token.get_kind();
+            let spelling = token.get_spelling();
+            let kind = match kind {
+                TokenKind::Comment => continue,
+                TokenKind::Identifier => VOCABULARY.id("<identifier>"),
+                TokenKind::Literal => VOCABULARY.id("<literal>"),
+                TokenKind::Keyword => VOCABULARY
+                    .get(&spelling)
+                    .unwrap_or_else(|| VOCABULARY.id("<keyword>")),
+                TokenKind::Punctuation => VOCABULARY
+                    .get(&spelling)
+                    .unwrap_or_else(|| VOCABULARY.id("<punctuation>")),
+            };
+
+            let (line, column) = position(token.get_location().get_file_location());
+            let (end_line, end_column) = position(token.get_range().get_end().get_file_location());
+            vector.push(Token {
+                spelling,
+                kind,
+                file: 0,
+                line,
+                column,
+                end_line,
+                end_column,
+                span: 0..0,
+            })
+        }
+    }
+    Ok(vector)
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let dir = tempdir()?;
+    let path = dir.path().join("code.cpp");
+    std::fs::write(&path, content)?;
+    tokenize(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_response, serve, tokenize, tokenize_in_process, tokenize_str, WorkerPool, VOCABULARY,
+    };
+    use crate::token::Token;
+    use rayon::prelude::*;
+    use std::{
+        path::Path,
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_tokenize() {
+        let code = "int main() { return 0; }";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "int");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "main");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 5);
+
+        assert_eq!(tokens[2].spelling, "(");
+        assert_eq!(tokens[2].line, 1);
+        assert_eq!(tokens[2].column, 9);
+
+        assert_eq!(tokens[3].spelling, ")");
+        assert_eq!(tokens[3].line, 1);
+        assert_eq!(tokens[3].column, 10);
+
+        assert_eq!(tokens[8].spelling, "}");
+        assert_eq!(tokens[8].line, 1);
+        assert_eq!(tokens[8].column, 24);
+    }
+
+    #[test]
+    fn test_non_ascii() {
+        // columns count characters, not bytes
+        let code = "int s = \"中文\"; int t;";
+        let tokens = tokenize_str(code).unwrap();
+        assert_eq!(tokens[3].spelling, "\"中文\"");
+        assert_eq!((tokens[3].column, tokens[3].end_column), (9, 13));
+        assert_eq!(tokens[5].spelling, "int");
+        assert_eq!(tokens[5].column, 15);
+    }
+
+    #[test]
+    fn test_kinds() {
+        let code = "int main() { return 0; }";
+        let tokens = tokenize_str(code).unwrap();
+        let kinds: Vec<&str> = tokens
+            .iter()
+            .map(|t| VOCABULARY.name(t.kind).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "int",
+                "<identifier>",
+                "(",
+                ")",
+                "{",
+                "return",
+                "<literal>",
+                ";",
+                "}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_protocol() {
+        let code = "int main() {\n    puts(\"hello world\");\n}\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("code.cpp");
+        std::fs::write(&path, code).unwrap();
+
+        let mut output = vec![];
+        serve(format!("{}\n", path.display()).as_bytes(), &mut output).unwrap();
+        let tokens = read_response(&mut output.as_slice()).unwrap().unwrap();
+        let expected = tokenize_in_process(&path).unwrap();
+        assert_eq!(tokens.len(), expected.len());
+        for (token, expected) in tokens.iter().zip(&expected) {
+            assert_eq!(token.spelling, expected.spelling);
+            assert_eq!(token.kind, expected.kind);
+            assert_eq!(
+                (token.line, token.column, token.end_line, token.end_column),
+                (
+                    expected.line,
+                    expected.column,
+                    expected.end_line,
+                    expected.end_column
+                )
+            );
+        }
+        assert!(tokens.iter().any(|t| t.spelling == "\"hello world\""));
+
+        let mut output = vec![];
+        serve("/nonexistent/code.cpp\n".as_bytes(), &mut output).unwrap();
+        assert!(read_response(&mut output.as_slice()).unwrap().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+        // a worker that never responds
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("hang.sh");
+        std::fs::write(&exe, "#!/bin/sh\nsleep 60\n").unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let pool = WorkerPool {
+            path: Some(exe),
+            timeout: Duration::from_millis(200),
+            idle: Mutex::new(vec![]),
+        };
+        let start = Instant::now();
+        let res = pool.tokenize(Path::new("code.cpp")).unwrap();
+        assert!(format!("{:#}", res.unwrap_err()).contains("no response"));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // the hung worker is not reused
+        assert!(pool.idle.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..32)
+            .map(|i| {
+                let path = dir.path().join(format!("code{}.cpp", i));
+                let code = format!("int f{}(int x) {{ return x * {}; }}", i, i);
+                std::fs::write(&path, code).unwrap();
+                path
+            })
+            .collect();
+        let spellings = |tokens: Vec<Token>| -> Vec<String> {
+            tokens.into_iter().map(|t| t.spelling).collect()
+        };
+        let tokens: Vec<Vec<String>> = paths
+            .par_iter()
+            .map(|path| spellings(tokenize(path).unwrap()))
+            .collect();
+        for (path, tokens) in paths.iter().zip(tokens) {
+            assert_eq!(tokens, spellings(tokenize_in_process(path).unwrap()));
+        }
+    }
+}
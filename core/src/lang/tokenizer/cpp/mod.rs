@@ -0,0 +1,222 @@
+//! C/C++ tokenizers
+//!
+//! Two backends share the vocabulary below: `libclang` (feature `cpp`) and the
+//! pure-Rust `lexer` (feature `cpp_lexer`, implied by `cpp`). The backend is chosen
+//! by the `CPP_BACKEND` environment variable (`clang` or `lexer`), defaulting to
+//! libclang when it is compiled in.
+use crate::lang::Tokenize;
+use crate::token::{Token, Vocabulary};
+use log::*;
+use once_cell::sync::Lazy;
+use std::path::Path;
+
+pub mod lexer;
+#[cfg(feature = "cpp")]
+pub mod libclang;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Clang,
+    Lexer,
+}
+
+static BACKEND: Lazy<Backend> = Lazy::new(|| {
+    let default = if cfg!(feature = "cpp") {
+        Backend::Clang
+    } else {
+        Backend::Lexer
+    };
+    match std::env::var("CPP_BACKEND").as_deref() {
+        Ok("clang") if cfg!(feature = "cpp") => Backend::Clang,
+        Ok("clang") => {
+            warn!("libclang support is not compiled in, using the lexer for C/C++");
+            Backend::Lexer
+        }
+        Ok("lexer") => Backend::Lexer,
+        Ok(other) => {
+            warn!("Unknown CPP_BACKEND {:?}, using {:?}", other, default);
+            default
+        }
+        Err(_) => default,
+    }
+});
+
+/// Backend used to tokenize C/C++
+pub fn backend() -> Backend {
+    *BACKEND
+}
+
+pub struct Cpp;
+
+impl Tokenize for Cpp {
+    fn tokenize(&self, path: &Path) -> anyhow::Result<Vec<Token>> {
+        match backend() {
+            #[cfg(feature = "cpp")]
+            Backend::Clang => libclang::tokenize(path),
+            _ => lexer::tokenize_str(&std::fs::read_to_string(path)?),
+        }
+    }
+
+    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
+        match backend() {
+            #[cfg(feature = "cpp")]
+            Backend::Clang => libclang::tokenize_str(content),
+            _ => lexer::tokenize_str(content),
+        }
+    }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
+}
+
+// C and C++ keywords, including alternative operator representations
+// https://en.cppreference.com/w/cpp/keyword
+// https://en.cppreference.com/w/c/keyword
+const KEYWORDS: [&str; 129] = [
+    "alignas",
+    "alignof",
+    "and",
+    "and_eq",
+    "asm",
+    "atomic_cancel",
+    "atomic_commit",
+    "atomic_noexcept",
+    "auto",
+    "bitand",
+    "bitor",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "char8_t",
+    "char16_t",
+    "char32_t",
+    "class",
+    "compl",
+    "concept",
+    "const",
+    "consteval",
+    "constexpr",
+    "constinit",
+    "const_cast",
+    "continue",
+    "co_await",
+    "co_return",
+    "co_yield",
+    "decltype",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "dynamic_cast",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "not",
+    "not_eq",
+    "nullptr",
+    "operator",
+    "or",
+    "or_eq",
+    "private",
+    "protected",
+    "public",
+    "reflexpr",
+    "register",
+    "reinterpret_cast",
+    "requires",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "static_cast",
+    "struct",
+    "switch",
+    "synchronized",
+    "template",
+    "this",
+    "thread_local",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typeid",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "wchar_t",
+    "while",
+    "xor",
+    "xor_eq",
+    "restrict",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_BitInt",
+    "_Bool",
+    "_Complex",
+    "_Decimal128",
+    "_Decimal32",
+    "_Decimal64",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+    "__attribute__",
+    "__asm__",
+    "__inline",
+    "__inline__",
+    "__restrict",
+    "__restrict__",
+    "__typeof__",
+    "__volatile__",
+    "__extension__",
+    "__int128",
+    "__builtin_va_arg",
+    "__builtin_offsetof",
+    "__alignof__",
+    "__thread",
+    "__func__",
+    "__FUNCTION__",
+    "__PRETTY_FUNCTION__",
+];
+
+// https://en.cppreference.com/w/cpp/language/punctuators
+const PUNCTUATIONS: [&str; 55] = [
+    "{", "}", "[", "]", "(", ")", "<:", ":>", "<%", "%>", ";", ":", "...", "?", "::", ".", ".*",
+    "->", "->*", "~", "!", "+", "-", "*", "/", "%", "^", "&", "|", "=", "+=", "-=", "*=", "/=",
+    "%=", "^=", "&=", "|=", "==", "!=", "<", ">", "<=", ">=", "<=>", "&&", "||", "<<", ">>", "<<=",
+    ">>=", "++", "--", ",", "#",
+];
+
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    // keywords and punctuations missing from the lists fall back to categories
+    let mut kinds = vec!["<identifier>", "<literal>", "<keyword>", "<punctuation>"];
+    kinds.extend(KEYWORDS);
+    kinds.extend(PUNCTUATIONS);
+    Vocabulary::new(kinds)
+});
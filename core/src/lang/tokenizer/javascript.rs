@@ -1,8 +1,10 @@
-use crate::lang::Tokenize;
-use crate::token::Token;
+use crate::lang::{SyntaxError, Tokenize};
+use crate::token::{Token, Vocabulary};
 use boa_interner::Interner;
 use boa_parser::lexer::token::TokenKind::*;
+use boa_parser::lexer::Error as LexError;
 use boa_parser::Lexer;
+use once_cell::sync::Lazy;
 use std::io::Cursor;
 
 pub struct JavaScript;
@@ -11,34 +13,127 @@ impl Tokenize for JavaScript {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
 }
 
+// https://tc39.es/ecma262/#sec-keywords-and-reserved-words
+const KEYWORDS: [&str; 41] = [
+    "await",
+    "async",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+// https://tc39.es/ecma262/#sec-punctuators
+const PUNCTUATORS: [&str; 57] = [
+    "?.", "{", "(", ")", "[", "]", ".", "...", ";", ",", "<", ">", "<=", ">=", "==", "!=", "===",
+    "!==", "+", "-", "*", "%", "**", "++", "--", "<<", ">>", ">>>", "&", "|", "^", "!", "~", "&&",
+    "||", "??", "?", ":", "=", "+=", "-=", "*=", "%=", "**=", "<<=", ">>=", ">>>=", "&=", "|=",
+    "^=", "&&=", "||=", "??=", "=>", "/", "/=", "}",
+];
+
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    // keywords and punctuators missing from the lists fall back to categories
+    let mut kinds = vec![
+        "<boolean>",
+        "<identifier>",
+        "<private identifier>",
+        "<keyword>",
+        "<null>",
+        "<number>",
+        "<punctuator>",
+        "<string>",
+        "<template>",
+        "<template middle>",
+        "<regexp>",
+    ];
+    kinds.extend(KEYWORDS);
+    kinds.extend(PUNCTUATORS);
+    Vocabulary::new(kinds)
+});
+
 fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
     let mut lexer = Lexer::new(Cursor::new(content));
     let mut interner = Interner::new();
-    while let Some(token) = lexer.next(&mut interner)? {
+    while let Some(token) = lexer.next(&mut interner).map_err(|err| match err {
+        LexError::Syntax(message, position) => SyntaxError {
+            line: position.line_number(),
+            message: message.to_string(),
+        }
+        .into(),
+        err => anyhow::Error::from(err),
+    })? {
+        let spelling = token.kind().to_string(&interner);
         let kind = match token.kind() {
-            BooleanLiteral(_) => 0,
+            BooleanLiteral(_) => VOCABULARY.id("<boolean>"),
             EOF => continue,
-            IdentifierName(_) => 1,
-            PrivateIdentifier(_) => 2,
-            Keyword(_) => 3,
-            NullLiteral(_) => 4,
-            NumericLiteral(_) => 5,
-            Punctuator(_) => 6,
-            StringLiteral(_) => 7,
-            TemplateNoSubstitution(_) => 8,
-            TemplateMiddle(_) => 9,
-            RegularExpressionLiteral(_, _) => 10,
+            IdentifierName(_) => VOCABULARY.id("<identifier>"),
+            PrivateIdentifier(_) => VOCABULARY.id("<private identifier>"),
+            Keyword(_) => VOCABULARY
+                .get(&spelling)
+                .unwrap_or_else(|| VOCABULARY.id("<keyword>")),
+            NullLiteral(_) => VOCABULARY.id("<null>"),
+            NumericLiteral(_) => VOCABULARY.id("<number>"),
+            Punctuator(_) => VOCABULARY
+                .get(&spelling)
+                .unwrap_or_else(|| VOCABULARY.id("<punctuator>")),
+            StringLiteral(_) => VOCABULARY.id("<string>"),
+            TemplateNoSubstitution(_) => VOCABULARY.id("<template>"),
+            TemplateMiddle(_) => VOCABULARY.id("<template middle>"),
+            RegularExpressionLiteral(_, _) => VOCABULARY.id("<regexp>"),
             LineTerminator => continue,
             Comment => continue,
         };
         res.push(Token {
             kind,
-            spelling: token.kind().to_string(&interner),
+            spelling,
+            file: 0,
             line: token.span().start().line_number(),
             column: token.span().start().column_number(),
+            end_line: token.span().end().line_number(),
+            end_column: token.span().end().column_number(),
+            span: 0..0,
         });
     }
     Ok(res)
@@ -1,7 +1,8 @@
-use crate::lang::Tokenize;
-use crate::token::Token;
+use crate::lang::{SyntaxError, Tokenize};
+use crate::token::{Token, Vocabulary};
 use full_moon::tokenizer::tokens;
 use full_moon::tokenizer::TokenKind::*;
+use once_cell::sync::Lazy;
 
 pub struct Lua;
 
@@ -9,28 +10,65 @@ impl Tokenize for Lua {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
 }
 
+// keywords and other symbols, https://www.lua.org/manual/5.4/manual.html#3.1
+const SYMBOLS: [&str; 55] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while", "+", "-",
+    "*", "/", "%", "^", "#", "&", "~", "|", "<<", ">>", "//", "==", "~=", "<=", ">=", "<", ">",
+    "=", "(", ")", "{", "}", "[", "]", "::", ";", ":", ",", ".", "..", "...",
+];
+
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    // symbols missing from the list fall back to a category
+    let mut kinds = vec![
+        "<identifier>",
+        "<number>",
+        "<shebang>",
+        "<string>",
+        "<symbol>",
+    ];
+    kinds.extend(SYMBOLS);
+    Vocabulary::new(kinds)
+});
+
 fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
-    for token in tokens(content)? {
+    let tokens = tokens(content).map_err(|err| SyntaxError {
+        line: err.position().line() as u32,
+        message: err.to_string(),
+    })?;
+    for token in tokens {
+        let spelling = token.to_string();
         let kind = match token.token_kind() {
             Eof => continue,
-            Identifier => 0,
+            Identifier => VOCABULARY.id("<identifier>"),
             MultiLineComment => continue,
-            Number => 1,
-            Shebang => 2,
+            Number => VOCABULARY.id("<number>"),
+            Shebang => VOCABULARY.id("<shebang>"),
             SingleLineComment => continue,
-            StringLiteral => 3,
-            Symbol => 4,
+            StringLiteral => VOCABULARY.id("<string>"),
+            Symbol => VOCABULARY
+                .get(&spelling)
+                .unwrap_or_else(|| VOCABULARY.id("<symbol>")),
             Whitespace => continue,
-            _ => todo!(),
+            // kinds of other dialects, e.g. interpolated strings of Luau
+            _ => VOCABULARY.id("<string>"),
         };
         res.push(Token {
             kind,
-            spelling: token.to_string(),
+            spelling,
+            file: 0,
             line: token.start_position().line() as u32,
             column: token.start_position().character() as u32,
+            end_line: token.end_position().line() as u32,
+            end_column: token.end_position().character() as u32,
+            span: 0..0,
         });
     }
     Ok(res)
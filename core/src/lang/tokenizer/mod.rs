@@ -1,14 +1,14 @@
-#[cfg(feature="cpp")]
+#[cfg(feature = "cpp_lexer")]
 pub mod cpp;
-#[cfg(feature="javascript")]
+#[cfg(feature = "javascript")]
 pub mod javascript;
-#[cfg(feature="lua")]
+#[cfg(feature = "lua")]
 pub mod lua;
-#[cfg(feature="python")]
+#[cfg(feature = "python")]
 pub mod python;
-#[cfg(feature="rust")]
+#[cfg(feature = "rust")]
 pub mod rust;
-#[cfg(feature="sql")]
+#[cfg(feature = "sql")]
 pub mod sql;
-#[cfg(feature="verilog")]
+#[cfg(feature = "verilog")]
 pub mod verilog;
@@ -1,6 +1,6 @@
-use crate::lang::Tokenize;
-use crate::token::Token;
-use anyhow::anyhow;
+use crate::lang::{SyntaxError, Tokenize};
+use crate::token::{Token, Vocabulary};
+use once_cell::sync::Lazy;
 use rustpython_parser::lexer::lex;
 use rustpython_parser::source_code::LineIndex;
 use rustpython_parser::Mode;
@@ -8,10 +8,115 @@ use rustpython_parser::Tok::*;
 
 pub struct Python;
 
+// kinds in the order of rustpython_parser::Tok, skipped tokens excluded
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    Vocabulary::new(vec![
+        "<name>",
+        "<int>",
+        "<float>",
+        "<complex>",
+        "<string>",
+        "<start module>",
+        "<start interactive>",
+        "<start expression>",
+        "<end of file>",
+        "(",
+        ")",
+        "[",
+        "]",
+        ":",
+        ",",
+        ";",
+        "+",
+        "-",
+        "*",
+        "/",
+        "|",
+        "&",
+        "<",
+        ">",
+        "=",
+        ".",
+        "%",
+        "{",
+        "}",
+        "==",
+        "!=",
+        "<=",
+        ">=",
+        "~",
+        "^",
+        "<<",
+        ">>",
+        "**",
+        "**=",
+        "+=",
+        "-=",
+        "*=",
+        "/=",
+        "%=",
+        "&=",
+        "|=",
+        "^=",
+        "<<=",
+        ">>=",
+        "//",
+        "//=",
+        ":=",
+        "@",
+        "@=",
+        "->",
+        "...",
+        "False",
+        "None",
+        "True",
+        "and",
+        "as",
+        "assert",
+        "async",
+        "await",
+        "break",
+        "class",
+        "continue",
+        "def",
+        "del",
+        "elif",
+        "else",
+        "except",
+        "finally",
+        "for",
+        "from",
+        "global",
+        "if",
+        "import",
+        "in",
+        "is",
+        "lambda",
+        "nonlocal",
+        "not",
+        "or",
+        "pass",
+        "raise",
+        "return",
+        "try",
+        "while",
+        "with",
+        "yield",
+        "match",
+        "type",
+        "case",
+        "<non-logical newline>",
+    ])
+});
+
 impl Tokenize for Python {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
 }
 
 #[warn(non_snake_case)]
@@ -20,125 +125,131 @@ pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
     let line_index = LineIndex::from_source_text(content);
     for item in tokens {
-        let (token, range) = item.map_err(|err| anyhow!("{} at {:?}", err.error, err.location))?;
-        let kind = match &token {
-            Name { name: _ } => 0,
-            Int { value: _ } => 1,
-            Float { value: _ } => 2,
-            Complex { real: _, imag: _ } => 3,
+        let (token, range) = item.map_err(|err| SyntaxError {
+            line: line_index.source_location(err.location, content).row.get(),
+            message: err.error.to_string(),
+        })?;
+        let kind = VOCABULARY.id(match &token {
+            Name { name: _ } => "<name>",
+            Int { value: _ } => "<int>",
+            Float { value: _ } => "<float>",
+            Complex { real: _, imag: _ } => "<complex>",
             String {
                 value: _,
                 kind: _,
                 triple_quoted: _,
-            } => 4,
+            } => "<string>",
             // skip newline or comments
             Comment(_) => continue,
-            Newline => continue, // 6
+            Newline => continue,
             // skip indentation
             Indent => continue,
             Dedent => continue,
-            // Indent => 7,
-            // Dedent => 8,
-            StartModule => 9,
-            StartInteractive => 10,
-            StartExpression => 11,
-            EndOfFile => 12,
-            Lpar => 13,
-            Rpar => 14,
-            Lsqb => 15,
-            Rsqb => 16,
-            Colon => 17,
-            Comma => 18,
-            Semi => 19,
-            Plus => 20,
-            Minus => 21,
-            Star => 22,
-            Slash => 23,
-            Vbar => 24,  // '|'
-            Amper => 25, // '&'
-            Less => 26,
-            Greater => 27,
-            Equal => 28,
-            Dot => 29,
-            Percent => 30,
-            Lbrace => 31,
-            Rbrace => 32,
-            EqEqual => 33,
-            NotEqual => 34,
-            LessEqual => 35,
-            GreaterEqual => 36,
-            Tilde => 37,
-            CircumFlex => 38,
-            LeftShift => 39,
-            RightShift => 40,
-            DoubleStar => 41,
-            DoubleStarEqual => 42, // '**='
-            PlusEqual => 43,
-            MinusEqual => 44,
-            StarEqual => 45,
-            SlashEqual => 46,
-            PercentEqual => 47,
-            AmperEqual => 48, // '&='
-            VbarEqual => 49,
-            CircumflexEqual => 50, // '^='
-            LeftShiftEqual => 51,
-            RightShiftEqual => 52,
-            DoubleSlash => 53, // '//'
-            DoubleSlashEqual => 54,
-            ColonEqual => 55,
-            At => 56,
-            AtEqual => 57,
-            Rarrow => 58,
-            Ellipsis => 59,
+            StartModule => "<start module>",
+            StartInteractive => "<start interactive>",
+            StartExpression => "<start expression>",
+            EndOfFile => "<end of file>",
+            Lpar => "(",
+            Rpar => ")",
+            Lsqb => "[",
+            Rsqb => "]",
+            Colon => ":",
+            Comma => ",",
+            Semi => ";",
+            Plus => "+",
+            Minus => "-",
+            Star => "*",
+            Slash => "/",
+            Vbar => "|",
+            Amper => "&",
+            Less => "<",
+            Greater => ">",
+            Equal => "=",
+            Dot => ".",
+            Percent => "%",
+            Lbrace => "{",
+            Rbrace => "}",
+            EqEqual => "==",
+            NotEqual => "!=",
+            LessEqual => "<=",
+            GreaterEqual => ">=",
+            Tilde => "~",
+            CircumFlex => "^",
+            LeftShift => "<<",
+            RightShift => ">>",
+            DoubleStar => "**",
+            DoubleStarEqual => "**=",
+            PlusEqual => "+=",
+            MinusEqual => "-=",
+            StarEqual => "*=",
+            SlashEqual => "/=",
+            PercentEqual => "%=",
+            AmperEqual => "&=",
+            VbarEqual => "|=",
+            CircumflexEqual => "^=",
+            LeftShiftEqual => "<<=",
+            RightShiftEqual => ">>=",
+            DoubleSlash => "//",
+            DoubleSlashEqual => "//=",
+            ColonEqual => ":=",
+            At => "@",
+            AtEqual => "@=",
+            Rarrow => "->",
+            Ellipsis => "...",
 
             // Keywords (alphabetically):
-            False => 60,
-            None => 61,
-            True => 62,
+            False => "False",
+            None => "None",
+            True => "True",
 
-            And => 63,
-            As => 64,
-            Assert => 65,
-            Async => 66,
-            Await => 67,
-            Break => 68,
-            Class => 69,
-            Continue => 70,
-            Def => 71,
-            Del => 72,
-            Elif => 73,
-            Else => 74,
-            Except => 75,
-            Finally => 76,
-            For => 77,
-            From => 78,
-            Global => 79,
-            If => 80,
-            Import => 81,
-            In => 82,
-            Is => 83,
-            Lambda => 84,
-            Nonlocal => 85,
-            Not => 86,
-            Or => 87,
-            Pass => 88,
-            Raise => 89,
-            Return => 90,
-            Try => 91,
-            While => 92,
-            With => 93,
-            Yield => 94,
-            Match => 95,
-            Type => 96,
-            Case => 97,
-            NonLogicalNewline => 98,
-        };
+            And => "and",
+            As => "as",
+            Assert => "assert",
+            Async => "async",
+            Await => "await",
+            Break => "break",
+            Class => "class",
+            Continue => "continue",
+            Def => "def",
+            Del => "del",
+            Elif => "elif",
+            Else => "else",
+            Except => "except",
+            Finally => "finally",
+            For => "for",
+            From => "from",
+            Global => "global",
+            If => "if",
+            Import => "import",
+            In => "in",
+            Is => "is",
+            Lambda => "lambda",
+            Nonlocal => "nonlocal",
+            Not => "not",
+            Or => "or",
+            Pass => "pass",
+            Raise => "raise",
+            Return => "return",
+            Try => "try",
+            While => "while",
+            With => "with",
+            Yield => "yield",
+            Match => "match",
+            Type => "type",
+            Case => "case",
+            NonLogicalNewline => "<non-logical newline>",
+        });
         let location = line_index.source_location(range.start(), content);
+        let end_location = line_index.source_location(range.end(), content);
         res.push(Token {
             kind,
             spelling: format!("{}", token),
+            file: 0,
             line: location.row.get(),
             column: location.column.get(),
+            end_line: end_location.row.get(),
+            end_column: end_location.column.get(),
+            span: 0..0,
         });
     }
     Ok(res)
@@ -146,7 +257,7 @@ pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
 
 #[cfg(test)]
 mod tests {
-    use super::tokenize_str;
+    use super::{tokenize_str, VOCABULARY};
 
     #[test]
     fn test_tokenize() {
@@ -171,4 +282,15 @@ mod tests {
         assert_eq!(tokens[13].line, 3);
         assert_eq!(tokens[13].column, 8);
     }
+
+    #[test]
+    fn test_kinds() {
+        let code = "if a:\n    pass";
+        let tokens = tokenize_str(code).unwrap();
+        let kinds: Vec<&str> = tokens
+            .iter()
+            .map(|t| VOCABULARY.name(t.kind).unwrap())
+            .collect();
+        assert_eq!(kinds[..4], ["if", "<name>", ":", "pass"]);
+    }
 }
@@ -1,6 +1,6 @@
-use crate::lang::Tokenize;
-use crate::token::Token;
-use anyhow::anyhow;
+use crate::lang::{SyntaxError, Tokenize};
+use crate::token::{Token, Vocabulary};
+use once_cell::sync::Lazy;
 use proc_macro2::{Delimiter, TokenStream, TokenTree};
 use std::str::FromStr;
 
@@ -10,22 +10,49 @@ impl Tokenize for Rust {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
 }
 
+// https://doc.rust-lang.org/reference/keywords.html
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "Self", "self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+// characters of proc_macro2::Punct, semicolon is skipped
+const PUNCTUATIONS: [&str; 20] = [
+    "=", "<", ">", "!", "~", "+", "-", "*", "/", "%", "^", "&", "|", "@", ".", ",", ":", "#", "$",
+    "?",
+];
+
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    let mut kinds = vec![
+        "(",
+        ")",
+        "{",
+        "}",
+        "[",
+        "]",
+        "<literal>",
+        "<identifier>",
+        "'",
+    ];
+    kinds.extend(KEYWORDS);
+    kinds.extend(PUNCTUATIONS);
+    Vocabulary::new(kinds)
+});
+
 fn flatten(token_stream: TokenStream) -> Vec<Token> {
     let mut res = vec![];
-    // https://doc.rust-lang.org/reference/keywords.html
-    let keywords = [
-        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
-        "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
-        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
-        "return", "Self", "self", "static", "struct", "super", "trait", "true", "try", "type",
-        "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
-    ];
     for tokens in token_stream {
         match tokens {
             TokenTree::Group(group) => {
-                // kind: [0, 2]
                 let spelling = match group.delimiter() {
                     Delimiter::Parenthesis => ("(", ")"),
                     Delimiter::Brace => ("{", "}"),
@@ -34,60 +61,72 @@ fn flatten(token_stream: TokenStream) -> Vec<Token> {
                 };
                 res.push(Token {
                     spelling: spelling.0.to_string(),
-                    kind: group.delimiter() as u8,
+                    kind: VOCABULARY.id(spelling.0),
+                    file: 0,
                     line: group.span_open().start().line as u32,
                     column: group.span_open().start().column as u32 + 1,
+                    end_line: group.span_open().end().line as u32,
+                    end_column: group.span_open().end().column as u32 + 1,
+                    span: 0..0,
                 });
                 res.extend(flatten(group.stream()));
                 res.push(Token {
                     spelling: spelling.1.to_string(),
-                    kind: group.delimiter() as u8,
+                    kind: VOCABULARY.id(spelling.1),
+                    file: 0,
                     line: group.span_close().start().line as u32,
                     column: group.span_close().start().column as u32 + 1,
+                    end_line: group.span_close().end().line as u32,
+                    end_column: group.span_close().end().column as u32 + 1,
+                    span: 0..0,
                 });
             }
             TokenTree::Literal(literal) => {
-                // kind: 3
                 res.push(Token {
                     spelling: format!("{}", literal),
-                    kind: 3,
+                    kind: VOCABULARY.id("<literal>"),
+                    file: 0,
                     line: literal.span().start().line as u32,
                     column: literal.span().start().column as u32 + 1,
+                    end_line: literal.span().end().line as u32,
+                    end_column: literal.span().end().column as u32 + 1,
+                    span: 0..0,
                 });
             }
             TokenTree::Ident(ident) => {
-                // kind: [4, 4+keywords.len()]
+                // keywords are kinds on their own
                 let spelling = format!("{}", ident);
-                if let Some(i) = keywords.iter().position(|s| s == &spelling) {
-                    res.push(Token {
-                        spelling: format!("{}", ident),
-                        kind: 5 + i as u8,
-                        line: ident.span().start().line as u32,
-                        column: ident.span().start().column as u32 + 1,
-                    });
-                } else {
-                    res.push(Token {
-                        spelling: format!("{}", ident),
-                        kind: 4,
-                        line: ident.span().start().line as u32,
-                        column: ident.span().start().column as u32 + 1,
-                    });
-                }
+                let kind = match VOCABULARY.get(&spelling) {
+                    Some(kind) => kind,
+                    None => VOCABULARY.id("<identifier>"),
+                };
+                res.push(Token {
+                    spelling,
+                    kind,
+                    file: 0,
+                    line: ident.span().start().line as u32,
+                    column: ident.span().start().column as u32 + 1,
+                    end_line: ident.span().end().line as u32,
+                    end_column: ident.span().end().column as u32 + 1,
+                    span: 0..0,
+                });
             }
             TokenTree::Punct(punct) => {
-                // kind: [5+keywords.len(), ...]
                 // skip semicolon
                 if punct.as_char() == ';' {
                     continue;
                 }
 
+                let spelling = format!("{}", punct);
                 res.push(Token {
-                    spelling: format!("{}", punct),
-                    kind: 5
-                        + keywords.len() as u8
-                        + (punct.as_char() as u8) % (251 - keywords.len() as u8),
+                    kind: VOCABULARY.id(&spelling),
+                    spelling,
+                    file: 0,
                     line: punct.span().start().line as u32,
                     column: punct.span().start().column as u32 + 1,
+                    end_line: punct.span().end().line as u32,
+                    end_column: punct.span().end().column as u32 + 1,
+                    span: 0..0,
                 });
             }
         }
@@ -96,14 +135,16 @@ fn flatten(token_stream: TokenStream) -> Vec<Token> {
 }
 
 pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
-    let token_stream = TokenStream::from_str(content)
-        .map_err(|err| anyhow!("Failed to parse source: {} {:?}", err, err,))?;
+    let token_stream = TokenStream::from_str(content).map_err(|err| SyntaxError {
+        line: err.span().start().line as u32,
+        message: format!("Failed to parse source: {}", err),
+    })?;
     Ok(flatten(token_stream))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::tokenize_str;
+    use super::{tokenize_str, VOCABULARY};
 
     #[test]
     fn test_tokenize() {
@@ -119,6 +160,8 @@ mod tests {
         assert_eq!(tokens[1].spelling, "main");
         assert_eq!(tokens[1].line, 1);
         assert_eq!(tokens[1].column, 4);
+        assert_eq!(tokens[1].end_line, 1);
+        assert_eq!(tokens[1].end_column, 8);
 
         assert_eq!(tokens[2].spelling, "(");
         assert_eq!(tokens[2].line, 1);
@@ -153,4 +196,29 @@ mod tests {
         assert_eq!(tokens[0].line, 2);
         assert_eq!(tokens[0].column, 1);
     }
+
+    #[test]
+    fn test_kinds() {
+        let code = "fn main() { let a = b; }";
+        let tokens = tokenize_str(code).unwrap();
+        let kinds: Vec<&str> = tokens
+            .iter()
+            .map(|t| VOCABULARY.name(t.kind).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "fn",
+                "<identifier>",
+                "(",
+                ")",
+                "{",
+                "let",
+                "<identifier>",
+                "=",
+                "<identifier>",
+                "}"
+            ]
+        );
+    }
 }
@@ -1,95 +1,208 @@
-use crate::lang::Tokenize;
-use crate::token::Token;
-use sqlparser::{dialect::GenericDialect, tokenizer::Token::*, tokenizer::Tokenizer};
+use crate::lang::{SyntaxError, Tokenize};
+use crate::token::{Token, Vocabulary};
+use once_cell::sync::Lazy;
+use sqlparser::{
+    dialect::GenericDialect,
+    keywords::{Keyword, ALL_KEYWORDS},
+    tokenizer::Token::*,
+    tokenizer::Tokenizer,
+};
 
 pub struct SQL;
 
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    let mut kinds = vec![
+        "<word>",
+        "<number>",
+        "<char>",
+        "<single quoted string>",
+        "<double quoted string>",
+        "<dollar quoted string>",
+        "<single quoted byte string>",
+        "<double quoted byte string>",
+        "<raw string>",
+        "<national string>",
+        "<escaped string>",
+        "<hex string>",
+        ",",
+        "==",
+        "=",
+        "<>",
+        "<",
+        ">",
+        "<=",
+        ">=",
+        "<=>",
+        "+",
+        "-",
+        "*",
+        "/",
+        "//",
+        "%",
+        "||",
+        "(",
+        ")",
+        ".",
+        ":",
+        "::",
+        ":=",
+        ";",
+        "\\",
+        "[",
+        "]",
+        "&",
+        "|",
+        "^",
+        "{",
+        "}",
+        "=>",
+        "#",
+        "~",
+        "~*",
+        "!~",
+        "!~*",
+        "<<",
+        ">>",
+        "&&",
+        "!",
+        "!!",
+        "@",
+        "|/",
+        "||/",
+        "<placeholder>",
+        "->",
+        "->>",
+        "#>",
+        "#>>",
+        "@>",
+        "<@",
+        "#-",
+        "@?",
+        "@@",
+        "<keyword>",
+    ];
+    // keywords come last, their ids change when sqlparser adds keywords,
+    // which requires bumping FINGERPRINT_VERSION
+    kinds.extend(ALL_KEYWORDS);
+    Vocabulary::new(kinds)
+});
+
 impl Tokenize for SQL {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
 }
 
 pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let dialect = GenericDialect {};
     let mut res = vec![];
-    for token in Tokenizer::new(&dialect, content).tokenize_with_location()? {
-        let kind = match token.token {
+    let tokens = Tokenizer::new(&dialect, content)
+        .tokenize_with_location()
+        .map_err(|err| SyntaxError {
+            line: err.line as u32,
+            message: err.message,
+        })?;
+    for token in tokens {
+        let kind = match &token.token {
             EOF => continue,
-            Word(_) => 1,
-            Number(_, _) => 2,
-            Char(_) => 3,
-            SingleQuotedString(_) => 4,
-            DoubleQuotedString(_) => 5,
-            DollarQuotedString(_) => 6,
-            SingleQuotedByteStringLiteral(_) => 7,
-            DoubleQuotedByteStringLiteral(_) => 8,
-            RawStringLiteral(_) => 9,
-            NationalStringLiteral(_) => 10,
-            EscapedStringLiteral(_) => 11,
-            HexStringLiteral(_) => 12,
-            Comma => 13,
+            Word(word) => match word.keyword {
+                Keyword::NoKeyword => VOCABULARY.id("<word>"),
+                _ => VOCABULARY
+                    .get(&word.value.to_uppercase())
+                    .unwrap_or_else(|| VOCABULARY.id("<keyword>")),
+            },
+            Number(_, _) => VOCABULARY.id("<number>"),
+            Char(_) => VOCABULARY.id("<char>"),
+            SingleQuotedString(_) => VOCABULARY.id("<single quoted string>"),
+            DoubleQuotedString(_) => VOCABULARY.id("<double quoted string>"),
+            DollarQuotedString(_) => VOCABULARY.id("<dollar quoted string>"),
+            SingleQuotedByteStringLiteral(_) => VOCABULARY.id("<single quoted byte string>"),
+            DoubleQuotedByteStringLiteral(_) => VOCABULARY.id("<double quoted byte string>"),
+            RawStringLiteral(_) => VOCABULARY.id("<raw string>"),
+            NationalStringLiteral(_) => VOCABULARY.id("<national string>"),
+            EscapedStringLiteral(_) => VOCABULARY.id("<escaped string>"),
+            HexStringLiteral(_) => VOCABULARY.id("<hex string>"),
+            Comma => VOCABULARY.id(","),
             Whitespace(_) => continue,
-            DoubleEq => 15,
-            Eq => 16,
-            Neq => 17,
-            Lt => 18,
-            Gt => 19,
-            LtEq => 20,
-            GtEq => 21,
-            Spaceship => 22,
-            Plus => 23,
-            Minus => 24,
-            Mul => 25,
-            Div => 26,
-            DuckIntDiv => 27,
-            Mod => 28,
-            StringConcat => 29,
-            LParen => 30,
-            RParen => 31,
-            Period => 32,
-            Colon => 33,
-            DoubleColon => 34,
-            DuckAssignment => 35,
-            SemiColon => 36,
-            Backslash => 37,
-            LBracket => 38,
-            RBracket => 39,
-            Ampersand => 40,
-            Pipe => 41,
-            Caret => 42,
-            LBrace => 43,
-            RBrace => 44,
-            RArrow => 45,
-            Sharp => 46,
-            Tilde => 47,
-            TildeAsterisk => 48,
-            ExclamationMarkTilde => 49,
-            ExclamationMarkTildeAsterisk => 50,
-            ShiftLeft => 51,
-            ShiftRight => 52,
-            Overlap => 53,
-            ExclamationMark => 54,
-            DoubleExclamationMark => 55,
-            AtSign => 56,
-            PGSquareRoot => 57,
-            PGCubeRoot => 58,
-            Placeholder(_) => 59,
-            Arrow => 60,
-            LongArrow => 61,
-            HashArrow => 62,
-            HashLongArrow => 63,
-            AtArrow => 64,
-            ArrowAt => 65,
-            HashMinus => 66,
-            AtQuestion => 67,
-            AtAt => 68,
+            DoubleEq => VOCABULARY.id("=="),
+            Eq => VOCABULARY.id("="),
+            Neq => VOCABULARY.id("<>"),
+            Lt => VOCABULARY.id("<"),
+            Gt => VOCABULARY.id(">"),
+            LtEq => VOCABULARY.id("<="),
+            GtEq => VOCABULARY.id(">="),
+            Spaceship => VOCABULARY.id("<=>"),
+            Plus => VOCABULARY.id("+"),
+            Minus => VOCABULARY.id("-"),
+            Mul => VOCABULARY.id("*"),
+            Div => VOCABULARY.id("/"),
+            DuckIntDiv => VOCABULARY.id("//"),
+            Mod => VOCABULARY.id("%"),
+            StringConcat => VOCABULARY.id("||"),
+            LParen => VOCABULARY.id("("),
+            RParen => VOCABULARY.id(")"),
+            Period => VOCABULARY.id("."),
+            Colon => VOCABULARY.id(":"),
+            DoubleColon => VOCABULARY.id("::"),
+            DuckAssignment => VOCABULARY.id(":="),
+            SemiColon => VOCABULARY.id(";"),
+            Backslash => VOCABULARY.id("\\"),
+            LBracket => VOCABULARY.id("["),
+            RBracket => VOCABULARY.id("]"),
+            Ampersand => VOCABULARY.id("&"),
+            Pipe => VOCABULARY.id("|"),
+            Caret => VOCABULARY.id("^"),
+            LBrace => VOCABULARY.id("{"),
+            RBrace => VOCABULARY.id("}"),
+            RArrow => VOCABULARY.id("=>"),
+            Sharp => VOCABULARY.id("#"),
+            Tilde => VOCABULARY.id("~"),
+            TildeAsterisk => VOCABULARY.id("~*"),
+            ExclamationMarkTilde => VOCABULARY.id("!~"),
+            ExclamationMarkTildeAsterisk => VOCABULARY.id("!~*"),
+            ShiftLeft => VOCABULARY.id("<<"),
+            ShiftRight => VOCABULARY.id(">>"),
+            Overlap => VOCABULARY.id("&&"),
+            ExclamationMark => VOCABULARY.id("!"),
+            DoubleExclamationMark => VOCABULARY.id("!!"),
+            AtSign => VOCABULARY.id("@"),
+            PGSquareRoot => VOCABULARY.id("|/"),
+            PGCubeRoot => VOCABULARY.id("||/"),
+            Placeholder(_) => VOCABULARY.id("<placeholder>"),
+            Arrow => VOCABULARY.id("->"),
+            LongArrow => VOCABULARY.id("->>"),
+            HashArrow => VOCABULARY.id("#>"),
+            HashLongArrow => VOCABULARY.id("#>>"),
+            AtArrow => VOCABULARY.id("@>"),
+            ArrowAt => VOCABULARY.id("<@"),
+            HashMinus => VOCABULARY.id("#-"),
+            AtQuestion => VOCABULARY.id("@?"),
+            AtAt => VOCABULARY.id("@@"),
+        };
+        // sqlparser only records start locations, derive end from spelling
+        let spelling = token.to_string();
+        let line = token.location.line as u32;
+        let column = token.location.column as u32;
+        let (end_line, end_column) = match spelling.rfind('\n') {
+            Some(i) => (
+                line + spelling.matches('\n').count() as u32,
+                spelling[i + 1..].chars().count() as u32 + 1,
+            ),
+            None => (line, column + spelling.chars().count() as u32),
         };
         res.push(Token {
             kind,
-            spelling: token.to_string(),
-            line: token.location.line as u32,
-            column: token.location.column as u32,
+            spelling,
+            file: 0,
+            line,
+            column,
+            end_line,
+            end_column,
+            span: 0..0,
         });
     }
     Ok(res)
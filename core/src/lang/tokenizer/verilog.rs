@@ -1,5 +1,6 @@
 use crate::lang::Tokenize;
-use crate::token::Token;
+use crate::token::{Token, Vocabulary};
+use once_cell::sync::Lazy;
 use verilog_lang::lexer::Lexer;
 
 pub struct Verilog;
@@ -8,6 +9,175 @@ impl Tokenize for Verilog {
     fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
         tokenize_str(content)
     }
+
+    fn vocabulary(&self) -> &'static Vocabulary {
+        &VOCABULARY
+    }
+}
+
+// IEEE 1364-2005 keywords
+const KEYWORDS: [&str; 124] = [
+    "always",
+    "and",
+    "assign",
+    "automatic",
+    "begin",
+    "buf",
+    "bufif0",
+    "bufif1",
+    "case",
+    "casex",
+    "casez",
+    "cell",
+    "cmos",
+    "config",
+    "deassign",
+    "default",
+    "defparam",
+    "design",
+    "disable",
+    "edge",
+    "else",
+    "end",
+    "endcase",
+    "endconfig",
+    "endfunction",
+    "endgenerate",
+    "endmodule",
+    "endprimitive",
+    "endspecify",
+    "endtable",
+    "endtask",
+    "event",
+    "for",
+    "force",
+    "forever",
+    "fork",
+    "function",
+    "generate",
+    "genvar",
+    "highz0",
+    "highz1",
+    "if",
+    "ifnone",
+    "incdir",
+    "include",
+    "initial",
+    "inout",
+    "input",
+    "instance",
+    "integer",
+    "join",
+    "large",
+    "liblist",
+    "library",
+    "localparam",
+    "macromodule",
+    "medium",
+    "module",
+    "nand",
+    "negedge",
+    "nmos",
+    "nor",
+    "noshowcancelled",
+    "not",
+    "notif0",
+    "notif1",
+    "or",
+    "output",
+    "parameter",
+    "pmos",
+    "posedge",
+    "primitive",
+    "pull0",
+    "pull1",
+    "pulldown",
+    "pullup",
+    "pulsestyle_onevent",
+    "pulsestyle_ondetect",
+    "rcmos",
+    "real",
+    "realtime",
+    "reg",
+    "release",
+    "repeat",
+    "rnmos",
+    "rpmos",
+    "rtran",
+    "rtranif0",
+    "rtranif1",
+    "scalared",
+    "showcancelled",
+    "signed",
+    "small",
+    "specify",
+    "specparam",
+    "strong0",
+    "strong1",
+    "supply0",
+    "supply1",
+    "table",
+    "task",
+    "time",
+    "tran",
+    "tranif0",
+    "tranif1",
+    "tri",
+    "tri0",
+    "tri1",
+    "triand",
+    "trior",
+    "trireg",
+    "unsigned",
+    "use",
+    "uwire",
+    "vectored",
+    "wait",
+    "wand",
+    "weak0",
+    "weak1",
+    "while",
+    "wire",
+    "wor",
+    "xnor",
+    "xor",
+];
+
+// operators and punctuation
+const PUNCTUATIONS: [&str; 48] = [
+    "(", ")", "[", "]", "{", "}", ";", ",", ".", ":", "?", "#", "@", "=", "+", "-", "*", "/", "%",
+    "**", "!", "~", "&", "|", "^", "~&", "~|", "~^", "^~", "&&", "||", "==", "!=", "===", "!==",
+    "<", ">", "<=", ">=", "<<", ">>", "<<<", ">>>", "+:", "-:", "(*", "*)", "->",
+];
+
+static VOCABULARY: Lazy<Vocabulary> = Lazy::new(|| {
+    let mut kinds = vec![
+        "<identifier>",
+        "<system identifier>",
+        "<directive>",
+        "<number>",
+        "<string>",
+        "<other>",
+    ];
+    kinds.extend(KEYWORDS);
+    kinds.extend(PUNCTUATIONS);
+    Vocabulary::new(kinds)
+});
+
+/// Kind of token by spelling, independent of the lexer's token types
+fn kind_of(spelling: &str) -> u16 {
+    if let Some(kind) = VOCABULARY.get(spelling) {
+        return kind;
+    }
+    let kind = match spelling.chars().next() {
+        Some('$') => "<system identifier>",
+        Some('`') => "<directive>",
+        Some('"') => "<string>",
+        Some(c) if c.is_ascii_digit() || c == '\'' => "<number>",
+        Some(c) if c.is_alphabetic() || c == '_' || c == '\\' => "<identifier>",
+        _ => "<other>",
+    };
+    VOCABULARY.id(kind)
 }
 
 pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
@@ -15,10 +185,14 @@ pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
     for token in lexer.tokens {
         res.push(Token {
-            kind: token.token as u8,
+            kind: kind_of(&token.text),
             spelling: token.text.to_string(),
+            file: 0,
             line: token.span.from.row as u32 + 1,
             column: token.span.from.col as u32 + 1,
+            end_line: token.span.to.row as u32 + 1,
+            end_column: token.span.to.col as u32 + 1,
+            span: 0..0,
         });
     }
     Ok(res)
@@ -26,7 +200,7 @@ pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
 
 #[cfg(test)]
 mod tests {
-    use super::tokenize_str;
+    use super::{kind_of, tokenize_str, VOCABULARY};
 
     #[test]
     fn test_tokenize() {
@@ -51,4 +225,23 @@ mod tests {
         assert_eq!(tokens[8].line, 2);
         assert_eq!(tokens[8].column, 1);
     }
+
+    #[test]
+    fn test_kinds() {
+        let kinds: Vec<&str> = ["module", "test_reg", "$display", "8'hff", "<=", "`define"]
+            .iter()
+            .map(|s| VOCABULARY.name(kind_of(s)).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "module",
+                "<identifier>",
+                "<system identifier>",
+                "<number>",
+                "<=",
+                "<directive>"
+            ]
+        );
+    }
 }
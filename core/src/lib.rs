@@ -1,4 +1,9 @@
 pub mod common;
+pub mod config;
+pub mod evidence;
+#[cfg(all(test, feature = "rust"))]
+mod fixtures;
 pub mod lang;
 pub mod matching;
+pub mod pipeline;
 pub mod token;
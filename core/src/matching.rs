@@ -1,132 +1,361 @@
-use crate::lang::Language;
+use crate::common::compact_kinds;
+use crate::config::DetectionConfig;
+use crate::lang::{identifier_kinds, normalize::normalize, Language};
 use crate::token::Token;
 
-use bitvec::bitvec;
 use log::*;
 use rkr_gst::Match;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
-pub fn compute_matches_from_token(
+/// Split matches so that each part lies in a single file on both sides,
+/// dropping parts shorter than `minimum_match_length`
+fn split_by_file(
+    matches: Vec<Match>,
     token_left: &[Token],
-    token_kind_left: &[u8],
-    lines_left: &[&str],
     token_right: &[Token],
-    token_kind_right: &[u8],
-    lines_right: &[&str],
-    template_kind: Option<&[u8]>,
+    minimum_match_length: usize,
 ) -> Vec<Match> {
-    let initial_search_length = 40;
-    let minimum_match_length = 20;
-    let mut matches = rkr_gst::run(
+    let mut res = vec![];
+    for m in matches {
+        let mut parts = vec![];
+        let mut from = 0;
+        for i in 1..=m.length {
+            if i == m.length
+                || token_left[m.pattern_index + i].file != token_left[m.pattern_index + i - 1].file
+                || token_right[m.text_index + i].file != token_right[m.text_index + i - 1].file
+            {
+                parts.push(Match {
+                    pattern_index: m.pattern_index + from,
+                    text_index: m.text_index + from,
+                    length: i - from,
+                });
+                from = i;
+            }
+        }
+        if parts.len() == 1 {
+            res.push(m);
+        } else {
+            res.extend(
+                parts
+                    .into_iter()
+                    .filter(|m| m.length >= minimum_match_length),
+            );
+        }
+    }
+    res
+}
+
+/// Compute matches of a token stream against the template
+///
+/// Depends on one side only, so it can be computed once per submission
+/// and shared by all pairs via `compute_matches_excluding`.
+pub fn compute_template_matches(
+    token_kind: &[u16],
+    template_kind: &[u16],
+    config: &DetectionConfig,
+) -> Vec<Match> {
+    let kinds = compact_kinds(&[token_kind, template_kind]);
+    rkr_gst::run(
+        &kinds[0],
+        &kinds[1],
+        config.initial_search_length,
+        config.minimum_match_length,
+    )
+}
+
+/// Token kinds of each file of the templates
+///
+/// Files are matched separately, so that no template match spans two files.
+pub fn template_kinds(templates: &[Vec<Token>]) -> Vec<Vec<u16>> {
+    templates
+        .iter()
+        .flat_map(|tokens| tokens.chunk_by(|a, b| a.file == b.file))
+        .map(|file| file.iter().map(|t| t.kind).collect())
+        .collect()
+}
+
+/// Compute matches between two token streams, excluding parts matching any template
+///
+/// Tokens may come from multiple files, each match lies in one file on each side.
+pub fn compute_matches_from_token(
+    token_left: &[Token],
+    token_kind_left: &[u16],
+    token_right: &[Token],
+    token_kind_right: &[u16],
+    template_kinds: &[Vec<u16>],
+    config: &DetectionConfig,
+) -> Vec<TokenMatch> {
+    let template_matches = |token_kind: &[u16]| -> Vec<Match> {
+        template_kinds
+            .iter()
+            .flat_map(|template_kind| compute_template_matches(token_kind, template_kind, config))
+            .collect()
+    };
+    let left_template_matches = template_matches(token_kind_left);
+    let right_template_matches = template_matches(token_kind_right);
+    compute_matches_excluding(
+        token_left,
         token_kind_left,
+        token_right,
         token_kind_right,
-        initial_search_length,
+        &left_template_matches,
+        &right_template_matches,
+        config,
+    )
+}
+
+/// Compute matches between two token streams, excluding parts covered by
+/// matches of each side against the template from `compute_template_matches`
+pub fn compute_matches_excluding(
+    token_left: &[Token],
+    token_kind_left: &[u16],
+    token_right: &[Token],
+    token_kind_right: &[u16],
+    left_template_matches: &[Match],
+    right_template_matches: &[Match],
+    config: &DetectionConfig,
+) -> Vec<TokenMatch> {
+    // shorter matches may still add up to a long one when merged across gaps
+    let minimum_match_length = if config.max_gap > 0 {
+        (config.minimum_match_length / 2).max(1)
+    } else {
+        config.minimum_match_length
+    };
+    let kinds = compact_kinds(&[token_kind_left, token_kind_right]);
+    let mut matches = rkr_gst::run(
+        &kinds[0],
+        &kinds[1],
+        config.initial_search_length,
         minimum_match_length,
     );
 
-    if let Some(token_kind_template) = template_kind {
-        let left_template_matches = rkr_gst::run(
-            token_kind_left,
-            token_kind_template,
-            initial_search_length,
-            minimum_match_length,
-        );
-        let right_template_matches = rkr_gst::run(
-            token_kind_right,
-            token_kind_template,
-            initial_search_length,
-            minimum_match_length,
-        );
+    // filter covered parts
+    let mut filter = |template_matches: &[rkr_gst::Match], left: bool| {
+        for mmm in template_matches {
+            let mmm_from = mmm.pattern_index;
+            let mmm_to = mmm.pattern_index + mmm.length - 1;
+
+            let mut new_matches = vec![];
+            for m in matches.iter() {
+                let mut m = *m;
+                let m_from = if left { m.pattern_index } else { m.text_index };
+                let m_to = m_from + m.length - 1;
+
+                if mmm_from <= m_from && m_to <= mmm_to {
+                    // fully covered
+                    continue;
+                } else if mmm_from <= m_from && m_from <= mmm_to {
+                    // move head
+                    let diff = mmm_to - m_from + 1;
+                    m.pattern_index += diff;
+                    m.text_index += diff;
+                    m.length -= diff;
+                    new_matches.push(m);
+                } else if mmm_from <= m_to && m_to <= mmm_to {
+                    // move tail
+                    let diff = m_to - mmm_from + 1;
+                    m.length -= diff;
+                    new_matches.push(m);
+                } else if m_from <= mmm_from && mmm_to <= m_to {
+                    // split into two
+                    new_matches.push(rkr_gst::Match {
+                        pattern_index: m.pattern_index,
+                        text_index: m.text_index,
+                        length: mmm_from - m_from,
+                    });
 
-        // filter covered parts
-        let mut filter = |template_matches: &Vec<rkr_gst::Match>, left: bool| {
-            for mmm in template_matches {
-                let mmm_from = mmm.pattern_index;
-                let mmm_to = mmm.pattern_index + mmm.length - 1;
-
-                let mut new_matches = vec![];
-                for m in matches.iter() {
-                    let mut m = *m;
-                    let m_from = if left { m.pattern_index } else { m.text_index };
-                    let m_to = m_from + m.length - 1;
-
-                    if mmm_from <= m_from && m_to <= mmm_to {
-                        // fully covered
-                        continue;
-                    } else if mmm_from <= m_from && m_from <= mmm_to {
-                        // move head
-                        let diff = mmm_to - m_from + 1;
-                        m.pattern_index += diff;
-                        m.text_index += diff;
-                        m.length -= diff;
-                        new_matches.push(m);
-                    } else if mmm_from <= m_to && m_to <= mmm_to {
-                        // move tail
-                        let diff = m_to - mmm_from + 1;
-                        m.length -= diff;
-                        new_matches.push(m);
-                    } else if m_from <= mmm_from && mmm_to <= m_to {
-                        // split into two
-                        new_matches.push(rkr_gst::Match {
-                            pattern_index: m.pattern_index,
-                            text_index: m.text_index,
-                            length: mmm_from - m_from,
-                        });
-
-                        let diff = mmm_to - m_from + 1;
-                        new_matches.push(rkr_gst::Match {
-                            pattern_index: m.pattern_index + diff,
-                            text_index: m.text_index + diff,
-                            length: m.length - diff,
-                        });
-                    } else {
-                        // unchanged
-                        new_matches.push(m);
-                    }
+                    let diff = mmm_to - m_from + 1;
+                    new_matches.push(rkr_gst::Match {
+                        pattern_index: m.pattern_index + diff,
+                        text_index: m.text_index + diff,
+                        length: m.length - diff,
+                    });
+                } else {
+                    // unchanged
+                    new_matches.push(m);
                 }
-                matches = new_matches;
             }
-        };
-        filter(&left_template_matches, true);
-        filter(&right_template_matches, false);
+            matches = new_matches;
+        }
+    };
+    filter(left_template_matches, true);
+    filter(right_template_matches, false);
+
+    // matches crossing file boundaries are split
+    let matches = split_by_file(matches, token_left, token_right, minimum_match_length);
+    merge_matches(matches, token_left, token_right, config)
+}
+
+/// Matched tokens of both sides, merged from matches separated by small gaps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenMatch {
+    // token indices
+    pub left: Range<usize>,
+    pub right: Range<usize>,
+    // number of unmatched tokens within the ranges
+    pub left_gap: usize,
+    pub right_gap: usize,
+    // aligned parts as (left start, right start, length)
+    pub parts: Vec<(usize, usize, usize)>,
+}
+
+/// Merge matches separated by at most `max_gap` unmatched tokens on both sides,
+/// e.g. by dummy statements inserted into a copy
+///
+/// Matches are merged in the same order on both sides and within the same files.
+/// Merged matches of less than `minimum_match_length` matched tokens are dropped,
+/// longest first in the result.
+pub fn merge_matches(
+    mut matches: Vec<Match>,
+    token_left: &[Token],
+    token_right: &[Token],
+    config: &DetectionConfig,
+) -> Vec<TokenMatch> {
+    let single = |m: &Match| TokenMatch {
+        left: m.pattern_index..m.pattern_index + m.length,
+        right: m.text_index..m.text_index + m.length,
+        left_gap: 0,
+        right_gap: 0,
+        parts: vec![(m.pattern_index, m.text_index, m.length)],
+    };
+    if config.max_gap == 0 {
+        return matches.iter().map(single).collect();
     }
 
-    // ensure matches are on distinct lines
-    let mut bitvec_left = bitvec![0; lines_left.len()];
-    let mut bitvec_right = bitvec![0; lines_right.len()];
-    let mut i = 0;
-    while i < matches.len() {
-        let m = matches[i];
-        let line_from_left = token_left[m.pattern_index].line as usize - 1;
-        let line_to_left = token_left[m.pattern_index + m.length - 1].line as usize - 1;
-        let line_from_right = token_right[m.text_index].line as usize - 1;
-        let line_to_right = token_right[m.text_index + m.length - 1].line as usize - 1;
-        if !bitvec_left[line_from_left]
-            && !bitvec_left[line_to_left]
-            && !bitvec_right[line_from_right]
-            && !bitvec_right[line_to_right]
-        {
-            // safe
-            for i in line_from_left..=line_to_left {
-                bitvec_left.set(i, true);
-            }
-            for i in line_from_right..=line_to_right {
-                bitvec_right.set(i, true);
+    matches.sort_by_key(|m| (m.pattern_index, m.text_index));
+    // merged matches with their number of matched tokens
+    let mut merged: Vec<(TokenMatch, usize)> = vec![];
+    for m in &matches {
+        let next = single(m);
+        let gap =
+            |from: usize, to: usize| to.checked_sub(from).filter(|gap| *gap <= config.max_gap);
+        let prev = merged.iter_mut().rev().find(|(prev, _)| {
+            gap(prev.left.end, next.left.start).is_some()
+                && gap(prev.right.end, next.right.start).is_some()
+                && token_left[prev.left.start].file == token_left[next.left.start].file
+                && token_right[prev.right.start].file == token_right[next.right.start].file
+        });
+        match prev {
+            Some((prev, matched)) => {
+                prev.left_gap += next.left.start - prev.left.end;
+                prev.right_gap += next.right.start - prev.right.end;
+                prev.left.end = next.left.end;
+                prev.right.end = next.right.end;
+                prev.parts.extend(next.parts);
+                *matched += m.length;
             }
-            i += 1;
-        } else {
-            matches.remove(i);
+            None => merged.push((next, m.length)),
         }
     }
-
-    matches
+    merged.retain(|(_, matched)| *matched >= config.minimum_match_length);
+    merged.sort_by_key(|(_, matched)| Reverse(*matched));
+    merged.into_iter().map(|(m, _)| m).collect()
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Block {
+    // index of file in submission
+    pub left_file: usize,
+    pub right_file: usize,
     // 0-based
     pub left_line_from: usize,
     pub left_line_to: usize,
     pub right_line_from: usize,
     pub right_line_to: usize,
+    // 0-based character columns, `from` on the first line and `to` (exclusive) on the last line
+    pub left_column_from: usize,
+    pub left_column_to: usize,
+    pub right_column_from: usize,
+    pub right_column_to: usize,
+    // number of unmatched tokens within the block, skipped when merging matches
+    pub left_gap: usize,
+    pub right_gap: usize,
+    // identifiers renamed between the sides, most used first
+    pub renames: Vec<Rename>,
+    // percentage of aligned identifiers following the one-to-one mapping, renamed or not
+    pub rename_consistency: usize,
+}
+
+/// Identifier of the left side consistently spelled differently on the right side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Rename {
+    pub left: String,
+    pub right: String,
+    // number of aligned occurrences
+    pub count: usize,
+}
+
+/// Align spellings of identifiers in the parts of a match into a one-to-one mapping
+///
+/// Spellings paired most often are mapped first, pairs conflicting with the mapping
+/// so far are left out. Returns the mapped pairs of different spellings and the
+/// percentage of aligned identifiers following the mapping.
+pub fn rename_mapping(
+    token_left: &[Token],
+    token_right: &[Token],
+    m: &TokenMatch,
+    identifiers: &[u16],
+) -> (Vec<Rename>, usize) {
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+    let mut total = 0;
+    for (left, right, length) in &m.parts {
+        for (l, r) in token_left[*left..left + length]
+            .iter()
+            .zip(&token_right[*right..right + length])
+        {
+            if identifiers.contains(&l.kind) && l.kind == r.kind {
+                *counts.entry((&l.spelling, &r.spelling)).or_default() += 1;
+                total += 1;
+            }
+        }
+    }
+    if total == 0 {
+        return (vec![], 100);
+    }
+
+    let mut counts: Vec<((&str, &str), usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut mapped_left = HashSet::new();
+    let mut mapped_right = HashSet::new();
+    let mut consistent = 0;
+    let mut renames = vec![];
+    for ((left, right), count) in counts {
+        if mapped_left.contains(left) || mapped_right.contains(right) {
+            continue;
+        }
+        mapped_left.insert(left);
+        mapped_right.insert(right);
+        consistent += count;
+        if left != right {
+            renames.push(Rename {
+                left: left.to_string(),
+                right: right.to_string(),
+                count,
+            });
+        }
+    }
+    (renames, consistent * 100 / total)
+}
+
+/// Number of distinct lines covered by blocks on one side
+///
+/// Blocks may share lines, which are only counted once.
+pub fn matched_lines(blocks: &[Block], is_left: bool) -> usize {
+    let mut lines = HashSet::new();
+    for block in blocks {
+        let (file, from, to) = if is_left {
+            (block.left_file, block.left_line_from, block.left_line_to)
+        } else {
+            (block.right_file, block.right_line_from, block.right_line_to)
+        };
+        lines.extend((from..=to).map(|line| (file, line)));
+    }
+    lines.len()
 }
 
 /// Compute matching blocks via RKR-GST algorithm
@@ -134,49 +363,200 @@ pub fn compute_matching_blocks_from_text(
     left: &str,
     right: &str,
     language: Language,
-    template: &Option<String>,
+    templates: &[String],
+    config: &DetectionConfig,
 ) -> anyhow::Result<Vec<Block>> {
-    let token_left = crate::lang::tokenize_str(left, language)?;
-    let token_kind_left: Vec<u8> = token_left.iter().map(|t| t.kind).collect();
-    let lines_left: Vec<&str> = left.lines().collect();
+    let tokenize = |code: &str| -> anyhow::Result<Vec<Token>> {
+        let mut tokens = crate::lang::tokenize_str(code, language)?;
+        normalize(&mut tokens, language, config);
+        Ok(tokens)
+    };
+    let token_left = tokenize(left)?;
+    let token_right = tokenize(right)?;
+    let identifiers = identifier_kinds(language);
+    let token_templates = templates
+        .iter()
+        .map(|t| tokenize(t))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    let token_right = crate::lang::tokenize_str(right, language).unwrap();
-    let token_kind_right: Vec<u8> = token_right.iter().map(|t| t.kind).collect();
-    let lines_right: Vec<&str> = right.lines().collect();
+    Ok(compute_matching_blocks_from_token(
+        &token_left,
+        &token_right,
+        &token_templates,
+        &identifiers,
+        config,
+    ))
+}
 
-    let template_kind: Option<Vec<u8>> = template.as_ref().map(|t| {
-        let token_template = crate::lang::tokenize_str(t, language).unwrap();
-        token_template.iter().map(|t| t.kind).collect()
-    });
+/// Compute matching blocks of tokenized submissions via RKR-GST algorithm
+///
+/// Renames are found among tokens of the `identifiers` kinds.
+pub fn compute_matching_blocks_from_token(
+    token_left: &[Token],
+    token_right: &[Token],
+    token_templates: &[Vec<Token>],
+    identifiers: &[u16],
+    config: &DetectionConfig,
+) -> Vec<Block> {
+    let token_kind_left: Vec<u16> = token_left.iter().map(|t| t.kind).collect();
+    let token_kind_right: Vec<u16> = token_right.iter().map(|t| t.kind).collect();
 
     let matches = compute_matches_from_token(
-        &token_left,
+        token_left,
         &token_kind_left,
-        &lines_left,
-        &token_right,
+        token_right,
         &token_kind_right,
-        &lines_right,
-        template_kind.as_deref(),
+        &template_kinds(token_templates),
+        config,
     );
+    blocks_from_matches(token_left, token_right, &matches, identifiers)
+}
 
+/// Convert matches of token indices to blocks of file positions
+pub fn blocks_from_matches(
+    token_left: &[Token],
+    token_right: &[Token],
+    matches: &[TokenMatch],
+    identifiers: &[u16],
+) -> Vec<Block> {
     let mut res = vec![];
 
     for (idx, m) in matches.iter().enumerate() {
-        let line_from_left = token_left[m.pattern_index].line as usize - 1;
-        let line_to_left = token_left[m.pattern_index + m.length - 1].line as usize - 1;
-        let line_from_right = token_right[m.text_index].line as usize - 1;
-        let line_to_right = token_right[m.text_index + m.length - 1].line as usize - 1;
-
-        res.push(Block {
-            left_line_from: line_from_left,
-            left_line_to: line_to_left,
-            right_line_from: line_from_right,
-            right_line_to: line_to_right,
-        });
+        let first_left = &token_left[m.left.start];
+        let last_left = &token_left[m.left.end - 1];
+        let first_right = &token_right[m.right.start];
+        let last_right = &token_right[m.right.end - 1];
+        let (renames, rename_consistency) = rename_mapping(token_left, token_right, m, identifiers);
+
+        let block = Block {
+            left_file: first_left.file as usize,
+            right_file: first_right.file as usize,
+            left_line_from: first_left.line as usize - 1,
+            left_line_to: last_left.end_line as usize - 1,
+            right_line_from: first_right.line as usize - 1,
+            right_line_to: last_right.end_line as usize - 1,
+            left_column_from: first_left.column as usize - 1,
+            left_column_to: last_left.end_column as usize - 1,
+            right_column_from: first_right.column as usize - 1,
+            right_column_to: last_right.end_column as usize - 1,
+            left_gap: m.left_gap,
+            right_gap: m.right_gap,
+            renames,
+            rename_consistency,
+        };
 
         debug!("Match #{}:", idx + 1);
-        debug!("Left L{}-L{}", line_from_left, line_to_left);
-        debug!("Right L{}-L{}", line_from_right, line_to_right);
+        debug!(
+            "Left F{} L{}:{}-L{}:{}",
+            block.left_file,
+            block.left_line_from,
+            block.left_column_from,
+            block.left_line_to,
+            block.left_column_to
+        );
+        debug!(
+            "Right F{} L{}:{}-L{}:{}",
+            block.right_file,
+            block.right_line_from,
+            block.right_column_from,
+            block.right_line_to,
+            block.right_column_to
+        );
+        res.push(block);
+    }
+    res
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod tests {
+    use super::{compute_matching_blocks_from_text, Rename};
+    use crate::{
+        config::DetectionConfig,
+        fixtures::{self, renamed, CODE},
+        lang::Language,
+    };
+
+    // blocks of 20 tokens, longer than the parts of a gapped copy
+    fn config() -> DetectionConfig {
+        DetectionConfig {
+            minimum_match_length: 20,
+            ..fixtures::config()
+        }
+    }
+
+    #[test]
+    fn test_merge_gaps() {
+        // a dummy statement every third line
+        let mut copy = String::new();
+        for (i, line) in CODE.lines().enumerate() {
+            copy += line;
+            copy += "\n";
+            if i % 3 == 2 {
+                copy += "drop(0);\n";
+            }
+        }
+        let config = config();
+        let blocks =
+            compute_matching_blocks_from_text(CODE, &copy, Language::Rust, &[], &config).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].left_gap, 0);
+        assert!(blocks[0].right_gap > 0);
+        assert!(blocks[0].left_line_to - blocks[0].left_line_from >= 6);
+
+        // the parts alone are too short
+        let config = DetectionConfig {
+            max_gap: 0,
+            ..config
+        };
+        let blocks =
+            compute_matching_blocks_from_text(CODE, &copy, Language::Rust, &[], &config).unwrap();
+        assert!(blocks.is_empty());
+    }
+
+    #[test]
+    fn test_short_tiles() {
+        // two statements in a different order, each shorter than the minimum match length
+        let left = "fn f(v: &[i64]) -> i64 {\n    let total: i64 = v.iter().sum();\n    println!(\"{}\", v.len());\n    total\n}\n";
+        let right = "struct S;\nimpl S {\n    fn g(&self, v: Vec<i64>) {\n        println!(\"{}\", v.len());\n        if v.is_empty() { return; }\n        let total: i64 = v.iter().sum();\n    }\n}\n";
+        let config = config();
+        let blocks =
+            compute_matching_blocks_from_text(left, right, Language::Rust, &[], &config).unwrap();
+        assert!(blocks.is_empty());
+
+        // found at half the minimum match length, but not merged into a block
+        let config = DetectionConfig {
+            minimum_match_length: 10,
+            max_gap: 0,
+            ..config
+        };
+        let blocks =
+            compute_matching_blocks_from_text(left, right, Language::Rust, &[], &config).unwrap();
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_renames() {
+        let copy = renamed().replace(" i", " idx");
+        let config = config();
+        let blocks =
+            compute_matching_blocks_from_text(CODE, &copy, Language::Rust, &[], &config).unwrap();
+        assert_eq!(blocks.len(), 1);
+        let rename = |left: &str, right: &str, count| Rename {
+            left: left.to_string(),
+            right: right.to_string(),
+            count,
+        };
+        assert_eq!(
+            blocks[0].renames,
+            [rename("i", "idx", 5), rename("sum", "total", 4)]
+        );
+        assert_eq!(blocks[0].rename_consistency, 100);
+
+        // a name mapped to two others is not consistent
+        let copy = CODE.replacen("i * i", "i * j", 1);
+        let blocks =
+            compute_matching_blocks_from_text(CODE, &copy, Language::Rust, &[], &config).unwrap();
+        assert_eq!(blocks[0].renames, []);
+        assert!(blocks[0].rename_consistency < 100);
     }
-    Ok(res)
 }
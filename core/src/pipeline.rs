@@ -0,0 +1,1335 @@
+//! Detection pipeline shared by the server and the command line tools
+//!
+//! https://theory.stanford.edu/~aiken/publications/papers/sigmod03.pdf
+//!
+//! 1. tokenize submissions and templates, normalizing tokens by the enabled passes
+//! 2. winnow fingerprints of each submission and build an inverted index
+//! 3. drop fingerprints in templates, in more than a set percentage of
+//!    submissions as common code, or shared by too many submissions
+//! 4. score shared fingerprints of each pair by rarity and take the top pairs
+//! 5. compute matching blocks and similarity metrics of top pairs via RKR-GST,
+//!    reusing tokens and template matches of each submission across its pairs
+//! 6. optionally, measure verbatim coverage of top pairs by fingerprints of
+//!    kinds together with spellings
+use crate::{
+    common::{all_fingerprint, fingerprint, verbatim_fingerprints, Fingerprint},
+    config::DetectionConfig,
+    evidence::{count_marks, extract_marks, shared_evidence, Evidence, EvidenceKind, Mark},
+    lang::{identifier_kinds, normalize::normalize, tokenize_str, tokenize_str_lossy, Language},
+    matching::{
+        blocks_from_matches, compute_matches_excluding, compute_template_matches, matched_lines,
+        template_kinds, Block, TokenMatch,
+    },
+    token::Token,
+};
+use anyhow::Context;
+use log::*;
+use rayon::prelude::*;
+use rkr_gst::Match;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// One file of a submission
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub code: String,
+}
+
+/// Source code of one submission
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub name: String,
+    pub files: Vec<SourceFile>,
+}
+
+impl Source {
+    /// Total number of lines in all files
+    pub fn lines(&self) -> usize {
+        self.files.iter().map(|f| f.code.lines().count()).sum()
+    }
+}
+
+// indices of files in the order of their names
+fn file_order(source: &Source) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..source.files.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&source.files[*a], &source.files[*b]);
+        (&a.name, &a.code).cmp(&(&b.name, &b.code))
+    });
+    order
+}
+
+/// Tokenize all files of a submission
+///
+/// Files are concatenated in the order of their names, so that the result does
+/// not depend on the order files are given in. `Token::file` is set to the
+/// index of the file in `source.files`.
+pub fn tokenize_source(source: &Source, language: Language) -> anyhow::Result<Vec<Token>> {
+    let mut res = vec![];
+    for i in file_order(source) {
+        let file = &source.files[i];
+        let mut tokens = tokenize_str(&file.code, language).with_context(|| file.name.clone())?;
+        for token in &mut tokens {
+            token.file = i as u32;
+        }
+        res.extend(tokens);
+    }
+    Ok(res)
+}
+
+/// Severity of a problem found while tokenizing a file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    // some lines were skipped
+    Warning,
+    // the whole file was skipped
+    Error,
+}
+
+/// Problem found while tokenizing a file of a submission
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    // index into submissions
+    pub submission: usize,
+    pub file: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Tokenize all files of a submission like `tokenize_source`, skipping the
+/// parts that cannot be tokenized instead of failing
+///
+/// Problems are returned with `Diagnostic::submission` set to zero.
+pub fn tokenize_source_lossy(source: &Source, language: Language) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut res = vec![];
+    let mut diagnostics = vec![];
+    for i in file_order(source) {
+        let file = &source.files[i];
+        let mut diagnostic = |severity, message| {
+            diagnostics.push(Diagnostic {
+                submission: 0,
+                file: file.name.clone(),
+                severity,
+                message,
+            })
+        };
+        let mut tokens = match tokenize_str_lossy(&file.code, language) {
+            Ok((tokens, warnings)) => {
+                for warning in warnings {
+                    diagnostic(Severity::Warning, warning);
+                }
+                tokens
+            }
+            Err(err) => {
+                diagnostic(Severity::Error, format!("{:#}", err));
+                continue;
+            }
+        };
+        for token in &mut tokens {
+            token.file = i as u32;
+        }
+        res.extend(tokens);
+    }
+    (res, diagnostics)
+}
+
+/// Fingerprints of a reference-only submission, e.g. from previous jobs
+///
+/// References are matched against submissions, but never against each other.
+#[derive(Clone, Debug)]
+pub struct Reference {
+    // identifier in the reference store
+    pub id: i64,
+    pub name: String,
+    // fingerprint hashes, may be limited to those shared with submissions
+    pub hashes: Vec<u64>,
+}
+
+/// Storage of reference-only submissions
+pub trait ReferenceStore {
+    /// Find references sharing any of the given fingerprint hashes
+    fn lookup(&mut self, hashes: &[u64]) -> anyhow::Result<Vec<Reference>>;
+
+    /// Load source code of a reference found by `lookup`
+    fn load(&mut self, reference: &Reference) -> anyhow::Result<Source>;
+}
+
+struct NoReferences;
+
+impl ReferenceStore for NoReferences {
+    fn lookup(&mut self, _hashes: &[u64]) -> anyhow::Result<Vec<Reference>> {
+        Ok(vec![])
+    }
+
+    fn load(&mut self, _reference: &Reference) -> anyhow::Result<Source> {
+        unreachable!()
+    }
+}
+
+/// A pair of submissions sharing fingerprints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    // index into submissions followed by references, left > right
+    pub left: usize,
+    pub right: usize,
+    // number of shared fingerprints
+    pub fingerprints: usize,
+    // sum of rarity weights of shared fingerprints, in thousandths
+    pub score: u64,
+}
+
+/// A candidate pair with its matching blocks
+pub struct Pair {
+    // index into submissions followed by references
+    pub left: usize,
+    pub right: usize,
+    // number of shared fingerprints
+    pub fingerprints: usize,
+    // sum of rarity weights of shared fingerprints, in thousandths
+    pub score: u64,
+    // percentage of matched lines
+    pub left_match_rate: i32,
+    pub right_match_rate: i32,
+    // matched lines of both sides
+    pub lines_matched: usize,
+    // percentage of matched tokens
+    pub left_token_rate: i32,
+    pub right_token_rate: i32,
+    // percentage of fingerprints shared among those of either side, i.e. Jaccard index
+    pub similarity: i32,
+    // percentage of fingerprints of one side found in the other
+    pub left_containment: i32,
+    pub right_containment: i32,
+    // matched tokens of the longest block
+    pub longest_block: usize,
+    // percentage of lines copied with the same spellings, 0 unless enabled
+    pub left_verbatim_rate: i32,
+    pub right_verbatim_rate: i32,
+    pub blocks: Vec<Block>,
+    // rare comments and literals found on both sides
+    pub evidence: Vec<Evidence>,
+}
+
+/// Code shared by many submissions, subtracted like the template
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommonCode {
+    // index into submissions the code is taken from
+    pub submission: usize,
+    pub file: String,
+    // 1-based, inclusive
+    pub line_from: usize,
+    pub line_to: usize,
+    // whole lines covering the common tokens
+    pub code: String,
+}
+
+/// Result of the whole pipeline
+pub struct Report {
+    // highest score first
+    pub pairs: Vec<Pair>,
+    // fingerprint hashes of each submission, excluding those in templates
+    pub hashes: Vec<Vec<u64>>,
+    // references found in the store
+    pub references: Vec<Reference>,
+    // problems found while tokenizing submissions, in the order of submissions
+    pub diagnostics: Vec<Diagnostic>,
+    // derived template of code shared by many submissions
+    pub common_code: Vec<CommonCode>,
+}
+
+/// Stage of the pipeline
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Tokenize,
+    Winnow,
+    Candidates,
+    Align,
+}
+
+/// Receiver of pipeline progress, which may also cancel the run
+///
+/// Methods may be called from multiple threads.
+pub trait Progress: Sync {
+    /// A new stage has started
+    fn stage(&self, _stage: Stage) {}
+    /// `done` of `total` candidate pairs have been aligned
+    fn pairs(&self, _done: usize, _total: usize) {}
+    /// Whether the run should stop, checked between stages and pairs
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Progress receiver that ignores everything
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// Error returned when a run is cancelled via `Progress::cancelled`
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+fn check_cancelled(progress: &dyn Progress) -> anyhow::Result<()> {
+    if progress.cancelled() {
+        return Err(Cancelled.into());
+    }
+    Ok(())
+}
+
+/// Tokenize and normalize submissions, skipping the parts that cannot be tokenized
+pub fn tokenize_sources(
+    sources: &[Source],
+    language: Language,
+    config: &DetectionConfig,
+) -> (Vec<Vec<Token>>, Vec<Diagnostic>) {
+    let tokenized: Vec<(Vec<Token>, Vec<Diagnostic>)> = sources
+        .par_iter()
+        .map(|source| {
+            let (mut tokens, diagnostics) = tokenize_source_lossy(source, language);
+            normalize(&mut tokens, language, config);
+            (tokens, diagnostics)
+        })
+        .collect();
+    let mut tokens = vec![];
+    let mut diagnostics = vec![];
+    for (i, (source_tokens, source_diagnostics)) in tokenized.into_iter().enumerate() {
+        for mut diagnostic in source_diagnostics {
+            warn!(
+                "{}: {}: {:?}: {}",
+                sources[i].name, diagnostic.file, diagnostic.severity, diagnostic.message
+            );
+            diagnostic.submission = i;
+            diagnostics.push(diagnostic);
+        }
+        tokens.push(source_tokens);
+    }
+    (tokens, diagnostics)
+}
+
+/// Winnow fingerprints of each submission, excluding fingerprints in templates
+///
+/// Templates are given by the token kinds of each of their files.
+pub fn winnow(
+    names: &[&str],
+    tokens: &[Vec<Token>],
+    templates: &[Vec<u16>],
+    config: &DetectionConfig,
+) -> Vec<Vec<Fingerprint>> {
+    let template_fingerprint: HashSet<u64> = templates
+        .iter()
+        .flat_map(|template| all_fingerprint(template.iter().copied(), config.noise))
+        .map(|f| f.hash)
+        .collect();
+
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let fingerprint =
+                fingerprint(token.iter().map(|t| t.kind), config.noise, config.guarantee);
+            info!(
+                "{}: {} tokens, {} fingerprints",
+                names[i],
+                token.len(),
+                fingerprint.len()
+            );
+            fingerprint
+                .into_iter()
+                .filter(|f| !template_fingerprint.contains(&f.hash))
+                .collect()
+        })
+        .collect()
+}
+
+/// Find fingerprint hashes in more than `common_code_percent` of submissions
+pub fn common_hashes(fingerprints: &[Vec<Fingerprint>], config: &DetectionConfig) -> HashSet<u64> {
+    if config.common_code_percent == 0 {
+        return HashSet::new();
+    }
+    // hash => number of submissions containing it
+    let mut submissions: HashMap<u64, usize> = HashMap::new();
+    for fingerprint in fingerprints {
+        let hashes: HashSet<u64> = fingerprint.iter().map(|f| f.hash).collect();
+        for hash in hashes {
+            *submissions.entry(hash).or_default() += 1;
+        }
+    }
+    submissions
+        .into_iter()
+        .filter(|(_, count)| count * 100 > config.common_code_percent * fingerprints.len())
+        .map(|(hash, _)| hash)
+        .collect()
+}
+
+/// Extract code covered by common fingerprints, to be used as an implicit template
+///
+/// Each part is taken once, from the first submission containing it.
+/// Returns the parts along with the token kinds of each, to be matched separately
+/// so that no template match spans the junction of two parts.
+pub fn extract_common_code(
+    sources: &[Source],
+    tokens: &[Vec<Token>],
+    fingerprints: &[Vec<Fingerprint>],
+    common: &HashSet<u64>,
+    config: &DetectionConfig,
+) -> (Vec<CommonCode>, Vec<Vec<u16>>) {
+    let mut res = vec![];
+    let mut kinds = vec![];
+    let mut seen: HashSet<u64> = HashSet::new();
+    for (i, fingerprint) in fingerprints.iter().enumerate() {
+        let tokens = &tokens[i];
+        // tokens covered by common fingerprints, merging gaps within a winnowing
+        // window since a fingerprint is picked in every window of common code
+        let mut parts: Vec<(Range<usize>, Vec<u64>)> = vec![];
+        for f in fingerprint.iter().filter(|f| common.contains(&f.hash)) {
+            let end = (f.offset + config.noise).min(tokens.len());
+            match parts.last_mut() {
+                Some((range, hashes))
+                    if f.offset <= range.end + (config.guarantee - config.noise)
+                        && tokens[range.start].file == tokens[f.offset].file =>
+                {
+                    range.end = range.end.max(end);
+                    hashes.push(f.hash);
+                }
+                _ => parts.push((f.offset..end, vec![f.hash])),
+            }
+        }
+
+        for (range, hashes) in parts {
+            if hashes.iter().all(|hash| seen.contains(hash)) {
+                continue;
+            }
+            seen.extend(hashes);
+            // keep tokens in the file of the first one
+            let file = tokens[range.start].file;
+            let part: Vec<&Token> = tokens[range]
+                .iter()
+                .take_while(|t| t.file == file)
+                .collect();
+            let source_file = &sources[i].files[file as usize];
+            let line_from = part[0].line as usize;
+            let line_to = part[part.len() - 1].end_line as usize;
+            let mut code = String::new();
+            for line in source_file
+                .code
+                .lines()
+                .skip(line_from - 1)
+                .take(line_to - line_from + 1)
+            {
+                code += line;
+                code.push('\n');
+            }
+            kinds.push(part.iter().map(|t| t.kind).collect());
+            res.push(CommonCode {
+                submission: i,
+                file: source_file.name.clone(),
+                line_from,
+                line_to,
+                code,
+            });
+        }
+    }
+    (res, kinds)
+}
+
+/// Find pairs of submissions sharing most fingerprints, most similar first
+///
+/// Each shared fingerprint is weighted by its rarity, `ln(1 + n / k)` for `k` out of
+/// `n` submissions and references holding it, so that a fingerprint of two students
+/// counts more than one of eight. Weights are summed in thousandths to keep the
+/// order of pairs independent of the order of summation.
+///
+/// Indices of references follow those of submissions.
+/// Pairs of two references are skipped.
+pub fn find_candidates(
+    names: &[&str],
+    fingerprints: &[Vec<Fingerprint>],
+    references: &[Reference],
+    config: &DetectionConfig,
+) -> Vec<Candidate> {
+    // inverted index: hash => (offset, submission)
+    let mut index: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+    for (i, fingerprint) in fingerprints.iter().enumerate() {
+        for f in fingerprint {
+            index.entry(f.hash).or_default().push((f.offset, i));
+        }
+    }
+    let num_submissions = fingerprints.len();
+    for (i, reference) in references.iter().enumerate() {
+        for hash in &reference.hashes {
+            // only fingerprints shared with submissions matter
+            if let Some(v) = index.get_mut(hash) {
+                v.push((0, num_submissions + i));
+            }
+        }
+    }
+    let name = |i: usize| {
+        if i < num_submissions {
+            names[i]
+        } else {
+            &references[i - num_submissions].name
+        }
+    };
+
+    // count and score shared fingerprints of pairs sharing any, keyed by (left, right)
+    // with left > right, so memory grows with shared fingerprints instead of n * n
+    let holders_total = num_submissions + references.len();
+    let mut counts: HashMap<(usize, usize), (usize, u64)> = HashMap::new();
+    for v in index.values() {
+        if v.len() > config.max_fingerprint_occurrences {
+            // too common, skip
+            continue;
+        }
+        let holders = v.iter().map(|(_, i)| *i).collect::<HashSet<usize>>().len();
+        let weight = ((1.0 + holders_total as f64 / holders as f64).ln() * 1000.0).round() as u64;
+
+        if v.len() > 5 {
+            debug!("Found {} entries:", v.len());
+            for (offset, i) in v {
+                debug!("{} offset {}", name(*i), offset);
+            }
+        }
+        for i in 0..v.len() {
+            for j in (i + 1)..v.len() {
+                let (left, right) = (v[i].1.max(v[j].1), v[i].1.min(v[j].1));
+                // skip same submission, and reference against reference
+                if left == right || right >= num_submissions {
+                    continue;
+                }
+                let (fingerprints, score) = counts.entry((left, right)).or_default();
+                *fingerprints += 1;
+                *score += weight;
+            }
+        }
+    }
+
+    // select highest scores without sorting all pairs, ties in a fixed order
+    let mut candidates: Vec<Candidate> = counts
+        .into_iter()
+        .map(|((left, right), (fingerprints, score))| Candidate {
+            left,
+            right,
+            fingerprints,
+            score,
+        })
+        .collect();
+    let order = |a: &Candidate, b: &Candidate| {
+        (b.score, b.fingerprints, b.right, b.left).cmp(&(a.score, a.fingerprints, a.right, a.left))
+    };
+    if candidates.len() > config.max_pairs && config.max_pairs > 0 {
+        candidates.select_nth_unstable_by(config.max_pairs - 1, order);
+    }
+    candidates.truncate(config.max_pairs);
+    candidates.sort_unstable_by(order);
+    candidates
+}
+
+/// Tokens of a submission, shared by all pairs it is aligned in
+pub struct Prepared {
+    pub tokens: Vec<Token>,
+    pub kinds: Vec<u16>,
+    // matches against templates, excluded from pairs
+    pub template_matches: Vec<Match>,
+    // number of lines in all files
+    pub lines: usize,
+    // comments and literals, empty if evidence is disabled
+    pub marks: Vec<Mark>,
+    // winnowed fingerprint hashes outside of templates
+    pub hashes: HashSet<u64>,
+    // `noise`-grams of kinds and spellings outside of templates, empty if verbatim is disabled
+    pub verbatim: Vec<Fingerprint>,
+}
+
+impl Prepared {
+    pub fn new(
+        source: &Source,
+        tokens: Vec<Token>,
+        marks: Vec<Mark>,
+        templates: &[Vec<u16>],
+        config: &DetectionConfig,
+    ) -> Self {
+        let kinds: Vec<u16> = tokens.iter().map(|t| t.kind).collect();
+        let template_matches: Vec<Match> = templates
+            .iter()
+            .flat_map(|template_kinds| compute_template_matches(&kinds, template_kinds, config))
+            .collect();
+        let mut in_template = vec![false; tokens.len()];
+        for m in &template_matches {
+            in_template[m.pattern_index..m.pattern_index + m.length].fill(true);
+        }
+        let outside =
+            |f: &Fingerprint| !in_template[f.offset..f.offset + config.noise].contains(&true);
+        let hashes = fingerprint(kinds.iter().copied(), config.noise, config.guarantee)
+            .into_iter()
+            .filter(outside)
+            .map(|f| f.hash)
+            .collect();
+        let verbatim = if config.verbatim {
+            verbatim_fingerprints(&tokens, config.noise)
+                .into_iter()
+                .filter(outside)
+                .collect()
+        } else {
+            vec![]
+        };
+        Self {
+            tokens,
+            kinds,
+            template_matches,
+            lines: source.lines(),
+            marks,
+            hashes,
+            verbatim,
+        }
+    }
+}
+
+/// Percentage of lines on each side covered by `noise`-grams of tokens
+/// spelled the same on the other side
+fn verbatim_rates(left: &Prepared, right: &Prepared, config: &DetectionConfig) -> (i32, i32) {
+    let rate = |this: &Prepared, other: &Prepared| {
+        let other: HashSet<u64> = other.verbatim.iter().map(|f| f.hash).collect();
+        let lines: HashSet<(u32, u32)> = this
+            .verbatim
+            .iter()
+            .filter(|f| other.contains(&f.hash))
+            .flat_map(|f| &this.tokens[f.offset..f.offset + config.noise])
+            .map(|t| (t.file, t.line))
+            .collect();
+        (lines.len() * 100 / this.lines.max(1)) as i32
+    };
+    (rate(left, right), rate(right, left))
+}
+
+/// Compute matching blocks of a candidate pair
+///
+/// Renames are found among tokens of the `identifiers` kinds.
+pub fn align(
+    left: &Prepared,
+    right: &Prepared,
+    candidate: &Candidate,
+    identifiers: &[u16],
+    config: &DetectionConfig,
+) -> Pair {
+    let matches = compute_matches_excluding(
+        &left.tokens,
+        &left.kinds,
+        &right.tokens,
+        &right.kinds,
+        &left.template_matches,
+        &right.template_matches,
+        config,
+    );
+    let blocks = blocks_from_matches(&left.tokens, &right.tokens, &matches, identifiers);
+
+    let left_matched_lines = matched_lines(&blocks, true);
+    let right_matched_lines = matched_lines(&blocks, false);
+    let left_lines = left.lines.max(1);
+    let right_lines = right.lines.max(1);
+    let (left_verbatim_rate, right_verbatim_rate) = if config.verbatim {
+        verbatim_rates(left, right, config)
+    } else {
+        (0, 0)
+    };
+
+    // both sides of a match have as many matched tokens
+    let matched_tokens = |m: &TokenMatch| m.parts.iter().map(|(_, _, len)| len).sum::<usize>();
+    let tokens_matched: usize = matches.iter().map(matched_tokens).sum();
+    let shared = left.hashes.intersection(&right.hashes).count();
+    let percent = |part: usize, whole: usize| (part * 100 / whole.max(1)) as i32;
+
+    Pair {
+        left: candidate.left,
+        right: candidate.right,
+        fingerprints: candidate.fingerprints,
+        score: candidate.score,
+        left_match_rate: (left_matched_lines * 100 / left_lines) as i32,
+        right_match_rate: (right_matched_lines * 100 / right_lines) as i32,
+        lines_matched: left_matched_lines + right_matched_lines,
+        left_token_rate: percent(tokens_matched, left.tokens.len()),
+        right_token_rate: percent(tokens_matched, right.tokens.len()),
+        similarity: percent(shared, left.hashes.len() + right.hashes.len() - shared),
+        left_containment: percent(shared, left.hashes.len()),
+        right_containment: percent(shared, right.hashes.len()),
+        longest_block: matches.iter().map(matched_tokens).max().unwrap_or(0),
+        left_verbatim_rate,
+        right_verbatim_rate,
+        blocks,
+        evidence: vec![],
+    }
+}
+
+/// Run the whole pipeline, returning pairs with highest score first
+pub fn run(
+    sources: &[Source],
+    templates: &[Source],
+    language: Language,
+    config: &DetectionConfig,
+) -> anyhow::Result<Vec<Pair>> {
+    let report = run_with_references(
+        sources,
+        templates,
+        language,
+        &mut NoReferences,
+        &NoProgress,
+        config,
+    )?;
+    Ok(report.pairs)
+}
+
+/// Run the whole pipeline, also matching submissions against references in the store
+///
+/// Code in any of the templates, each of one or more files, is ignored.
+pub fn run_with_references(
+    sources: &[Source],
+    templates: &[Source],
+    language: Language,
+    store: &mut dyn ReferenceStore,
+    progress: &dyn Progress,
+    config: &DetectionConfig,
+) -> anyhow::Result<Report> {
+    progress.stage(Stage::Tokenize);
+    let template_tokens = templates
+        .iter()
+        .map(|template| {
+            let mut tokens = tokenize_source(template, language)
+                .with_context(|| format!("template {}", template.name))?;
+            normalize(&mut tokens, language, config);
+            Ok(tokens)
+        })
+        .collect::<anyhow::Result<Vec<Vec<Token>>>>()?;
+    let (mut tokens, diagnostics) = tokenize_sources(sources, language, config);
+    info!("Tokenized {} submissions", tokens.len());
+
+    // comments and literals are collected apart from tokens
+    let evidence = config.max_evidence_occurrences > 0;
+    let (marks, template_marks) = if evidence {
+        let marks: Vec<Vec<Mark>> = sources
+            .par_iter()
+            .zip(&tokens)
+            .map(|(source, tokens)| extract_marks(source, tokens, language))
+            .collect();
+        let template_marks: Vec<Mark> = templates
+            .iter()
+            .zip(&template_tokens)
+            .flat_map(|(template, tokens)| extract_marks(template, tokens, language))
+            .collect();
+        (marks, template_marks)
+    } else {
+        (vec![], vec![])
+    };
+    let mark_counts = count_marks(&marks);
+    let template_marks: HashSet<(EvidenceKind, &str)> = template_marks
+        .iter()
+        .map(|m| (m.kind, m.text.as_str()))
+        .collect();
+    // evidence in few submissions, references are not counted
+    let is_rare = |mark: &Mark, in_reference: bool| {
+        let key = (mark.kind, mark.text.as_str());
+        !template_marks.contains(&key)
+            && mark_counts.get(&key).copied().unwrap_or(0) + in_reference as usize
+                <= config.max_evidence_occurrences
+    };
+    let mut templates = template_kinds(&template_tokens);
+
+    check_cancelled(progress)?;
+    progress.stage(Stage::Winnow);
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    let fingerprints = winnow(&names, &tokens, &templates, config);
+
+    // code in many submissions is dropped like the template
+    let common = common_hashes(&fingerprints, config);
+    let (common_code, common_kinds) =
+        extract_common_code(sources, &tokens, &fingerprints, &common, config);
+    if !common.is_empty() {
+        info!(
+            "Found {} common fingerprints in {} parts",
+            common.len(),
+            common_code.len()
+        );
+    }
+    let fingerprints: Vec<Vec<Fingerprint>> = fingerprints
+        .into_iter()
+        .map(|f| {
+            f.into_iter()
+                .filter(|f| !common.contains(&f.hash))
+                .collect()
+        })
+        .collect();
+
+    check_cancelled(progress)?;
+    progress.stage(Stage::Candidates);
+    // find references sharing fingerprints
+    let mut hashes: Vec<u64> = fingerprints.iter().flatten().map(|f| f.hash).collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    let references = store.lookup(&hashes)?;
+    info!("Found {} references", references.len());
+
+    let candidates = find_candidates(&names, &fingerprints, &references, config);
+
+    // load references in candidates
+    let mut reference_sources = HashMap::new();
+    for candidate in &candidates {
+        // left > right, so only left can be a reference
+        if candidate.left >= sources.len() {
+            let i = candidate.left - sources.len();
+            if let Entry::Vacant(e) = reference_sources.entry(i) {
+                e.insert(store.load(&references[i])?);
+            }
+        }
+    }
+    let source = |i: usize| {
+        if i < sources.len() {
+            &sources[i]
+        } else {
+            &reference_sources[&(i - sources.len())]
+        }
+    };
+
+    check_cancelled(progress)?;
+    progress.stage(Stage::Align);
+    progress.pairs(0, candidates.len());
+
+    // tokenize references and match against templates once for all pairs
+    templates.extend(common_kinds);
+    let involved: BTreeSet<usize> = candidates.iter().flat_map(|c| [c.left, c.right]).collect();
+    let involved: Vec<(usize, Option<Vec<Token>>)> = involved
+        .into_iter()
+        .map(|i| (i, tokens.get_mut(i).map(std::mem::take)))
+        .collect();
+    let prepared = involved
+        .into_par_iter()
+        .map(|(i, tokens)| {
+            let source = source(i);
+            let tokens = match tokens {
+                Some(tokens) => tokens,
+                // problems of references were reported by their own jobs
+                None => {
+                    let mut tokens = tokenize_source_lossy(source, language).0;
+                    normalize(&mut tokens, language, config);
+                    tokens
+                }
+            };
+            let marks = match marks.get(i) {
+                Some(marks) => marks.clone(),
+                None if evidence => extract_marks(source, &tokens, language),
+                None => vec![],
+            };
+            (i, Prepared::new(source, tokens, marks, &templates, config))
+        })
+        .collect::<HashMap<usize, Prepared>>();
+
+    let identifiers = identifier_kinds(language);
+    let done = AtomicUsize::new(0);
+    let mut pairs = candidates
+        .par_iter()
+        .map(|candidate| {
+            check_cancelled(progress)?;
+            let (left, right) = (&prepared[&candidate.left], &prepared[&candidate.right]);
+            let mut pair = align(left, right, candidate, &identifiers, config);
+            // left > right, so only left can be a reference
+            let in_reference = candidate.left >= sources.len();
+            pair.evidence =
+                shared_evidence(&left.marks, &right.marks, |m| is_rare(m, in_reference));
+            progress.pairs(done.fetch_add(1, Ordering::Relaxed) + 1, candidates.len());
+            Ok(pair)
+        })
+        .collect::<anyhow::Result<Vec<Pair>>>()?;
+
+    pairs.sort_by_key(|p| (p.score, p.lines_matched));
+    pairs.reverse();
+    Ok(Report {
+        pairs,
+        hashes: fingerprints
+            .iter()
+            .map(|f| f.iter().map(|f| f.hash).collect())
+            .collect(),
+        references,
+        diagnostics,
+        common_code,
+    })
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod tests {
+    use super::{
+        common_hashes, extract_common_code, find_candidates, run, run_with_references, winnow,
+        Cancelled, Candidate, NoProgress, NoReferences, Progress, Reference, ReferenceStore,
+        Report, Severity, Source, Stage,
+    };
+    use crate::{
+        common::Fingerprint,
+        config::DetectionConfig,
+        evidence::EvidenceKind,
+        fixtures::{config, renamed, source, CODE, COMMON, OTHER},
+        lang::{identifier_kinds, tokenize_str, Language},
+        matching::{compute_matching_blocks_from_token, compute_template_matches},
+        pipeline::tokenize_source,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    fn detect(sources: &[Source], config: &DetectionConfig) -> Report {
+        run_with_references(
+            sources,
+            &[],
+            Language::Rust,
+            &mut NoReferences,
+            &NoProgress,
+            config,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        let renamed = renamed();
+        let sources = vec![
+            source("a", &[("main.rs", CODE), ("point.rs", OTHER)]),
+            source("b", &[("main.rs", OTHER)]),
+            // same files in different order
+            source("c", &[("point.rs", OTHER), ("main.rs", &renamed)]),
+        ];
+        let pairs = run(&sources, &[], Language::Rust, &config()).unwrap();
+
+        // the renamed copy is the most similar pair
+        assert_eq!((pairs[0].left, pairs[0].right), (2, 0));
+        assert_eq!(pairs[0].left_match_rate, 100);
+        assert_eq!(pairs[0].right_match_rate, 100);
+
+        // blocks record file of each side
+        for block in &pairs[0].blocks {
+            let left = &sources[2].files[block.left_file].name;
+            let right = &sources[0].files[block.right_file].name;
+            assert_eq!(left, right);
+        }
+    }
+
+    #[test]
+    fn test_find_candidates() {
+        let fingerprints = |hashes: &[u64]| -> Vec<Fingerprint> {
+            hashes
+                .iter()
+                .enumerate()
+                .map(|(offset, hash)| Fingerprint {
+                    hash: *hash,
+                    offset,
+                })
+                .collect()
+        };
+        let all = [
+            fingerprints(&[1, 2, 3, 9]),
+            fingerprints(&[1, 2, 3, 9]),
+            fingerprints(&[3, 4, 9]),
+            fingerprints(&[4, 5, 9]),
+        ];
+        let reference = |id: i64, hashes: Vec<u64>| Reference {
+            id,
+            name: format!("r{}", id),
+            hashes,
+        };
+        // references share with each other, which is skipped
+        let references = [reference(0, vec![5, 6]), reference(1, vec![6, 2])];
+        let names = ["a", "b", "c", "d"];
+        let config = DetectionConfig {
+            max_fingerprint_occurrences: 3,
+            ..Default::default()
+        };
+        let candidate = |left, right, fingerprints, score| Candidate {
+            left,
+            right,
+            fingerprints,
+            score,
+        };
+
+        // hash 9 is too common, ties ordered by right then left, descending
+        // of 6 submissions and references, 2 hold hash 1 and 3 hold hash 2,
+        // weighted ln(1 + 6 / 2) and ln(1 + 6 / 3)
+        assert_eq!(
+            find_candidates(&names, &all, &references, &config),
+            [
+                candidate(1, 0, 3, 1386 + 1099 + 1099),
+                candidate(4, 3, 1, 1386),
+                candidate(3, 2, 1, 1386),
+                candidate(5, 1, 1, 1099),
+                candidate(2, 1, 1, 1099),
+                candidate(5, 0, 1, 1099),
+                candidate(2, 0, 1, 1099),
+            ]
+        );
+        let config = DetectionConfig {
+            max_pairs: 2,
+            ..config
+        };
+        assert_eq!(
+            find_candidates(&names, &all, &references, &config),
+            [candidate(1, 0, 3, 3584), candidate(4, 3, 1, 1386)]
+        );
+
+        // one fingerprint of two outweighs two fingerprints of seven
+        let mut all = vec![fingerprints(&[1, 2]); 6];
+        all.push(fingerprints(&[1, 2, 3]));
+        all.push(fingerprints(&[3]));
+        let config = DetectionConfig {
+            max_pairs: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            find_candidates(&["a"; 8], &all, &[], &config),
+            [candidate(7, 6, 1, 1609)]
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let broken = CODE.replace("let mut sum = 0;", "let mut sum = 0 ¤;");
+        let sources = vec![
+            source("a", &[("main.rs", CODE)]),
+            source("b", &[("main.rs", &broken), ("bad.rs", &"¤\n".repeat(30))]),
+        ];
+        let report = detect(&sources, &config());
+
+        // the pair is still found without the broken line and file
+        assert_eq!(report.pairs.len(), 1);
+        assert!(report.pairs[0].right_match_rate > 80);
+        let diagnostics: Vec<(usize, &str, Severity)> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.submission, d.file.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                (1, "bad.rs", Severity::Error),
+                (1, "main.rs", Severity::Warning)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        let sources = vec![
+            source("a", &[("main.rs", CODE), ("point.rs", OTHER)]),
+            source("b", &[("main.rs", &renamed())]),
+            source("c", &[("point.rs", OTHER)]),
+        ];
+        let templates = [source("starter", &[("point.rs", OTHER)])];
+        let pairs = run(&sources, &templates, Language::Rust, &config()).unwrap();
+
+        // same blocks as aligning the pair on its own
+        for pair in &pairs {
+            let blocks = compute_matching_blocks_from_token(
+                &tokenize_source(&sources[pair.left], Language::Rust).unwrap(),
+                &tokenize_source(&sources[pair.right], Language::Rust).unwrap(),
+                &[tokenize_str(OTHER, Language::Rust).unwrap()],
+                &identifier_kinds(Language::Rust),
+                &config(),
+            );
+            assert_eq!(pair.blocks, blocks);
+        }
+
+        // code of all templates is ignored
+        let templates = [
+            source("starter", &[("point.rs", OTHER)]),
+            source("library", &[("main.rs", CODE)]),
+        ];
+        let pairs = run(&sources, &templates, Language::Rust, &config()).unwrap();
+        assert!(pairs.is_empty());
+    }
+
+    #[test]
+    fn test_evidence() {
+        let extra = "// works for teh samples\nconst URL: &str = \"http://example.com\";\n";
+        let sources = vec![
+            source("a", &[("main.rs", &format!("{}{}", CODE, extra))]),
+            source("b", &[("main.rs", &format!("{}{}", renamed(), extra))]),
+            source(
+                "c",
+                &[("main.rs", &format!("{}// works for teh samples\n", OTHER))],
+            ),
+        ];
+        let config = DetectionConfig {
+            max_evidence_occurrences: 2,
+            ..config()
+        };
+        let pairs = run(&sources, &[], Language::Rust, &config).unwrap();
+        assert_eq!(pairs.len(), 1);
+        // the comment is in too many submissions
+        let evidence = &pairs[0].evidence;
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].kind, EvidenceKind::Literal);
+        assert_eq!(evidence[0].text, "\"http://example.com\"");
+        assert_eq!((evidence[0].left_line, evidence[0].right_line), (12, 12));
+
+        // nor is evidence taken from templates
+        let templates = [source("starter", &[("url.rs", extra)])];
+        let pairs = run(&sources, &templates, Language::Rust, &config).unwrap();
+        assert!(pairs[0].evidence.is_empty());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let sources = vec![
+            source("a", &[("main.rs", CODE), ("point.rs", OTHER)]),
+            source("b", &[("main.rs", CODE)]),
+        ];
+        let pairs = run(&sources, &[], Language::Rust, &config()).unwrap();
+        assert_eq!(pairs.len(), 1);
+        let pair = &pairs[0];
+        assert_eq!((pair.left, pair.right), (1, 0));
+        // b is contained in a
+        let tokens = tokenize_source(&sources[1], Language::Rust).unwrap().len();
+        assert_eq!(pair.longest_block, tokens);
+        assert_eq!((pair.left_token_rate, pair.right_token_rate), (100, 54));
+        assert_eq!((pair.left_containment, pair.right_containment), (100, 53));
+        // with nothing but b in common, similarity is the containment of a
+        assert_eq!(pair.similarity, pair.right_containment);
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let sources = vec![
+            source("a", &[("main.rs", CODE)]),
+            source("b", &[("main.rs", CODE)]),
+            source("c", &[("main.rs", &renamed())]),
+        ];
+        let verbatim = DetectionConfig {
+            verbatim: true,
+            ..config()
+        };
+        let pairs = run(&sources, &[], Language::Rust, &verbatim).unwrap();
+        assert_eq!(pairs.len(), 3);
+        // structurally all the same, but only the untouched copy is verbatim
+        assert!(pairs.iter().all(|p| p.left_match_rate == 100));
+        let mut rates: Vec<(usize, usize, i32, i32)> = pairs
+            .iter()
+            .map(|p| (p.left, p.right, p.left_verbatim_rate, p.right_verbatim_rate))
+            .collect();
+        rates.sort();
+        // lines without `sum` are still verbatim in the renamed copy
+        assert_eq!(rates, [(1, 0, 100, 100), (2, 0, 63, 63), (2, 1, 63, 63)]);
+
+        // disabled by default
+        let pairs = run(&sources, &[], Language::Rust, &config()).unwrap();
+        assert!(pairs.iter().all(|p| p.left_verbatim_rate == 0));
+    }
+
+    #[test]
+    fn test_common_code() {
+        // given in lecture, but not as template
+        let with_common = |code: &str| format!("{}{}", COMMON, code);
+        let sources = vec![
+            source("a", &[("main.rs", &with_common(CODE))]),
+            source("b", &[("main.rs", &with_common(OTHER))]),
+            source("c", &[("main.rs", &with_common("struct Empty;\n"))]),
+            source("d", &[("main.rs", &with_common(&renamed()))]),
+        ];
+        // without it, all pairs share the common code
+        let report = detect(&sources, &config());
+        assert!(report.common_code.is_empty());
+        assert_eq!(report.pairs.len(), 6);
+
+        let report = detect(
+            &sources,
+            &DetectionConfig {
+                common_code_percent: 50,
+                ..config()
+            },
+        );
+        assert!(!report.common_code.is_empty());
+        for part in &report.common_code {
+            assert_eq!((part.submission, part.file.as_str()), (0, "main.rs"));
+            assert!(part.line_to <= 5);
+            assert!(COMMON.contains(&part.code));
+        }
+        // the copy is found without the common code, up to its closing brace
+        assert_eq!(report.pairs.len(), 1);
+        assert_eq!((report.pairs[0].left, report.pairs[0].right), (3, 0));
+        for pair in &report.pairs {
+            for block in &pair.blocks {
+                assert!(block.left_line_from >= 4 && block.right_line_from >= 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_common_code_parts() {
+        // two functions given in lecture, in separate files of every submission
+        let write = "fn write(v: &[i64]) {\n    for x in v {\n        println!(\"{} {}\", x, x * x);\n    }\n}\n";
+        let sources: Vec<Source> = [CODE, OTHER, "struct Empty;\n", "const N: usize = 10;\n"]
+            .iter()
+            .enumerate()
+            .map(|(i, code)| {
+                source(
+                    &i.to_string(),
+                    &[("main.rs", code), ("read.rs", COMMON), ("write.rs", write)],
+                )
+            })
+            .collect();
+        let config = DetectionConfig {
+            common_code_percent: 50,
+            ..config()
+        };
+        let tokens: Vec<_> = sources
+            .iter()
+            .map(|s| tokenize_source(s, Language::Rust).unwrap())
+            .collect();
+        let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+        let fingerprints = winnow(&names, &tokens, &[], &config);
+        let common = common_hashes(&fingerprints, &config);
+        let (parts, kinds) =
+            extract_common_code(&sources, &tokens, &fingerprints, &common, &config);
+        let files: Vec<&str> = parts.iter().map(|p| p.file.as_str()).collect();
+        assert_eq!(files, ["read.rs", "write.rs"]);
+        assert_eq!(kinds.len(), 2);
+
+        // the end of one part followed by the start of the other, as in a submission
+        // with both functions in one file, is too short to match either part
+        let junction = [&kinds[0][kinds[0].len() - 6..], &kinds[1][..6]].concat();
+        for part in &kinds {
+            assert!(compute_template_matches(&junction, part, &config).is_empty());
+        }
+        assert!(!compute_template_matches(&junction, &kinds.concat(), &config).is_empty());
+    }
+
+    struct Store(Vec<(Source, Vec<u64>)>);
+
+    impl ReferenceStore for Store {
+        fn lookup(&mut self, hashes: &[u64]) -> anyhow::Result<Vec<Reference>> {
+            Ok(self
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, (source, h))| Reference {
+                    id: i as i64,
+                    name: source.name.clone(),
+                    hashes: h.iter().copied().filter(|h| hashes.contains(h)).collect(),
+                })
+                .collect())
+        }
+
+        fn load(&mut self, reference: &Reference) -> anyhow::Result<Source> {
+            Ok(self.0[reference.id as usize].0.clone())
+        }
+    }
+
+    #[test]
+    fn test_references() {
+        // archive two identical submissions of a previous job
+        let archived = vec![
+            source("old1", &[("main.rs", CODE)]),
+            source("old2", &[("main.rs", CODE)]),
+        ];
+        let report = run_with_references(
+            &archived,
+            &[],
+            Language::Rust,
+            &mut Store(vec![]),
+            &NoProgress,
+            &config(),
+        )
+        .unwrap();
+        let mut store = Store(archived.into_iter().zip(report.hashes).collect());
+
+        let sources = vec![
+            source("new1", &[("main.rs", OTHER)]),
+            source("new2", &[("main.rs", &renamed())]),
+        ];
+        let report = run_with_references(
+            &sources,
+            &[],
+            Language::Rust,
+            &mut store,
+            &NoProgress,
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(report.references.len(), 2);
+
+        // copy from archive is found, references are never paired
+        assert_eq!(report.pairs[0].right, 1);
+        assert!(report.pairs[0].left >= 2);
+        assert!(report.pairs.iter().all(|p| p.right < 2));
+    }
+
+    struct Recorder {
+        stages: Mutex<Vec<Stage>>,
+        pairs: AtomicUsize,
+        cancel_at: Option<Stage>,
+    }
+
+    impl Progress for Recorder {
+        fn stage(&self, stage: Stage) {
+            self.stages.lock().unwrap().push(stage);
+        }
+
+        fn pairs(&self, done: usize, _total: usize) {
+            self.pairs.fetch_max(done, Ordering::Relaxed);
+        }
+
+        fn cancelled(&self) -> bool {
+            self.cancel_at.is_some()
+                && self.stages.lock().unwrap().last() == self.cancel_at.as_ref()
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let sources = vec![
+            source("a", &[("main.rs", CODE)]),
+            source("b", &[("main.rs", &renamed())]),
+        ];
+        let run = |cancel_at| {
+            let progress = Recorder {
+                stages: Mutex::new(vec![]),
+                pairs: AtomicUsize::new(0),
+                cancel_at,
+            };
+            let res = run_with_references(
+                &sources,
+                &[],
+                Language::Rust,
+                &mut NoReferences,
+                &progress,
+                &config(),
+            );
+            (res, progress)
+        };
+
+        let (res, progress) = run(None);
+        assert_eq!(res.unwrap().pairs.len(), 1);
+        assert_eq!(
+            *progress.stages.lock().unwrap(),
+            [
+                Stage::Tokenize,
+                Stage::Winnow,
+                Stage::Candidates,
+                Stage::Align
+            ]
+        );
+        assert_eq!(progress.pairs.load(Ordering::Relaxed), 1);
+
+        let (res, progress) = run(Some(Stage::Winnow));
+        assert!(res.err().unwrap().is::<Cancelled>());
+        assert_eq!(
+            *progress.stages.lock().unwrap(),
+            [Stage::Tokenize, Stage::Winnow]
+        );
+    }
+}
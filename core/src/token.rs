@@ -1,8 +1,91 @@
+use std::{collections::HashMap, ops::Range};
+
 #[derive(Clone, Debug)]
 pub struct Token {
-    pub kind: u8,
+    // id in the vocabulary of the language
+    pub kind: u16,
     pub spelling: String,
-    // 1-based
+    // 0-based index of file in submission
+    pub file: u32,
+    // 1-based, columns count characters
     pub line: u32,
     pub column: u32,
+    // 1-based, position right after the token
+    pub end_line: u32,
+    pub end_column: u32,
+    // byte range in file, filled by `lang::tokenize` and `lang::tokenize_str`
+    pub span: Range<usize>,
+}
+
+/// Token kinds of one language
+///
+/// The id of a kind is its index in the list, so kinds must only be appended
+/// to keep ids stable. Changing existing ids requires bumping `FINGERPRINT_VERSION`.
+/// By convention, kinds named in angle brackets like `<identifier>` are categories,
+/// other kinds are the exact spelling of keywords and punctuation.
+#[derive(Debug)]
+pub struct Vocabulary {
+    kinds: Vec<&'static str>,
+    ids: HashMap<&'static str, u16>,
+}
+
+impl Vocabulary {
+    /// Build vocabulary from kind names, panics on duplicate names
+    pub fn new(kinds: Vec<&'static str>) -> Self {
+        assert!(kinds.len() <= u16::MAX as usize, "too many token kinds");
+        let mut ids = HashMap::new();
+        for (id, kind) in kinds.iter().enumerate() {
+            if ids.insert(*kind, id as u16).is_some() {
+                panic!("duplicate token kind {:?}", kind);
+            }
+        }
+        Self { kinds, ids }
+    }
+
+    /// Id of kind, if present
+    pub fn get(&self, kind: &str) -> Option<u16> {
+        self.ids.get(kind).copied()
+    }
+
+    /// Id of kind, panics if missing
+    ///
+    /// Use this for kinds known to be in the vocabulary.
+    pub fn id(&self, kind: &str) -> u16 {
+        match self.get(kind) {
+            Some(id) => id,
+            None => panic!("unknown token kind {:?}", kind),
+        }
+    }
+
+    /// Name of kind by id
+    pub fn name(&self, id: u16) -> Option<&'static str> {
+        self.kinds.get(id as usize).copied()
+    }
+
+    /// All kinds, ordered by id
+    pub fn kinds(&self) -> &[&'static str] {
+        &self.kinds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vocabulary;
+
+    #[test]
+    fn test_vocabulary() {
+        let vocab = Vocabulary::new(vec!["<identifier>", "if", "("]);
+        assert_eq!(vocab.id("<identifier>"), 0);
+        assert_eq!(vocab.id("("), 2);
+        assert_eq!(vocab.get(")"), None);
+        assert_eq!(vocab.name(1), Some("if"));
+        assert_eq!(vocab.name(3), None);
+        assert_eq!(vocab.kinds().len(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_vocabulary_duplicate() {
+        Vocabulary::new(vec!["if", "if"]);
+    }
 }
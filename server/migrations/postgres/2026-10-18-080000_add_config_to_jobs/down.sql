@@ -0,0 +1 @@
+ALTER TABLE jobs DROP COLUMN config;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE jobs ADD COLUMN config TEXT NOT NULL DEFAULT '{}';
\ No newline at end of file
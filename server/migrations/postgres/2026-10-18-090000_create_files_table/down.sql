@@ -0,0 +1,10 @@
+ALTER TABLE blocks DROP COLUMN left_file_id;
+ALTER TABLE blocks DROP COLUMN right_file_id;
+
+ALTER TABLE submissions ADD COLUMN code TEXT NOT NULL DEFAULT '';
+UPDATE submissions SET code = (
+    SELECT string_agg(code, E'\n' ORDER BY name) FROM files WHERE files.submission_id = submissions.id
+);
+ALTER TABLE submissions ALTER COLUMN code DROP DEFAULT;
+
+DROP TABLE files;
@@ -0,0 +1,20 @@
+CREATE TABLE files (
+    id SERIAL NOT NULL,
+    submission_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+-- existing submissions become single-file submissions
+INSERT INTO files (submission_id, name, code) SELECT id, name, code FROM submissions;
+ALTER TABLE submissions DROP COLUMN code;
+
+ALTER TABLE blocks ADD COLUMN left_file_id INT NOT NULL DEFAULT 0;
+ALTER TABLE blocks ADD COLUMN right_file_id INT NOT NULL DEFAULT 0;
+UPDATE blocks SET left_file_id = files.id FROM matches, files
+    WHERE blocks.match_id = matches.id AND files.submission_id = matches.left_submission_id;
+UPDATE blocks SET right_file_id = files.id FROM matches, files
+    WHERE blocks.match_id = matches.id AND files.submission_id = matches.right_submission_id;
+ALTER TABLE blocks ALTER COLUMN left_file_id DROP DEFAULT;
+ALTER TABLE blocks ALTER COLUMN right_file_id DROP DEFAULT;
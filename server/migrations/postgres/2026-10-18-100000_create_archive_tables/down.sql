@@ -0,0 +1,2 @@
+DROP TABLE archive_fingerprints;
+DROP TABLE archive_submissions;
@@ -0,0 +1,17 @@
+CREATE TABLE archive_submissions (
+    id SERIAL NOT NULL,
+    submission_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    language TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE archive_fingerprints (
+    id SERIAL NOT NULL,
+    archive_submission_id INT NOT NULL,
+    hash BIGINT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX archive_fingerprints_hash ON archive_fingerprints (hash);
@@ -0,0 +1 @@
+ALTER TABLE archive_submissions DROP COLUMN fingerprint_version;
@@ -0,0 +1,2 @@
+-- fingerprints archived before versioning used an unstable hash
+ALTER TABLE archive_submissions ADD COLUMN fingerprint_version INT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+ALTER TABLE blocks DROP COLUMN left_column_from;
+ALTER TABLE blocks DROP COLUMN left_column_to;
+ALTER TABLE blocks DROP COLUMN right_column_from;
+ALTER TABLE blocks DROP COLUMN right_column_to;
@@ -0,0 +1,5 @@
+-- blocks stored before cover whole lines
+ALTER TABLE blocks ADD COLUMN left_column_from INT NOT NULL DEFAULT 0;
+ALTER TABLE blocks ADD COLUMN left_column_to INT NOT NULL DEFAULT 2147483647;
+ALTER TABLE blocks ADD COLUMN right_column_from INT NOT NULL DEFAULT 0;
+ALTER TABLE blocks ADD COLUMN right_column_to INT NOT NULL DEFAULT 2147483647;
@@ -0,0 +1,9 @@
+DROP INDEX jobs_status;
+ALTER TABLE jobs DROP COLUMN status;
+ALTER TABLE jobs DROP COLUMN stage;
+ALTER TABLE jobs DROP COLUMN pairs_done;
+ALTER TABLE jobs DROP COLUMN pairs_total;
+ALTER TABLE jobs DROP COLUMN error;
+ALTER TABLE jobs DROP COLUMN language;
+ALTER TABLE jobs DROP COLUMN template;
+ALTER TABLE jobs DROP COLUMN archive;
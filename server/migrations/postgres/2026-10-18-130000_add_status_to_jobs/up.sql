@@ -0,0 +1,10 @@
+-- jobs stored before ran synchronously and are done
+ALTER TABLE jobs ADD COLUMN status TEXT NOT NULL DEFAULT 'done';
+ALTER TABLE jobs ADD COLUMN stage TEXT;
+ALTER TABLE jobs ADD COLUMN pairs_done INT NOT NULL DEFAULT 0;
+ALTER TABLE jobs ADD COLUMN pairs_total INT NOT NULL DEFAULT 0;
+ALTER TABLE jobs ADD COLUMN error TEXT;
+ALTER TABLE jobs ADD COLUMN language TEXT NOT NULL DEFAULT '';
+ALTER TABLE jobs ADD COLUMN template TEXT;
+ALTER TABLE jobs ADD COLUMN archive TEXT;
+CREATE INDEX jobs_status ON jobs (status);
@@ -0,0 +1,3 @@
+DROP TABLE share_links;
+DROP TABLE job_shares;
+DROP TABLE user_roles;
@@ -0,0 +1,28 @@
+CREATE TABLE user_roles (
+    id SERIAL NOT NULL,
+    user_id INT NOT NULL,
+    role TEXT NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (user_id, role)
+);
+
+-- job is shared with either a user or everyone of a role
+CREATE TABLE job_shares (
+    id SERIAL NOT NULL,
+    job_id INT NOT NULL,
+    user_id INT,
+    role TEXT,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX job_shares_job_id ON job_shares (job_id);
+
+CREATE TABLE share_links (
+    id SERIAL NOT NULL,
+    job_id INT NOT NULL,
+    token TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id),
+    UNIQUE (token)
+);
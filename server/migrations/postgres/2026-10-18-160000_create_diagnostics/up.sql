@@ -0,0 +1,11 @@
+-- problems found while tokenizing files of submissions
+CREATE TABLE diagnostics (
+    id SERIAL NOT NULL,
+    submission_id INT NOT NULL,
+    file TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX diagnostics_submission_id ON diagnostics (submission_id);
@@ -0,0 +1,2 @@
+-- derived template of code shared by many submissions
+ALTER TABLE jobs ADD COLUMN common_code TEXT;
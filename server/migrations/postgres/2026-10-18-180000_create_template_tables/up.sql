@@ -0,0 +1,30 @@
+-- named templates of a user, reusable across jobs
+CREATE TABLE templates (
+    id SERIAL NOT NULL,
+    owner_user_id INT NOT NULL,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX templates_owner_user_id ON templates (owner_user_id);
+
+CREATE TABLE template_files (
+    id SERIAL NOT NULL,
+    template_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX template_files_template_id ON template_files (template_id);
+
+-- templates of the library used by a job
+CREATE TABLE job_templates (
+    id SERIAL NOT NULL,
+    job_id INT NOT NULL,
+    template_id INT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX job_templates_job_id ON job_templates (job_id);
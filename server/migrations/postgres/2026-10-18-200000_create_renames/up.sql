@@ -0,0 +1,14 @@
+-- identifiers consistently renamed between the sides of a block
+CREATE TABLE renames (
+    id SERIAL NOT NULL,
+    block_id INT NOT NULL,
+    left_name TEXT NOT NULL,
+    right_name TEXT NOT NULL,
+    count INT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX renames_block_id ON renames (block_id);
+
+-- blocks found before renames were recorded have none
+ALTER TABLE blocks ADD COLUMN rename_consistency INT NOT NULL DEFAULT 100;
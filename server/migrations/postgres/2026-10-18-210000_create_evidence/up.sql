@@ -0,0 +1,14 @@
+-- rare comments and literals shared by the sides of a match
+CREATE TABLE evidence (
+    id SERIAL NOT NULL,
+    match_id INT NOT NULL,
+    kind TEXT NOT NULL,
+    text TEXT NOT NULL,
+    left_file_id INT NOT NULL,
+    left_line INT NOT NULL,
+    right_file_id INT NOT NULL,
+    right_line INT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX evidence_match_id ON evidence (match_id);
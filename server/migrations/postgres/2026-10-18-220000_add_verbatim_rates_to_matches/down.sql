@@ -0,0 +1,2 @@
+ALTER TABLE matches DROP COLUMN left_verbatim_rate;
+ALTER TABLE matches DROP COLUMN right_verbatim_rate;
@@ -0,0 +1,6 @@
+ALTER TABLE matches DROP COLUMN left_token_rate;
+ALTER TABLE matches DROP COLUMN right_token_rate;
+ALTER TABLE matches DROP COLUMN similarity;
+ALTER TABLE matches DROP COLUMN left_containment;
+ALTER TABLE matches DROP COLUMN right_containment;
+ALTER TABLE matches DROP COLUMN longest_block;
@@ -0,0 +1,10 @@
+-- files of the template given with a job, kept apart so that no match spans two of them
+CREATE TABLE job_template_files (
+    id SERIAL NOT NULL,
+    job_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX job_template_files_job_id ON job_template_files (job_id);
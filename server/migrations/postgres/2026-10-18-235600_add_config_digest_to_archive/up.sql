@@ -0,0 +1,3 @@
+-- digest of the detection parameters fingerprints were computed with,
+-- fingerprints archived without it are never compared
+ALTER TABLE archive_submissions ADD COLUMN config_digest BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+DROP TABLE share_links;
+DROP TABLE job_shares;
+DROP TABLE user_roles;
+DROP TABLE archive_fingerprints;
+DROP TABLE archive_submissions;
+DROP TABLE blocks;
+DROP TABLE matches;
+DROP TABLE files;
+DROP TABLE submissions;
+DROP TABLE jobs;
+DROP TABLE users;
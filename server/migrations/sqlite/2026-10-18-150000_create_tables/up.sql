@@ -0,0 +1,106 @@
+-- same schema as all postgres migrations up to this date
+CREATE TABLE users (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_name TEXT NOT NULL,
+    salt BLOB NOT NULL,
+    password BLOB NOT NULL,
+    UNIQUE (user_name)
+);
+
+CREATE TABLE jobs (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    creator_user_id INT NOT NULL,
+    slug TEXT NOT NULL,
+    config TEXT NOT NULL DEFAULT '{}',
+    status TEXT NOT NULL DEFAULT 'done',
+    stage TEXT,
+    pairs_done INT NOT NULL DEFAULT 0,
+    pairs_total INT NOT NULL DEFAULT 0,
+    error TEXT,
+    language TEXT NOT NULL DEFAULT '',
+    template TEXT,
+    archive TEXT
+);
+
+CREATE INDEX jobs_status ON jobs (status);
+
+CREATE TABLE submissions (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE files (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    submission_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL
+);
+
+CREATE TABLE matches (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    left_submission_id INT NOT NULL,
+    left_match_rate INT NOT NULL,
+    right_submission_id INT NOT NULL,
+    right_match_rate INT NOT NULL,
+    lines_matched INT NOT NULL
+);
+
+CREATE TABLE blocks (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    match_id INT NOT NULL,
+    left_line_from INT NOT NULL,
+    left_line_to INT NOT NULL,
+    right_line_from INT NOT NULL,
+    right_line_to INT NOT NULL,
+    left_file_id INT NOT NULL,
+    right_file_id INT NOT NULL,
+    left_column_from INT NOT NULL DEFAULT 0,
+    left_column_to INT NOT NULL DEFAULT 2147483647,
+    right_column_from INT NOT NULL DEFAULT 0,
+    right_column_to INT NOT NULL DEFAULT 2147483647
+);
+
+CREATE TABLE archive_submissions (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    submission_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    language TEXT NOT NULL,
+    label TEXT NOT NULL,
+    fingerprint_version INT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE archive_fingerprints (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    archive_submission_id INT NOT NULL,
+    hash BIGINT NOT NULL
+);
+
+CREATE INDEX archive_fingerprints_hash ON archive_fingerprints (hash);
+
+CREATE TABLE user_roles (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INT NOT NULL,
+    role TEXT NOT NULL,
+    UNIQUE (user_id, role)
+);
+
+-- job is shared with either a user or everyone of a role
+CREATE TABLE job_shares (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    user_id INT,
+    role TEXT
+);
+
+CREATE INDEX job_shares_job_id ON job_shares (job_id);
+
+CREATE TABLE share_links (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    token TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    UNIQUE (token)
+);
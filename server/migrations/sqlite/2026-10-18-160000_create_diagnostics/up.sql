@@ -0,0 +1,10 @@
+-- problems found while tokenizing files of submissions
+CREATE TABLE diagnostics (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    submission_id INT NOT NULL,
+    file TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL
+);
+
+CREATE INDEX diagnostics_submission_id ON diagnostics (submission_id);
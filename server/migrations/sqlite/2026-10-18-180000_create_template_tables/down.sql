@@ -0,0 +1,3 @@
+DROP TABLE job_templates;
+DROP TABLE template_files;
+DROP TABLE templates;
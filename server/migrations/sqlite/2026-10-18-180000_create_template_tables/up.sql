@@ -0,0 +1,27 @@
+-- named templates of a user, reusable across jobs
+CREATE TABLE templates (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    owner_user_id INT NOT NULL,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL
+);
+
+CREATE INDEX templates_owner_user_id ON templates (owner_user_id);
+
+CREATE TABLE template_files (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    template_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL
+);
+
+CREATE INDEX template_files_template_id ON template_files (template_id);
+
+-- templates of the library used by a job
+CREATE TABLE job_templates (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    template_id INT NOT NULL
+);
+
+CREATE INDEX job_templates_job_id ON job_templates (job_id);
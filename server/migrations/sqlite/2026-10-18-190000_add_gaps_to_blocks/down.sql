@@ -0,0 +1,2 @@
+ALTER TABLE blocks DROP COLUMN left_gap;
+ALTER TABLE blocks DROP COLUMN right_gap;
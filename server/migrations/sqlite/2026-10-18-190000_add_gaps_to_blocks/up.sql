@@ -0,0 +1,3 @@
+-- unmatched tokens within blocks merged across gaps
+ALTER TABLE blocks ADD COLUMN left_gap INT NOT NULL DEFAULT 0;
+ALTER TABLE blocks ADD COLUMN right_gap INT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE blocks DROP COLUMN rename_consistency;
+DROP TABLE renames;
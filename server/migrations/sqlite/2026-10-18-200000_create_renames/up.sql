@@ -0,0 +1,13 @@
+-- identifiers consistently renamed between the sides of a block
+CREATE TABLE renames (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    block_id INT NOT NULL,
+    left_name TEXT NOT NULL,
+    right_name TEXT NOT NULL,
+    count INT NOT NULL
+);
+
+CREATE INDEX renames_block_id ON renames (block_id);
+
+-- blocks found before renames were recorded have none
+ALTER TABLE blocks ADD COLUMN rename_consistency INT NOT NULL DEFAULT 100;
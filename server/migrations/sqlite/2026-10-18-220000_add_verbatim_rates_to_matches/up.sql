@@ -0,0 +1,3 @@
+-- percentage of lines copied with the same spellings, 0 if not computed
+ALTER TABLE matches ADD COLUMN left_verbatim_rate INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN right_verbatim_rate INT NOT NULL DEFAULT 0;
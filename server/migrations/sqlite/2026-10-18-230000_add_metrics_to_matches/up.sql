@@ -0,0 +1,7 @@
+-- similarity metrics besides matched lines, 0 for matches found before
+ALTER TABLE matches ADD COLUMN left_token_rate INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN right_token_rate INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN similarity INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN left_containment INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN right_containment INT NOT NULL DEFAULT 0;
+ALTER TABLE matches ADD COLUMN longest_block INT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- sum of rarity weights of shared fingerprints in thousandths, matches are ranked by it
+ALTER TABLE matches ADD COLUMN score BIGINT NOT NULL DEFAULT 0;
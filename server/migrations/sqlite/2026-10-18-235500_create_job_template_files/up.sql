@@ -0,0 +1,9 @@
+-- files of the template given with a job, kept apart so that no match spans two of them
+CREATE TABLE job_template_files (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    job_id INT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL
+);
+
+CREATE INDEX job_template_files_job_id ON job_template_files (job_id);
@@ -0,0 +1 @@
+ALTER TABLE archive_submissions DROP COLUMN config_digest;
@@ -0,0 +1,262 @@
+use crate::{
+    common::{err, generate_uuid},
+    db::{DbConnection, DbPool},
+    models::{Job, NewJobShare, NewShareLink, ShareLink, User},
+    session::authenticate,
+};
+use api::{
+    def::{LoginRequest, RevokeLinkRequest, ShareLinkRequest, ShareLinkResponse, ShareRequest},
+    env::ENV,
+};
+
+use actix_session::Session;
+use actix_web::{post, web, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use log::*;
+use serde::Deserialize;
+
+// longest lifetime of share links
+const MAX_LINK_HOURS: u32 = 24 * 30;
+
+/// Query string of result pages
+#[derive(Deserialize, Debug)]
+pub struct AccessQuery {
+    // share link token
+    pub token: Option<String>,
+}
+
+// session key remembering share link of job, so that frames need no token
+fn session_key(job: &Job) -> String {
+    format!("share:{}", job.slug)
+}
+
+fn valid_link(conn: &mut DbConnection, job: &Job, token: &str) -> QueryResult<bool> {
+    use crate::schema::share_links::dsl;
+    let link = dsl::share_links
+        .filter(dsl::job_id.eq(job.id))
+        .filter(dsl::token.eq(token))
+        .first::<ShareLink>(conn)
+        .optional()?;
+    Ok(match link {
+        Some(link) => !link.revoked && link.expires_at > Utc::now().naive_utc(),
+        None => false,
+    })
+}
+
+/// Whether user may view results of job, as owner or via shares
+pub fn is_shared_with(conn: &mut DbConnection, job: &Job, user_id: i32) -> QueryResult<bool> {
+    use crate::schema::job_shares::dsl as shares;
+    use crate::schema::user_roles::dsl as roles;
+
+    if job.creator_user_id == user_id {
+        return Ok(true);
+    }
+    let user_roles: Vec<String> = roles::user_roles
+        .filter(roles::user_id.eq(user_id))
+        .select(roles::role)
+        .load(conn)?;
+    let shares: i64 = shares::job_shares
+        .filter(shares::job_id.eq(job.id))
+        .filter(
+            shares::user_id
+                .eq(user_id)
+                .or(shares::role.eq_any(user_roles)),
+        )
+        .count()
+        .get_result(conn)?;
+    Ok(shares > 0)
+}
+
+/// Check access to results of job by session or share link
+///
+/// A valid token is remembered in session for the pages linked from results.
+pub fn can_view(
+    conn: &mut DbConnection,
+    session: &Session,
+    job: &Job,
+    query: &AccessQuery,
+) -> Result<bool> {
+    if let Some(user_id) = session.get::<i32>("id")? {
+        if is_shared_with(conn, job, user_id).map_err(err)? {
+            return Ok(true);
+        }
+    }
+    if let Some(token) = &query.token {
+        if valid_link(conn, job, token).map_err(err)? {
+            session.insert(session_key(job), token)?;
+            return Ok(true);
+        }
+    }
+    if let Some(token) = session.get::<String>(&session_key(job))? {
+        if valid_link(conn, job, &token).map_err(err)? {
+            return Ok(true);
+        }
+        session.remove(&session_key(job));
+    }
+    Ok(false)
+}
+
+/// Load job of slug created by the authenticated user
+fn load_own_job(
+    session: &Session,
+    conn: &mut DbConnection,
+    slug: &str,
+    login: &Option<LoginRequest>,
+) -> Result<Option<Job>> {
+    use crate::schema::jobs::dsl;
+    let user_id = match authenticate(session, conn, login)? {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+    dsl::jobs
+        .filter(dsl::slug.eq(slug))
+        .filter(dsl::creator_user_id.eq(user_id))
+        .first::<Job>(conn)
+        .optional()
+        .map_err(err)
+}
+
+/// Look up target of share request, `None` if the request is invalid
+fn share_target(
+    conn: &mut DbConnection,
+    job: &Job,
+    body: &ShareRequest,
+) -> Result<Option<NewJobShare>> {
+    use crate::schema::users::dsl;
+    Ok(match (&body.user_name, &body.role) {
+        (Some(user_name), None) => dsl::users
+            .filter(dsl::user_name.eq(user_name))
+            .first::<User>(conn)
+            .optional()
+            .map_err(err)?
+            .map(|user| NewJobShare {
+                job_id: job.id,
+                user_id: Some(user.id),
+                role: None,
+            }),
+        (None, Some(role)) => Some(NewJobShare {
+            job_id: job.id,
+            user_id: None,
+            role: Some(role.clone()),
+        }),
+        _ => None,
+    })
+}
+
+#[post("/jobs/{slug}/share")]
+pub async fn share(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    body: web::Json<ShareRequest>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_own_job(&session, &mut conn, &slug, &body.login)? {
+        Some(job) => job,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    let target = match share_target(&mut conn, &job, &body)? {
+        Some(target) => target,
+        None => return Ok(HttpResponse::BadRequest().json(false)),
+    };
+    info!("Job {} shared with {:?}", slug, target);
+    diesel::insert_into(crate::schema::job_shares::table)
+        .values(&target)
+        .execute(&mut conn)
+        .map_err(err)?;
+    Ok(HttpResponse::Ok().json(true))
+}
+
+#[post("/jobs/{slug}/unshare")]
+pub async fn unshare(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    body: web::Json<ShareRequest>,
+) -> Result<HttpResponse> {
+    use crate::schema::job_shares::dsl;
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_own_job(&session, &mut conn, &slug, &body.login)? {
+        Some(job) => job,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    let target = match share_target(&mut conn, &job, &body)? {
+        Some(target) => target,
+        None => return Ok(HttpResponse::BadRequest().json(false)),
+    };
+    let query = dsl::job_shares.filter(dsl::job_id.eq(job.id));
+    let deleted = match (target.user_id, target.role) {
+        (Some(user_id), _) => diesel::delete(query.filter(dsl::user_id.eq(user_id)))
+            .execute(&mut conn)
+            .map_err(err)?,
+        (_, Some(role)) => diesel::delete(query.filter(dsl::role.eq(role)))
+            .execute(&mut conn)
+            .map_err(err)?,
+        _ => 0,
+    };
+    Ok(HttpResponse::Ok().json(deleted > 0))
+}
+
+#[post("/jobs/{slug}/links")]
+pub async fn create_link(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    body: web::Json<ShareLinkRequest>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_own_job(&session, &mut conn, &slug, &body.login)? {
+        Some(job) => job,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    if body.hours == 0 || body.hours > MAX_LINK_HOURS {
+        return Ok(HttpResponse::BadRequest()
+            .json(format!("hours must be in range [1, {}]", MAX_LINK_HOURS)));
+    }
+    let link = NewShareLink {
+        job_id: job.id,
+        token: generate_uuid(),
+        expires_at: Utc::now().naive_utc() + Duration::hours(body.hours as i64),
+    };
+    diesel::insert_into(crate::schema::share_links::table)
+        .values(&link)
+        .execute(&mut conn)
+        .map_err(err)?;
+    info!(
+        "Share link of job {} created, expires at {}",
+        slug, link.expires_at
+    );
+    Ok(HttpResponse::Ok().json(ShareLinkResponse {
+        url: format!(
+            "{}/results/{}/?token={}",
+            ENV.public_url, job.slug, link.token
+        ),
+        token: link.token,
+        expires_at: link.expires_at.and_utc().to_rfc3339(),
+    }))
+}
+
+#[post("/jobs/{slug}/links/revoke")]
+pub async fn revoke_link(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    body: web::Json<RevokeLinkRequest>,
+) -> Result<HttpResponse> {
+    use crate::schema::share_links::dsl;
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_own_job(&session, &mut conn, &slug, &body.login)? {
+        Some(job) => job,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    let revoked = diesel::update(
+        dsl::share_links
+            .filter(dsl::job_id.eq(job.id))
+            .filter(dsl::token.eq(&body.token)),
+    )
+    .set(dsl::revoked.eq(true))
+    .execute(&mut conn)
+    .map_err(err)?;
+    Ok(HttpResponse::Ok().json(revoked > 0))
+}
@@ -1,6 +1,6 @@
 use api::env::ENV;
 use clap::Parser;
-use diesel::{Connection, RunQueryDsl};
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
 use dotenv::dotenv;
 
 #[derive(Parser)]
@@ -13,6 +13,10 @@ struct Args {
 
     #[arg(short, long)]
     force: bool,
+
+    /// Roles of user, e.g. course staff that jobs are shared with. Replaces existing roles
+    #[arg(short, long)]
+    role: Vec<String>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -20,24 +24,43 @@ fn main() -> anyhow::Result<()> {
     dotenv().ok();
     let url = ENV.database_url.clone();
     let mut conn = server::db::DbConnection::establish(&url)?;
+    server::db::run_migrations(&mut conn)?;
 
     let (salt, hash) = server::session::hash(&args.password)?;
     let new_user = server::models::NewUser {
-        user_name: args.user_name,
+        user_name: args.user_name.clone(),
         salt: Vec::from(salt),
         password: Vec::from(hash),
     };
-    if args.force {
+    let user_id: i32 = if args.force {
         diesel::insert_into(server::schema::users::table)
             .values(&new_user)
             .on_conflict(server::schema::users::user_name)
             .do_update()
             .set(&new_user)
-            .execute(&mut conn)?;
+            .returning(server::schema::users::id)
+            .get_result(&mut conn)?
     } else {
         diesel::insert_into(server::schema::users::table)
             .values(&new_user)
-            .execute(&mut conn)?;
-    }
+            .returning(server::schema::users::id)
+            .get_result(&mut conn)?
+    };
+
+    diesel::delete(
+        server::schema::user_roles::table.filter(server::schema::user_roles::user_id.eq(user_id)),
+    )
+    .execute(&mut conn)?;
+    let roles: Vec<server::models::NewUserRole> = args
+        .role
+        .iter()
+        .map(|role| server::models::NewUserRole {
+            user_id,
+            role: role.clone(),
+        })
+        .collect();
+    diesel::insert_into(server::schema::user_roles::table)
+        .values(roles)
+        .execute(&mut conn)?;
     Ok(())
 }
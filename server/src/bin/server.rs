@@ -6,21 +6,12 @@ use actix_web::{
     web::{self, JsonConfig},
     App, HttpServer,
 };
-use diesel::r2d2::{ConnectionManager, Pool};
-use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
 use dotenv::dotenv;
 use log::*;
 use ring::digest;
 
 use api::env::ENV;
-use server::{
-    db::DbConnection,
-    render::{render_job, render_match, render_match_frame},
-    session::login,
-    submit::submit,
-};
-
-pub const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!();
+use server::{db::connect, queue::JobQueue, routes::configure};
 
 #[actix_rt::main]
 async fn main() -> anyhow::Result<()> {
@@ -28,11 +19,10 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     info!("Setup DB");
-    let url = ENV.database_url.clone();
-    let manager = ConnectionManager::<DbConnection>::new(url);
-    let pool = Pool::builder().build(manager)?;
-    let mut conn = pool.get()?;
-    conn.run_pending_migrations(MIGRATIONS).unwrap();
+    let pool = connect(&ENV.database_url)?;
+
+    info!("Setup {} workers", ENV.workers);
+    let queue = JobQueue::start(pool.clone(), ENV.workers)?;
 
     info!("Setup Server");
     let secret = ENV.cookie_secret.clone();
@@ -41,6 +31,7 @@ async fn main() -> anyhow::Result<()> {
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(queue.clone()))
             .app_data(web::Data::new(
                 JsonConfig::default().limit(32 * 1024 * 1024 * 1024),
             )) // Enlarge body size limit
@@ -55,13 +46,7 @@ async fn main() -> anyhow::Result<()> {
                 .build(),
             )
             .wrap(middleware::Logger::default())
-            .service(
-                web::scope(uri.path())
-                    .service(web::scope("/api").service(login).service(submit))
-                    .service(render_match)
-                    .service(render_match_frame)
-                    .service(render_job),
-            )
+            .service(web::scope(uri.path()).configure(configure))
     })
     .bind("0.0.0.0:8765")?
     .run()
@@ -1,5 +1,50 @@
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
 
+#[cfg(not(feature = "sqlite"))]
 pub type DbConnection = PgConnection;
-pub(crate) type DbPool = Pool<ConnectionManager<DbConnection>>;
+#[cfg(feature = "sqlite")]
+pub type DbConnection = SqliteConnection;
+
+pub type DbPool = Pool<ConnectionManager<DbConnection>>;
+
+#[cfg(not(feature = "sqlite"))]
+pub const MIGRATIONS: EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("migrations/postgres");
+#[cfg(feature = "sqlite")]
+pub const MIGRATIONS: EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("migrations/sqlite");
+
+/// Bring database schema up to date
+pub fn run_migrations(conn: &mut DbConnection) -> anyhow::Result<()> {
+    conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| anyhow::anyhow!("Failed to run migrations: {}", e))?;
+    Ok(())
+}
+
+/// Wait for locks held by other connections instead of failing,
+/// since workers and requests write to the same file
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqliteOptions;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqliteOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+        conn.batch_execute("PRAGMA busy_timeout = 10000; PRAGMA journal_mode = WAL;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Connect to database and run pending migrations
+pub fn connect(url: &str) -> anyhow::Result<DbPool> {
+    let manager = ConnectionManager::<DbConnection>::new(url);
+    let builder = Pool::builder();
+    #[cfg(feature = "sqlite")]
+    let builder = builder.connection_customizer(Box::new(SqliteOptions));
+    let pool = builder.build(manager)?;
+    run_migrations(&mut *pool.get()?)?;
+    Ok(pool)
+}
@@ -0,0 +1,84 @@
+use crate::{
+    access::{can_view, AccessQuery},
+    common::err,
+    db::DbPool,
+    models::Job,
+    queue::JobQueue,
+    session::authenticate,
+    work::parse_debug,
+};
+use api::def::{CancelRequest, JobState, JobStatus};
+
+use actix_session::Session;
+use actix_web::{get, post, web, HttpResponse, Result};
+use diesel::prelude::*;
+use log::*;
+
+/// Convert job in database to its status
+pub fn job_status(job: &Job) -> anyhow::Result<JobStatus> {
+    Ok(JobStatus {
+        slug: job.slug.clone(),
+        state: job.status.parse().map_err(anyhow::Error::msg)?,
+        stage: job.stage.as_deref().map(parse_debug).transpose()?,
+        pairs_done: job.pairs_done,
+        pairs_total: job.pairs_total,
+        error: job.error.clone(),
+    })
+}
+
+#[get("/jobs/{slug}")]
+pub async fn status(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    access: web::Query<AccessQuery>,
+) -> Result<HttpResponse> {
+    use crate::schema::jobs::dsl;
+    let mut conn = pool.get().map_err(err)?;
+    let job = match dsl::jobs
+        .filter(dsl::slug.eq(&*slug))
+        .first::<Job>(&mut conn)
+        .optional()
+        .map_err(err)?
+    {
+        Some(job) => job,
+        None => return Ok(HttpResponse::NotFound().json(false)),
+    };
+    // errors may quote code of students
+    if !can_view(&mut conn, &session, &job, &access)? {
+        return Ok(HttpResponse::Forbidden().json(false));
+    }
+    Ok(HttpResponse::Ok().json(job_status(&job).map_err(err)?))
+}
+
+#[post("/jobs/{slug}/cancel")]
+pub async fn cancel(
+    session: Session,
+    pool: web::Data<DbPool>,
+    queue: web::Data<JobQueue>,
+    slug: web::Path<String>,
+    body: web::Json<CancelRequest>,
+) -> Result<HttpResponse> {
+    use crate::schema::jobs::dsl;
+    let mut conn = pool.get().map_err(err)?;
+    let user_id = match authenticate(&session, &mut conn, &body.login)? {
+        Some(user_id) => user_id,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    // only unfinished jobs of the creator can be cancelled
+    let job_ids: Vec<i32> = diesel::update(
+        dsl::jobs
+            .filter(dsl::slug.eq(&*slug))
+            .filter(dsl::creator_user_id.eq(user_id))
+            .filter(dsl::status.eq_any([JobState::Queued.as_str(), JobState::Running.as_str()])),
+    )
+    .set(dsl::status.eq(JobState::Cancelled.as_str()))
+    .returning(dsl::id)
+    .get_results(&mut conn)
+    .map_err(err)?;
+    for job_id in &job_ids {
+        queue.cancel(*job_id);
+        info!("Job {} cancelled by {}", slug, user_id);
+    }
+    Ok(HttpResponse::Ok().json(!job_ids.is_empty()))
+}
@@ -1,11 +1,17 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod access;
 pub mod common;
 pub mod db;
+pub mod job;
 pub mod models;
+pub mod queue;
 pub mod render;
+pub mod results;
+pub mod routes;
 pub mod schema;
 pub mod session;
 pub mod submit;
+pub mod template;
 pub mod work;
@@ -1,4 +1,9 @@
-use crate::schema::{blocks, jobs, matches, submissions, users};
+use crate::schema::{
+    archive_fingerprints, archive_submissions, blocks, diagnostics, evidence, files, job_shares,
+    job_template_files, job_templates, jobs, matches, renames, share_links, submissions,
+    template_files, templates, user_roles, users,
+};
+use chrono::NaiveDateTime;
 
 #[derive(Debug, Insertable, AsChangeset)]
 #[diesel(table_name = users)]
@@ -21,6 +26,15 @@ pub struct User {
 pub struct NewJob {
     pub creator_user_id: i32,
     pub slug: String,
+    // serialized DetectionConfig
+    pub config: String,
+    // serialized JobState
+    pub status: String,
+    // Debug of Language
+    pub language: String,
+    pub template: Option<String>,
+    // archive label
+    pub archive: Option<String>,
 }
 
 #[derive(Debug, Queryable)]
@@ -28,6 +42,22 @@ pub struct Job {
     pub id: i32,
     pub creator_user_id: i32,
     pub slug: String,
+    // serialized DetectionConfig
+    pub config: String,
+    // serialized JobState
+    pub status: String,
+    // Debug of Stage
+    pub stage: Option<String>,
+    pub pairs_done: i32,
+    pub pairs_total: i32,
+    pub error: Option<String>,
+    // Debug of Language
+    pub language: String,
+    pub template: Option<String>,
+    // archive label
+    pub archive: Option<String>,
+    // derived template of code shared by many submissions
+    pub common_code: Option<String>,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -39,6 +69,15 @@ pub struct NewMatch {
     pub right_submission_id: i32,
     pub right_match_rate: i32,
     pub lines_matched: i32,
+    pub left_verbatim_rate: i32,
+    pub right_verbatim_rate: i32,
+    pub left_token_rate: i32,
+    pub right_token_rate: i32,
+    pub similarity: i32,
+    pub left_containment: i32,
+    pub right_containment: i32,
+    pub longest_block: i32,
+    pub score: i64,
 }
 
 #[derive(Debug, Queryable)]
@@ -50,6 +89,15 @@ pub struct Match {
     pub right_submission_id: i32,
     pub right_match_rate: i32,
     pub lines_matched: i32,
+    pub left_verbatim_rate: i32,
+    pub right_verbatim_rate: i32,
+    pub left_token_rate: i32,
+    pub right_token_rate: i32,
+    pub similarity: i32,
+    pub left_containment: i32,
+    pub right_containment: i32,
+    pub longest_block: i32,
+    pub score: i64,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -61,6 +109,18 @@ pub struct NewBlock {
     pub left_line_to: i32,
     pub right_line_from: i32,
     pub right_line_to: i32,
+    pub left_file_id: i32,
+    pub right_file_id: i32,
+    // 0-based character columns, `to` is exclusive
+    pub left_column_from: i32,
+    pub left_column_to: i32,
+    pub right_column_from: i32,
+    pub right_column_to: i32,
+    // unmatched tokens within the block
+    pub left_gap: i32,
+    pub right_gap: i32,
+    // percentage of aligned identifiers following the renames
+    pub rename_consistency: i32,
 }
 
 #[derive(Debug, Queryable)]
@@ -72,6 +132,18 @@ pub struct Block {
     pub left_line_to: i32,
     pub right_line_from: i32,
     pub right_line_to: i32,
+    pub left_file_id: i32,
+    pub right_file_id: i32,
+    // 0-based character columns, `to` is exclusive
+    pub left_column_from: i32,
+    pub left_column_to: i32,
+    pub right_column_from: i32,
+    pub right_column_to: i32,
+    // unmatched tokens within the block
+    pub left_gap: i32,
+    pub right_gap: i32,
+    // percentage of aligned identifiers following the renames
+    pub rename_consistency: i32,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -79,7 +151,6 @@ pub struct Block {
 pub struct NewSubmission {
     pub job_id: i32,
     pub name: String,
-    pub code: String,
 }
 
 #[derive(Debug, Queryable)]
@@ -87,5 +158,209 @@ pub struct Submission {
     pub id: i32,
     pub job_id: i32,
     pub name: String,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = files)]
+pub struct NewFile {
+    pub submission_id: i32,
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct File {
+    pub id: i32,
+    pub submission_id: i32,
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = archive_submissions)]
+pub struct NewArchiveSubmission {
+    pub submission_id: i32,
+    pub owner_user_id: i32,
+    pub language: String,
+    pub label: String,
+    pub fingerprint_version: i32,
+    // DetectionConfig::fingerprint_digest
+    pub config_digest: i64,
+}
+
+#[derive(Debug, Queryable)]
+pub struct ArchiveSubmission {
+    pub id: i32,
+    pub submission_id: i32,
+    pub owner_user_id: i32,
+    pub language: String,
+    pub label: String,
+    pub fingerprint_version: i32,
+    // DetectionConfig::fingerprint_digest
+    pub config_digest: i64,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = archive_fingerprints)]
+pub struct NewArchiveFingerprint {
+    pub archive_submission_id: i32,
+    pub hash: i64,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = user_roles)]
+pub struct NewUserRole {
+    pub user_id: i32,
+    pub role: String,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = job_shares)]
+pub struct NewJobShare {
+    pub job_id: i32,
+    // exactly one of user and role is set
+    pub user_id: Option<i32>,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = share_links)]
+pub struct NewShareLink {
+    pub job_id: i32,
+    pub token: String,
+    // UTC
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable)]
+pub struct ShareLink {
+    pub id: i32,
+    pub job_id: i32,
+    pub token: String,
+    // UTC
+    pub expires_at: NaiveDateTime,
+    pub revoked: bool,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = diagnostics)]
+pub struct NewDiagnostic {
+    pub submission_id: i32,
+    pub file: String,
+    // Debug of Severity
+    pub severity: String,
+    pub message: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Diagnostic {
+    pub id: i32,
+    pub submission_id: i32,
+    pub file: String,
+    // Debug of Severity
+    pub severity: String,
+    pub message: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = evidence)]
+pub struct NewEvidence {
+    pub match_id: i32,
+    // Debug of EvidenceKind
+    pub kind: String,
+    pub text: String,
+    // 0-based lines of the first occurrence on each side
+    pub left_file_id: i32,
+    pub left_line: i32,
+    pub right_file_id: i32,
+    pub right_line: i32,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Evidence {
+    pub id: i32,
+    pub match_id: i32,
+    // Debug of EvidenceKind
+    pub kind: String,
+    pub text: String,
+    // 0-based lines of the first occurrence on each side
+    pub left_file_id: i32,
+    pub left_line: i32,
+    pub right_file_id: i32,
+    pub right_line: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = renames)]
+pub struct NewRename {
+    pub block_id: i32,
+    pub left_name: String,
+    pub right_name: String,
+    pub count: i32,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Rename {
+    pub id: i32,
+    pub block_id: i32,
+    pub left_name: String,
+    pub right_name: String,
+    pub count: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = templates)]
+pub struct NewTemplate {
+    pub owner_user_id: i32,
+    pub name: String,
+    // Debug of Language
+    pub language: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Template {
+    pub id: i32,
+    pub owner_user_id: i32,
+    pub name: String,
+    // Debug of Language
+    pub language: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = template_files)]
+pub struct NewTemplateFile {
+    pub template_id: i32,
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct TemplateFile {
+    pub id: i32,
+    pub template_id: i32,
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = job_templates)]
+pub struct NewJobTemplate {
+    pub job_id: i32,
+    pub template_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = job_template_files)]
+pub struct NewJobTemplateFile {
+    pub job_id: i32,
+    pub name: String,
+    pub code: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct JobTemplateFile {
+    pub id: i32,
+    pub job_id: i32,
+    pub name: String,
     pub code: String,
 }
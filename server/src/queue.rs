@@ -0,0 +1,244 @@
+use crate::{
+    db::{DbConnection, DbPool},
+    models::Job,
+    work::{store_result, work_blocking, WorkRequest},
+};
+use api::def::JobState;
+use core::pipeline::{Cancelled, Progress, Stage};
+use diesel::prelude::*;
+use log::*;
+use std::{
+    any::Any,
+    collections::HashSet,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+/// Queue of jobs, processed by a fixed number of worker threads
+pub struct JobQueue {
+    sender: Mutex<Sender<i32>>,
+    // running jobs to be stopped
+    cancelled: Mutex<HashSet<i32>>,
+}
+
+impl JobQueue {
+    /// Start workers, and resume jobs left unfinished by previous run of server
+    pub fn start(pool: DbPool, workers: usize) -> anyhow::Result<Arc<Self>> {
+        let (sender, receiver) = channel();
+        let queue = Arc::new(Self {
+            sender: Mutex::new(sender),
+            cancelled: Mutex::new(HashSet::new()),
+        });
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers.max(1) {
+            let pool = pool.clone();
+            let queue = queue.clone();
+            let receiver = receiver.clone();
+            std::thread::spawn(move || worker(pool, queue, receiver));
+        }
+
+        use crate::schema::jobs::dsl;
+        let mut conn = pool.get()?;
+        diesel::update(dsl::jobs.filter(dsl::status.eq(JobState::Running.as_str())))
+            .set(dsl::status.eq(JobState::Queued.as_str()))
+            .execute(&mut conn)?;
+        let job_ids: Vec<i32> = dsl::jobs
+            .filter(dsl::status.eq(JobState::Queued.as_str()))
+            .order(dsl::id)
+            .select(dsl::id)
+            .load(&mut conn)?;
+        if !job_ids.is_empty() {
+            info!("Resuming {} jobs", job_ids.len());
+        }
+        for job_id in job_ids {
+            queue.push(job_id);
+        }
+        Ok(queue)
+    }
+
+    /// Enqueue a job in queued state
+    pub fn push(&self, job_id: i32) {
+        // workers never exit, so sending cannot fail
+        self.sender.lock().unwrap().send(job_id).unwrap();
+    }
+
+    /// Ask the worker running the job to stop
+    pub fn cancel(&self, job_id: i32) {
+        self.cancelled.lock().unwrap().insert(job_id);
+    }
+
+    fn is_cancelled(&self, job_id: i32) -> bool {
+        self.cancelled.lock().unwrap().contains(&job_id)
+    }
+}
+
+fn worker(pool: DbPool, queue: Arc<JobQueue>, receiver: Arc<Mutex<Receiver<i32>>>) {
+    loop {
+        let job_id = match receiver.lock().unwrap().recv() {
+            Ok(job_id) => job_id,
+            Err(_) => return,
+        };
+        // a panic, e.g. of a tokenizer on malformed input, fails the job but not the worker
+        let res = catch_unwind(AssertUnwindSafe(|| run_job(&pool, &queue, job_id)))
+            .unwrap_or_else(|panic| Err(anyhow::anyhow!("panicked: {}", panic_message(&*panic))));
+        if let Err(e) = res {
+            error!("Job {} failed: {}", job_id, e);
+            if let Ok(mut conn) = pool.get() {
+                set_state(&mut conn, job_id, JobState::Failed, Some(format!("{}", e))).ok();
+            }
+        }
+        queue.cancelled.lock().unwrap().remove(&job_id);
+    }
+}
+
+/// Message of a caught panic, as printed by the default hook
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+        .unwrap_or("Box<dyn Any>")
+}
+
+fn set_state(
+    conn: &mut DbConnection,
+    job_id: i32,
+    state: JobState,
+    error: Option<String>,
+) -> QueryResult<usize> {
+    use crate::schema::jobs::dsl;
+    // jobs cancelled by user stay cancelled
+    diesel::update(
+        dsl::jobs
+            .filter(dsl::id.eq(job_id))
+            .filter(dsl::status.eq(JobState::Running.as_str())),
+    )
+    .set((dsl::status.eq(state.as_str()), dsl::error.eq(error)))
+    .execute(conn)
+}
+
+fn run_job(pool: &DbPool, queue: &JobQueue, job_id: i32) -> anyhow::Result<()> {
+    use crate::schema::jobs::dsl;
+    let mut conn = pool.get()?;
+
+    // claim the job, skipped if cancelled while queued
+    let claimed = diesel::update(
+        dsl::jobs
+            .filter(dsl::id.eq(job_id))
+            .filter(dsl::status.eq(JobState::Queued.as_str())),
+    )
+    .set(dsl::status.eq(JobState::Running.as_str()))
+    .execute(&mut conn)?;
+    if claimed == 0 {
+        return Ok(());
+    }
+    let job: Job = dsl::jobs.filter(dsl::id.eq(job_id)).first(&mut conn)?;
+    info!("Job {} started", job.slug);
+
+    let req = WorkRequest::load(&mut conn, &job)?;
+    // not held while the job runs, the pipeline and progress take their own
+    drop(conn);
+    let progress = JobProgress {
+        pool,
+        queue,
+        job_id,
+    };
+    let work = work_blocking(&req, pool, &progress);
+    let mut conn = pool.get()?;
+    match work {
+        Ok(work) => {
+            let done = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                store_result(conn, &req, &work)?;
+                if set_state(conn, job_id, JobState::Done, None)? == 0 {
+                    // cancelled meanwhile, discard results
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                Ok(())
+            });
+            match done {
+                Ok(()) => info!("Job {} done", job.slug),
+                Err(diesel::result::Error::RollbackTransaction) => {
+                    info!("Job {} cancelled", job.slug)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(e) if e.is::<Cancelled>() => {
+            set_state(&mut conn, job_id, JobState::Cancelled, None)?;
+            info!("Job {} cancelled", job.slug);
+        }
+        Err(e) => {
+            warn!("Job {} failed: {:#}", job.slug, e);
+            set_state(
+                &mut conn,
+                job_id,
+                JobState::Failed,
+                Some(format!("{:#}", e)),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Records progress of running job in database
+struct JobProgress<'a> {
+    pool: &'a DbPool,
+    queue: &'a JobQueue,
+    job_id: i32,
+}
+
+impl JobProgress<'_> {
+    fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut DbConnection) -> QueryResult<usize>,
+    {
+        let res = self
+            .pool
+            .get()
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn| f(&mut conn).map_err(|e| e.to_string()));
+        if let Err(e) = res {
+            warn!("Failed to update progress of job {}: {}", self.job_id, e);
+        }
+    }
+}
+
+impl Progress for JobProgress<'_> {
+    fn stage(&self, stage: Stage) {
+        use crate::schema::jobs::dsl;
+        self.update(|conn| {
+            diesel::update(dsl::jobs.filter(dsl::id.eq(self.job_id)))
+                .set(dsl::stage.eq(format!("{:?}", stage)))
+                .execute(conn)
+        });
+    }
+
+    fn pairs(&self, done: usize, total: usize) {
+        use crate::schema::jobs::dsl;
+        // limit to about a hundred writes per job
+        let step = (total / 100).max(1);
+        if !done.is_multiple_of(step) && done != total {
+            return;
+        }
+        self.update(|conn| {
+            // pairs finish concurrently, never move backwards
+            diesel::update(
+                dsl::jobs
+                    .filter(dsl::id.eq(self.job_id))
+                    .filter(dsl::pairs_done.le(done as i32)),
+            )
+            .set((
+                dsl::pairs_done.eq(done as i32),
+                dsl::pairs_total.eq(total as i32),
+            ))
+            .execute(conn)
+        });
+    }
+
+    fn cancelled(&self) -> bool {
+        self.queue.is_cancelled(self.job_id)
+    }
+}
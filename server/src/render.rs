@@ -1,16 +1,147 @@
 use crate::{
+    access::{can_view, AccessQuery},
     common::err,
-    db::DbPool,
-    models::{Block, Job, Match, Submission},
+    db::{DbConnection, DbPool},
+    job::job_status,
+    models::{Block, Diagnostic, File, Job, Match, Submission},
+    results::{load_evidence, load_renames, match_info, sort_matches},
 };
+use actix_session::Session;
 use actix_web::{get, http::header, web, HttpResponse, Result};
-use core::common::gen_svg;
+use api::def::{JobState, JobStatus, MatchInfo, MatchQuery, MatchSort};
+use core::{
+    common::{gen_svg, highlight_html, Highlight, LineStarts},
+    config::DetectionConfig,
+    evidence::EvidenceKind,
+    pipeline::Stage,
+};
 use diesel::prelude::*;
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Load files of submission, sorted by name
+fn load_files(conn: &mut DbConnection, submission_id: i32) -> QueryResult<Vec<File>> {
+    crate::schema::files::dsl::files
+        .filter(crate::schema::files::dsl::submission_id.eq(submission_id))
+        .order((
+            crate::schema::files::dsl::name,
+            crate::schema::files::dsl::id,
+        ))
+        .load::<File>(conn)
+}
+
+/// Whether verbatim rates were computed for matches of job
+fn verbatim_enabled(job: &Job) -> bool {
+    serde_json::from_str::<DetectionConfig>(&job.config).is_ok_and(|config| config.verbatim)
+}
+
+/// Title of a column of the job page, linking to the page sorted by the column,
+/// descending unless already so
+fn sort_header(
+    title: &str,
+    column: MatchSort,
+    current: &MatchQuery,
+    token: Option<&str>,
+) -> String {
+    let name = serde_json::to_value(column)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let desc = !(current.sort == column && current.desc);
+    let mut params = vec![("sort", name), ("desc", desc.to_string())];
+    if let Some(token) = token {
+        params.push(("token", token.to_string()));
+    }
+    let href = format!(
+        "?{}",
+        serde_urlencoded::to_string(&params).unwrap_or_default()
+    );
+    format!(
+        "<th><a href=\"{}\">{}</a></th>",
+        html_escape::encode_double_quoted_attribute(&href),
+        title
+    )
+}
+
+/// Load display name of submission, archived submissions of earlier jobs are labelled
+fn load_submission_name(
+    conn: &mut DbConnection,
+    job: &Job,
+    submission_id: i32,
+) -> QueryResult<String> {
+    let submission = crate::schema::submissions::dsl::submissions
+        .filter(crate::schema::submissions::dsl::id.eq(submission_id))
+        .first::<Submission>(conn)?;
+    if submission.job_id == job.id {
+        return Ok(submission.name);
+    }
+    let label = crate::schema::archive_submissions::dsl::archive_submissions
+        .filter(crate::schema::archive_submissions::dsl::submission_id.eq(submission_id))
+        .select(crate::schema::archive_submissions::dsl::label)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(match label {
+        Some(label) => format!("{} [archive: {}]", submission.name, label),
+        None => submission.name,
+    })
+}
+
+/// Render one file of a submission with matched spans highlighted
+fn render_file(res: &mut String, file: &File, blocks: &[&(usize, Block)], is_left: bool) {
+    let colors = ["#FF0000", "#00FF00", "#0000FF", "#00FFFF", "#FF00FF"];
+    res.push_str(&format!(
+        "<b>{}</b>\n",
+        html_escape::encode_text(&format!("==> {} <==", file.name))
+    ));
+
+    let lines = LineStarts::new(&file.code);
+    let mut highlights = vec![];
+    for (idx, b) in blocks.iter() {
+        let (line_from, column_from, line_to, column_to) = if is_left {
+            (
+                b.left_line_from,
+                b.left_column_from,
+                b.left_line_to,
+                b.left_column_to,
+            )
+        } else {
+            (
+                b.right_line_from,
+                b.right_column_from,
+                b.right_line_to,
+                b.right_column_to,
+            )
+        };
+        let from = lines.byte_offset(&file.code, line_from as usize, column_from as usize);
+        let to = lines.byte_offset(&file.code, line_to as usize, column_to as usize);
+        let opposite_side = if is_left { "right" } else { "left" };
+
+        // add anchor, and link to jump to opposite side
+        highlights.push(Highlight {
+            span: from..to,
+            color: colors[idx % 5],
+            prefix: format!(
+                "<a name=\"{}\"></a><a href=\"{}.html#{}\" target=\"{}\">{}</a>",
+                idx,
+                opposite_side,
+                idx,
+                opposite_side,
+                gen_svg(colors[idx % 5], 1)
+            ),
+        });
+    }
+    res.push_str(&highlight_html(&file.code, &highlights));
+    if !file.code.ends_with('\n') {
+        res.push('\n');
+    }
+    res.push('\n');
+}
 
 #[get("/results/{slug}/{match_id}/{frame}")]
 pub async fn render_match_frame(
+    session: Session,
     pool: web::Data<DbPool>,
     path: web::Path<(String, i64, String)>,
+    query: web::Query<AccessQuery>,
 ) -> Result<HttpResponse> {
     let (slug, match_id, frame) = path.into_inner();
     let (is_top, is_left) = match frame.as_str() {
@@ -27,8 +158,12 @@ pub async fn render_match_frame(
         .filter(crate::schema::jobs::dsl::slug.eq(&*slug))
         .first::<Job>(&mut conn)
         .map_err(err)?;
+    if !can_view(&mut conn, &session, &job, &query)? {
+        return Ok(forbidden());
+    }
     let m = crate::schema::matches::dsl::matches
         .filter(crate::schema::matches::dsl::job_id.eq(job.id))
+        .order(crate::schema::matches::dsl::id)
         .offset(match_id)
         .first::<Match>(&mut conn)
         .map_err(err)?;
@@ -45,23 +180,44 @@ pub async fn render_match_frame(
         // add title
 
         res += "<tr>";
-        let left_s = crate::schema::submissions::dsl::submissions
-            .filter(crate::schema::submissions::dsl::id.eq(m.left_submission_id))
-            .first::<Submission>(&mut conn)
-            .map_err(err)?;
-        res += &format!("<th>{} ({}%)</th>", left_s.name, m.left_match_rate);
+        let rate = |match_rate: i32, verbatim_rate: i32| {
+            if verbatim_enabled(&job) {
+                format!("{}%, {}% verbatim", match_rate, verbatim_rate)
+            } else {
+                format!("{}%", match_rate)
+            }
+        };
+        let left_name = load_submission_name(&mut conn, &job, m.left_submission_id).map_err(err)?;
+        res += &format!(
+            "<th>{} ({})</th>",
+            html_escape::encode_text(&left_name),
+            rate(m.left_match_rate, m.left_verbatim_rate)
+        );
         res += &format!("<th>{}</th>", gen_svg("#FF0000", m.left_match_rate));
-        let right_s = crate::schema::submissions::dsl::submissions
-            .filter(crate::schema::submissions::dsl::id.eq(m.right_submission_id))
-            .first::<Submission>(&mut conn)
-            .map_err(err)?;
-        res += &format!("<th>{} ({}%)</th>", right_s.name, m.right_match_rate);
+        let right_name =
+            load_submission_name(&mut conn, &job, m.right_submission_id).map_err(err)?;
+        res += &format!(
+            "<th>{} ({})</th>",
+            html_escape::encode_text(&right_name),
+            rate(m.right_match_rate, m.right_verbatim_rate)
+        );
         res += &format!("<th>{}</th>", gen_svg("#FF0000", m.right_match_rate));
         res += "<th> </th>";
         res += "</tr>";
 
-        let left_lines = left_s.code.lines().count();
-        let right_lines = right_s.code.lines().count();
+        // file id => (name, lines)
+        let mut files = HashMap::new();
+        for submission_id in [m.left_submission_id, m.right_submission_id] {
+            for file in load_files(&mut conn, submission_id).map_err(err)? {
+                files.insert(
+                    file.id,
+                    (file.name.clone(), file.code.lines().count().max(1)),
+                );
+            }
+        }
+
+        let block_ids: Vec<i32> = blocks.iter().map(|b| b.id).collect();
+        let renames = load_renames(&mut conn, &block_ids).map_err(err)?;
 
         // add index to blocks before sorting
         // so that index remains sync-ed in top, left & right panels
@@ -74,22 +230,85 @@ pub async fn render_match_frame(
         blocks.reverse();
 
         for (idx, block) in blocks.iter() {
+            let (left_name, left_lines) = &files[&block.left_file_id];
+            let (right_name, right_lines) = &files[&block.right_file_id];
             res += "<tr>";
             res += &format!(
-                "<td><a href=\"./left.html#{}\" target=\"left\">{}-{}</td>",
-                block.left_line_from, block.left_line_from, block.left_line_to
+                "<td><a href=\"./left.html#{}\" target=\"left\">{}: {}-{}</td>",
+                idx,
+                html_escape::encode_text(left_name),
+                block.left_line_from,
+                block.left_line_to
             );
             let left_ratio =
-                (block.left_line_to - block.left_line_from + 1) * 100 / left_lines as i32;
+                (block.left_line_to - block.left_line_from + 1) * 100 / *left_lines as i32;
             res += &format!("<td>{}</td>", gen_svg(colors[idx % 5], left_ratio));
             res += &format!(
-                "<td><a href=\"./right.html#{}\" target=\"right\">{}-{}</td>",
-                block.right_line_from, block.right_line_from, block.right_line_to
+                "<td><a href=\"./right.html#{}\" target=\"right\">{}: {}-{}</td>",
+                idx,
+                html_escape::encode_text(right_name),
+                block.right_line_from,
+                block.right_line_to
             );
             let right_ratio =
-                (block.right_line_to - block.right_line_from + 1) * 100 / right_lines as i32;
+                (block.right_line_to - block.right_line_from + 1) * 100 / *right_lines as i32;
             res += &format!("<td>{}</td>", gen_svg(colors[idx % 5], right_ratio));
-            res += "<td> </td>";
+            let mut notes = vec![];
+            // blocks merged across inserted code
+            if block.left_gap > 0 || block.right_gap > 0 {
+                notes.push(format!(
+                    "gaps of {} and {} tokens",
+                    block.left_gap, block.right_gap
+                ));
+            }
+            if let Some(renames) = renames.get(&block.id) {
+                let renames: Vec<String> = renames
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "<code>{}</code>&rarr;<code>{}</code> ({})",
+                            html_escape::encode_text(&r.left),
+                            html_escape::encode_text(&r.right),
+                            r.count
+                        )
+                    })
+                    .collect();
+                notes.push(format!(
+                    "renamed {}, {}% consistent",
+                    renames.join(", "),
+                    block.rename_consistency
+                ));
+            }
+            if notes.is_empty() {
+                res += "<td> </td>";
+            } else {
+                res += &format!("<td>{}</td>", notes.join("; "));
+            }
+            res += "</tr>";
+        }
+
+        // rare comments and literals in both, which may lie outside of blocks
+        for evidence in load_evidence(&mut conn, m.id).map_err(err)? {
+            let kind = match evidence.kind {
+                EvidenceKind::Comment => "comment",
+                EvidenceKind::Literal => "literal",
+            };
+            res += "<tr>";
+            res += &format!(
+                "<td colspan=\"2\">{}: {}</td>",
+                html_escape::encode_text(&files[&evidence.left_file_id].0),
+                evidence.left_line
+            );
+            res += &format!(
+                "<td colspan=\"2\">{}: {}</td>",
+                html_escape::encode_text(&files[&evidence.right_file_id].0),
+                evidence.right_line
+            );
+            res += &format!(
+                "<td>shared {} <code>{}</code></td>",
+                kind,
+                html_escape::encode_text(&evidence.text)
+            );
             res += "</tr>";
         }
 
@@ -100,77 +319,37 @@ pub async fn render_match_frame(
         } else {
             m.right_submission_id
         };
-        let s = crate::schema::submissions::dsl::submissions
-            .filter(crate::schema::submissions::dsl::id.eq(submission_id))
-            .first::<Submission>(&mut conn)
-            .map_err(err)?;
-        let lines: Vec<&str> = s.code.lines().collect();
+        let files = load_files(&mut conn, submission_id).map_err(err)?;
 
         res = "<html><head><meta charset=\"UTF-8\"></head><body><pre style=\"font-family: JetBrains Mono, Cascadia Code, Fira Code, Inconsolata, Iosevka, Monaco, Menlo, Roboto Mono, Source Code Pro, Ubuntu Mono, Consolas, monospace\">".to_string();
-        let mut last_line = 0;
 
         // add index to blocks before sorting
         // so that index remains sync-ed in left & right panels
         let mut blocks: Vec<(usize, Block)> = blocks.into_iter().enumerate().collect();
 
-        // sort by line_from
+        // sort by position
         blocks.sort_by_key(|b| {
             if is_left {
-                b.1.left_line_from
+                (b.1.left_line_from, b.1.left_column_from)
             } else {
-                b.1.right_line_from
+                (b.1.right_line_from, b.1.right_column_from)
             }
         });
 
-        for (idx, b) in blocks.iter() {
-            let line_from = if is_left {
-                b.left_line_from
-            } else {
-                b.right_line_from
-            } as usize;
-
-            let opposite_line_from = if is_left {
-                b.right_line_from
-            } else {
-                b.left_line_from
-            } as usize;
-            let opposite_side = if is_left { "right" } else { "left" };
-
-            let line_to = if is_left {
-                b.left_line_to
-            } else {
-                b.right_line_to
-            } as usize;
-
-            assert!(last_line <= line_from);
-            assert!(line_from <= line_to);
-            if last_line < line_from {
-                res += &html_escape::encode_text(&lines[last_line..=(line_from - 1)].join("\n"));
-                res += "\n";
-            }
-            last_line = line_to + 1;
-
-            // add link to jump to opposite side
-            res += &format!("<a name=\"{}\">", line_from);
-            res += &format!("<font color=\"{}\">", colors[idx % 5]);
-            res += &format!(
-                "<a href=\"{}.html#{}\" target=\"{}\">",
-                opposite_side, opposite_line_from, opposite_side
-            );
-            res += &gen_svg(colors[idx % 5], 1);
-            res += "</a>\n";
-            res += &format!(
-                "{}",
-                html_escape::encode_text(&lines[line_from..=line_to].join("\n"))
-            );
-            res += "\n";
-            res += "</font>";
-        }
-
-        // the rest
-        if last_line < lines.len() {
-            res += &html_escape::encode_text(&lines[last_line..].join("\n"));
-            res += "\n";
+        // one view for each file
+        for file in &files {
+            let file_blocks: Vec<&(usize, Block)> = blocks
+                .iter()
+                .filter(|b| {
+                    let file_id = if is_left {
+                        b.1.left_file_id
+                    } else {
+                        b.1.right_file_id
+                    };
+                    file_id == file.id
+                })
+                .collect();
+            render_file(&mut res, file, &file_blocks, is_left);
         }
 
         res += "</pre></body></html>";
@@ -181,7 +360,20 @@ pub async fn render_match_frame(
 }
 
 #[get("/results/{slug}/{match_id}/")]
-pub async fn render_match(_path: web::Path<(String, i64)>) -> Result<HttpResponse> {
+pub async fn render_match(
+    session: Session,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, i64)>,
+    query: web::Query<AccessQuery>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let job = crate::schema::jobs::dsl::jobs
+        .filter(crate::schema::jobs::dsl::slug.eq(&path.0))
+        .first::<Job>(&mut conn)
+        .map_err(err)?;
+    if !can_view(&mut conn, &session, &job, &query)? {
+        return Ok(forbidden());
+    }
     let res = r#"
 <html>
 	<head>
@@ -204,49 +396,184 @@ pub async fn render_match(_path: web::Path<(String, i64)>) -> Result<HttpRespons
         .body(res));
 }
 
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden()
+        .append_header(header::ContentType::html())
+        .body(
+            "<html><body>Please login as owner of the job, or use a valid share link</body></html>",
+        )
+}
+
+/// Render progress of unfinished job, refreshing until done
+fn render_status(status: &JobStatus) -> String {
+    let mut res = "<html><head>".to_string();
+    if !status.state.finished() {
+        res += "<meta http-equiv=\"refresh\" content=\"5\">";
+    }
+    res += "</head><body>";
+    res += &format!("<p>Job {}</p>", status.state.as_str());
+    if let Some(stage) = status.stage {
+        if status.state == JobState::Running {
+            res += &format!("<p>Stage: {:?}</p>", stage);
+        }
+        if stage == Stage::Align && status.pairs_total > 0 {
+            res += &format!(
+                "<p><progress value=\"{}\" max=\"{}\"></progress> {}/{} pairs</p>",
+                status.pairs_done, status.pairs_total, status.pairs_done, status.pairs_total
+            );
+        }
+    }
+    if let Some(error) = &status.error {
+        res += &format!("<pre>{}</pre>", html_escape::encode_text(error));
+    }
+    res += "</body></html>";
+    res
+}
+
 #[get("/results/{slug}/")]
-pub async fn render_job(pool: web::Data<DbPool>, slug: web::Path<String>) -> Result<HttpResponse> {
+pub async fn render_job(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    query: web::Query<AccessQuery>,
+    sort: web::Query<MatchQuery>,
+) -> Result<HttpResponse> {
     let mut conn = pool.get().map_err(err)?;
     let job = crate::schema::jobs::dsl::jobs
         .filter(crate::schema::jobs::dsl::slug.eq(&*slug))
         .first::<Job>(&mut conn)
         .map_err(err)?;
+    if !can_view(&mut conn, &session, &job, &query)? {
+        return Ok(forbidden());
+    }
+    let status = job_status(&job).map_err(err)?;
+    if status.state != JobState::Done {
+        return Ok(HttpResponse::Ok()
+            .append_header(header::ContentType::html())
+            .body(render_status(&status)));
+    }
     let matches = crate::schema::matches::dsl::matches
         .filter(crate::schema::matches::dsl::job_id.eq(job.id))
+        .order(crate::schema::matches::dsl::id)
         .load::<Match>(&mut conn)
         .map_err(err)?;
+    let mut names = HashMap::new();
+    for m in &matches {
+        for submission_id in [m.left_submission_id, m.right_submission_id] {
+            if let Entry::Vacant(e) = names.entry(submission_id) {
+                e.insert(load_submission_name(&mut conn, &job, submission_id).map_err(err)?);
+            }
+        }
+    }
+    let mut matches: Vec<MatchInfo> = matches
+        .iter()
+        .enumerate()
+        .map(|(index, m)| match_info(index, m, &names))
+        .collect();
+    sort_matches(&mut matches, sort.sort, sort.desc);
 
     let mut res = "<html><head></head><body>".to_string();
     res += "<table><tbody>";
 
-    // add title
-    res += "<tr><th>File 1</th><th>File 2</th><th>Lines Matched</th></tr>";
+    // add title, each column sorts by its metric
+    let verbatim = verbatim_enabled(&job);
+    let mut columns = vec![
+        ("File 1", MatchSort::LeftMatchRate),
+        ("File 2", MatchSort::RightMatchRate),
+        ("Lines Matched", MatchSort::LinesMatched),
+        ("Tokens Matched", MatchSort::TokenRate),
+        ("Similarity", MatchSort::Similarity),
+        ("Containment", MatchSort::Containment),
+        ("Longest Block", MatchSort::LongestBlock),
+        ("Score", MatchSort::Score),
+    ];
+    if verbatim {
+        columns.push(("Verbatim", MatchSort::VerbatimRate));
+    }
+    res += "<tr>";
+    for (title, column) in columns {
+        res += &sort_header(title, column, &sort, query.token.as_deref());
+    }
+    res += "</tr>";
 
-    for (idx, m) in matches.iter().enumerate() {
+    for m in &matches {
         res += "<tr>";
-        let left_s = crate::schema::submissions::dsl::submissions
-            .filter(crate::schema::submissions::dsl::id.eq(m.left_submission_id))
-            .first::<Submission>(&mut conn)
-            .map_err(err)?;
         res += &format!(
             "<td><a href=\"./{}/\">{} ({}%)</a></td>",
-            idx, left_s.name, m.left_match_rate
+            m.index,
+            html_escape::encode_text(&m.left_submission_name),
+            m.left_match_rate
         );
-        let right_s = crate::schema::submissions::dsl::submissions
-            .filter(crate::schema::submissions::dsl::id.eq(m.right_submission_id))
-            .first::<Submission>(&mut conn)
-            .map_err(err)?;
         res += &format!(
             "<td><a href=\"./{}/\">{} ({}%)</a></td>",
-            idx, right_s.name, m.right_match_rate
+            m.index,
+            html_escape::encode_text(&m.right_submission_name),
+            m.right_match_rate
         );
         res += &format!("<td align=\"right\">{}</td>", m.lines_matched);
+        res += &format!(
+            "<td align=\"right\">{}% / {}%</td>",
+            m.left_token_rate, m.right_token_rate
+        );
+        res += &format!("<td align=\"right\">{}%</td>", m.similarity);
+        res += &format!(
+            "<td align=\"right\">{}% / {}%</td>",
+            m.left_containment, m.right_containment
+        );
+        res += &format!("<td align=\"right\">{}</td>", m.longest_block);
+        res += &format!("<td align=\"right\">{:.3}</td>", m.score as f64 / 1000.0);
+        if verbatim {
+            res += &format!(
+                "<td align=\"right\">{}% / {}%</td>",
+                m.left_verbatim_rate, m.right_verbatim_rate
+            );
+        }
         res += "</tr>";
     }
 
     res += "</tbody></table>";
+    res += &render_diagnostics(&mut conn, &job).map_err(err)?;
+    if let Some(common_code) = &job.common_code {
+        res += "<p>Code shared by many submissions, ignored like the template:</p>";
+        res += &format!("<pre>{}</pre>", html_escape::encode_text(common_code));
+    }
     res += "</body></html>";
     return Ok(HttpResponse::Ok()
         .append_header(header::ContentType::html())
         .body(res));
 }
+
+/// Render problems found while tokenizing submissions of job, if any
+fn render_diagnostics(conn: &mut DbConnection, job: &Job) -> QueryResult<String> {
+    use crate::schema::diagnostics::dsl as diagnostics;
+    use crate::schema::submissions::dsl as submissions;
+
+    let names: HashMap<i32, String> = submissions::submissions
+        .filter(submissions::job_id.eq(job.id))
+        .select((submissions::id, submissions::name))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect();
+    let all: Vec<Diagnostic> = diagnostics::diagnostics
+        .filter(diagnostics::submission_id.eq_any(names.keys()))
+        .order(diagnostics::id)
+        .load(conn)?;
+    if all.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut res = "<p>Some files could only be checked in part:</p>".to_string();
+    res += "<table><tbody>";
+    res += "<tr><th>Submission</th><th>File</th><th>Severity</th><th>Message</th></tr>";
+    for d in &all {
+        res += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape::encode_text(&names[&d.submission_id]),
+            html_escape::encode_text(&d.file),
+            d.severity,
+            html_escape::encode_text(&d.message)
+        );
+    }
+    res += "</tbody></table>";
+    Ok(res)
+}
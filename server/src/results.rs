@@ -0,0 +1,432 @@
+use crate::{
+    access::{can_view, AccessQuery},
+    common::err,
+    db::{DbConnection, DbPool},
+    job::job_status,
+    models::{Block, Diagnostic, Evidence, File, Job, Match, Rename, Submission},
+    work::parse_debug,
+};
+use api::def::{
+    BlockInfo, DiagnosticInfo, EvidenceInfo, FileInfo, JobState, MatchDetail, MatchInfo, MatchList,
+    MatchQuery, MatchSort, SubmissionInfo,
+};
+
+use core::matching;
+
+use actix_session::Session;
+use actix_web::{get, web, HttpResponse, Result};
+use diesel::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+
+// matches in one page, if not specified
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Load job by slug, or the response to send if it is not visible or has no results (yet)
+fn load_job(
+    conn: &mut DbConnection,
+    session: &Session,
+    slug: &str,
+    access: &AccessQuery,
+) -> Result<std::result::Result<Job, HttpResponse>> {
+    use crate::schema::jobs::dsl;
+    let job = match dsl::jobs
+        .filter(dsl::slug.eq(slug))
+        .first::<Job>(conn)
+        .optional()
+        .map_err(err)?
+    {
+        Some(job) => job,
+        None => return Ok(Err(HttpResponse::NotFound().json(false))),
+    };
+    if !can_view(conn, session, &job, access)? {
+        return Ok(Err(HttpResponse::Forbidden().json(false)));
+    }
+    let status = job_status(&job).map_err(err)?;
+    if status.state != JobState::Done {
+        return Ok(Err(HttpResponse::Conflict().json(status)));
+    }
+    Ok(Ok(job))
+}
+
+/// Load matches of job, in the order of their index
+fn load_matches(conn: &mut DbConnection, job: &Job) -> QueryResult<Vec<Match>> {
+    use crate::schema::matches::dsl;
+    dsl::matches
+        .filter(dsl::job_id.eq(job.id))
+        .order(dsl::id)
+        .load(conn)
+}
+
+/// Load submissions of job and archived submissions it matched
+fn load_submissions(
+    conn: &mut DbConnection,
+    job: &Job,
+    matches: &[Match],
+) -> anyhow::Result<Vec<SubmissionInfo>> {
+    use crate::schema::archive_submissions::dsl as archive_submissions;
+    use crate::schema::diagnostics::dsl as diagnostics;
+    use crate::schema::files::dsl as files;
+    use crate::schema::submissions::dsl as submissions;
+
+    let matched: BTreeSet<i32> = matches
+        .iter()
+        .flat_map(|m| [m.left_submission_id, m.right_submission_id])
+        .collect();
+    let all: Vec<Submission> = submissions::submissions
+        .filter(
+            submissions::job_id
+                .eq(job.id)
+                .or(submissions::id.eq_any(matched)),
+        )
+        .order(submissions::id)
+        .load(conn)?;
+    let ids: Vec<i32> = all.iter().map(|s| s.id).collect();
+    let labels: HashMap<i32, String> = archive_submissions::archive_submissions
+        .filter(archive_submissions::submission_id.eq_any(&ids))
+        .select((
+            archive_submissions::submission_id,
+            archive_submissions::label,
+        ))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect();
+    let mut all_files: HashMap<i32, Vec<FileInfo>> = HashMap::new();
+    for file in files::files
+        .filter(files::submission_id.eq_any(&ids))
+        .order((files::name, files::id))
+        .load::<File>(conn)?
+    {
+        all_files
+            .entry(file.submission_id)
+            .or_default()
+            .push(FileInfo {
+                id: file.id,
+                name: file.name,
+                lines: file.code.lines().count(),
+            });
+    }
+
+    let mut all_diagnostics: HashMap<i32, Vec<DiagnosticInfo>> = HashMap::new();
+    for diagnostic in diagnostics::diagnostics
+        .filter(diagnostics::submission_id.eq_any(&ids))
+        .order(diagnostics::id)
+        .load::<Diagnostic>(conn)?
+    {
+        all_diagnostics
+            .entry(diagnostic.submission_id)
+            .or_default()
+            .push(DiagnosticInfo {
+                file: diagnostic.file,
+                severity: parse_debug(&diagnostic.severity)?,
+                message: diagnostic.message,
+            });
+    }
+
+    Ok(all
+        .into_iter()
+        .map(|s| SubmissionInfo {
+            id: s.id,
+            archive: if s.job_id == job.id {
+                None
+            } else {
+                labels.get(&s.id).cloned()
+            },
+            name: s.name,
+            files: all_files.remove(&s.id).unwrap_or_default(),
+            diagnostics: all_diagnostics.remove(&s.id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Load renames of blocks, keyed by block id, most used first
+pub fn load_renames(
+    conn: &mut DbConnection,
+    block_ids: &[i32],
+) -> QueryResult<HashMap<i32, Vec<matching::Rename>>> {
+    use crate::schema::renames::dsl;
+    let mut res: HashMap<i32, Vec<matching::Rename>> = HashMap::new();
+    for rename in dsl::renames
+        .filter(dsl::block_id.eq_any(block_ids))
+        .order(dsl::id)
+        .load::<Rename>(conn)?
+    {
+        res.entry(rename.block_id)
+            .or_default()
+            .push(matching::Rename {
+                left: rename.left_name,
+                right: rename.right_name,
+                count: rename.count as usize,
+            });
+    }
+    Ok(res)
+}
+
+/// Load comments and literals found on both sides of match
+pub fn load_evidence(conn: &mut DbConnection, match_id: i32) -> anyhow::Result<Vec<EvidenceInfo>> {
+    use crate::schema::evidence::dsl;
+    dsl::evidence
+        .filter(dsl::match_id.eq(match_id))
+        .order(dsl::id)
+        .load::<Evidence>(conn)?
+        .into_iter()
+        .map(|e| {
+            Ok(EvidenceInfo {
+                kind: parse_debug(&e.kind)?,
+                text: e.text,
+                left_file_id: e.left_file_id,
+                left_line: e.left_line,
+                right_file_id: e.right_file_id,
+                right_line: e.right_line,
+            })
+        })
+        .collect()
+}
+
+pub fn match_info(index: usize, m: &Match, names: &HashMap<i32, String>) -> MatchInfo {
+    MatchInfo {
+        index,
+        left_submission_id: m.left_submission_id,
+        left_submission_name: names
+            .get(&m.left_submission_id)
+            .cloned()
+            .unwrap_or_default(),
+        left_match_rate: m.left_match_rate,
+        right_submission_id: m.right_submission_id,
+        right_submission_name: names
+            .get(&m.right_submission_id)
+            .cloned()
+            .unwrap_or_default(),
+        right_match_rate: m.right_match_rate,
+        lines_matched: m.lines_matched,
+        left_verbatim_rate: m.left_verbatim_rate,
+        right_verbatim_rate: m.right_verbatim_rate,
+        left_token_rate: m.left_token_rate,
+        right_token_rate: m.right_token_rate,
+        similarity: m.similarity,
+        left_containment: m.left_containment,
+        right_containment: m.right_containment,
+        longest_block: m.longest_block,
+        score: m.score,
+    }
+}
+
+fn submission_names(submissions: &[SubmissionInfo]) -> HashMap<i32, String> {
+    submissions.iter().map(|s| (s.id, s.name.clone())).collect()
+}
+
+/// Sort matches by key, equal keys stay in the order of index
+pub fn sort_matches(matches: &mut [MatchInfo], sort: MatchSort, desc: bool) {
+    let key = |m: &MatchInfo| -> i64 {
+        match sort {
+            MatchSort::Index => m.index as i64,
+            MatchSort::LinesMatched => m.lines_matched.into(),
+            MatchSort::MatchRate => m.left_match_rate.max(m.right_match_rate).into(),
+            MatchSort::LeftMatchRate => m.left_match_rate.into(),
+            MatchSort::RightMatchRate => m.right_match_rate.into(),
+            MatchSort::VerbatimRate => m.left_verbatim_rate.max(m.right_verbatim_rate).into(),
+            MatchSort::TokenRate => m.left_token_rate.max(m.right_token_rate).into(),
+            MatchSort::LeftTokenRate => m.left_token_rate.into(),
+            MatchSort::RightTokenRate => m.right_token_rate.into(),
+            MatchSort::Similarity => m.similarity.into(),
+            MatchSort::Containment => m.left_containment.max(m.right_containment).into(),
+            MatchSort::LeftContainment => m.left_containment.into(),
+            MatchSort::RightContainment => m.right_containment.into(),
+            MatchSort::LongestBlock => m.longest_block.into(),
+            MatchSort::Score => m.score,
+        }
+    };
+    matches.sort_by(|a, b| {
+        let order = key(a).cmp(&key(b));
+        let order = if desc { order.reverse() } else { order };
+        order.then(a.index.cmp(&b.index))
+    });
+}
+
+/// Sort and paginate matches
+fn select_matches(mut matches: Vec<MatchInfo>, query: &MatchQuery) -> MatchList {
+    let total = matches.len();
+    sort_matches(&mut matches, query.sort, query.desc);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    MatchList {
+        total,
+        matches: matches.into_iter().skip(query.offset).take(limit).collect(),
+    }
+}
+
+#[get("/jobs/{slug}/submissions")]
+pub async fn list_submissions(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    access: web::Query<AccessQuery>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_job(&mut conn, &session, &slug, &access)? {
+        Ok(job) => job,
+        Err(res) => return Ok(res),
+    };
+    let matches = load_matches(&mut conn, &job).map_err(err)?;
+    let submissions = load_submissions(&mut conn, &job, &matches).map_err(err)?;
+    Ok(HttpResponse::Ok().json(submissions))
+}
+
+#[get("/jobs/{slug}/matches")]
+pub async fn list_matches(
+    session: Session,
+    pool: web::Data<DbPool>,
+    slug: web::Path<String>,
+    query: web::Query<MatchQuery>,
+    access: web::Query<AccessQuery>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_job(&mut conn, &session, &slug, &access)? {
+        Ok(job) => job,
+        Err(res) => return Ok(res),
+    };
+    let matches = load_matches(&mut conn, &job).map_err(err)?;
+    let submissions = load_submissions(&mut conn, &job, &matches).map_err(err)?;
+    let names = submission_names(&submissions);
+    let matches = matches
+        .iter()
+        .enumerate()
+        .map(|(index, m)| match_info(index, m, &names))
+        .collect();
+    Ok(HttpResponse::Ok().json(select_matches(matches, &query)))
+}
+
+#[get("/jobs/{slug}/matches/{index}")]
+pub async fn get_match(
+    session: Session,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, usize)>,
+    access: web::Query<AccessQuery>,
+) -> Result<HttpResponse> {
+    let (slug, index) = path.into_inner();
+    let mut conn = pool.get().map_err(err)?;
+    let job = match load_job(&mut conn, &session, &slug, &access)? {
+        Ok(job) => job,
+        Err(res) => return Ok(res),
+    };
+    let matches = load_matches(&mut conn, &job).map_err(err)?;
+    let m = match matches.get(index) {
+        Some(m) => m,
+        None => return Ok(HttpResponse::NotFound().json(false)),
+    };
+    let submissions = load_submissions(&mut conn, &job, std::slice::from_ref(m)).map_err(err)?;
+    let blocks = crate::schema::blocks::dsl::blocks
+        .filter(crate::schema::blocks::dsl::match_id.eq(m.id))
+        .order(crate::schema::blocks::dsl::id)
+        .load::<Block>(&mut conn)
+        .map_err(err)?;
+    let block_ids: Vec<i32> = blocks.iter().map(|b| b.id).collect();
+    let mut renames = load_renames(&mut conn, &block_ids).map_err(err)?;
+    let evidence = load_evidence(&mut conn, m.id).map_err(err)?;
+    Ok(HttpResponse::Ok().json(MatchDetail {
+        info: match_info(index, m, &submission_names(&submissions)),
+        blocks: blocks
+            .into_iter()
+            .map(|b| BlockInfo {
+                left_file_id: b.left_file_id,
+                right_file_id: b.right_file_id,
+                left_line_from: b.left_line_from,
+                left_line_to: b.left_line_to,
+                right_line_from: b.right_line_from,
+                right_line_to: b.right_line_to,
+                left_column_from: b.left_column_from,
+                left_column_to: b.left_column_to,
+                right_column_from: b.right_column_from,
+                right_column_to: b.right_column_to,
+                left_gap: b.left_gap,
+                right_gap: b.right_gap,
+                renames: renames.remove(&b.id).unwrap_or_default(),
+                rename_consistency: b.rename_consistency,
+            })
+            .collect(),
+        evidence,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_matches;
+    use api::def::{MatchInfo, MatchQuery, MatchSort};
+
+    fn info(index: usize, left: i32, right: i32, lines: i32, verbatim: i32) -> MatchInfo {
+        MatchInfo {
+            index,
+            left_submission_id: 0,
+            left_submission_name: String::new(),
+            left_match_rate: left,
+            right_submission_id: 1,
+            right_submission_name: String::new(),
+            right_match_rate: right,
+            lines_matched: lines,
+            left_verbatim_rate: verbatim,
+            right_verbatim_rate: 0,
+            left_token_rate: 0,
+            right_token_rate: 0,
+            similarity: lines,
+            left_containment: 0,
+            right_containment: 0,
+            longest_block: 0,
+            score: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_matches() {
+        let matches = vec![
+            info(0, 10, 90, 5, 0),
+            info(1, 50, 50, 20, 40),
+            info(2, 80, 20, 20, 10),
+        ];
+        let indices = |query: MatchQuery| -> Vec<usize> {
+            let list = select_matches(matches.clone(), &query);
+            assert_eq!(list.total, 3);
+            list.matches.iter().map(|m| m.index).collect()
+        };
+
+        assert_eq!(indices(MatchQuery::default()), [0, 1, 2]);
+        assert_eq!(
+            indices(MatchQuery {
+                sort: MatchSort::MatchRate,
+                desc: true,
+                ..Default::default()
+            }),
+            [0, 2, 1]
+        );
+        assert_eq!(
+            indices(MatchQuery {
+                sort: MatchSort::LinesMatched,
+                desc: true,
+                ..Default::default()
+            }),
+            [1, 2, 0]
+        );
+        assert_eq!(
+            indices(MatchQuery {
+                sort: MatchSort::VerbatimRate,
+                ..Default::default()
+            }),
+            [0, 2, 1]
+        );
+        assert_eq!(
+            indices(MatchQuery {
+                sort: MatchSort::Similarity,
+                desc: true,
+                ..Default::default()
+            }),
+            [1, 2, 0]
+        );
+        assert_eq!(
+            indices(MatchQuery {
+                offset: 1,
+                limit: Some(1),
+                sort: MatchSort::LeftMatchRate,
+                desc: true,
+            }),
+            [1]
+        );
+    }
+}
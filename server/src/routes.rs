@@ -0,0 +1,33 @@
+use crate::{
+    access::{create_link, revoke_link, share, unshare},
+    job::{cancel, status},
+    render::{render_job, render_match, render_match_frame},
+    results::{get_match, list_matches, list_submissions},
+    session::login,
+    submit::submit,
+    template::{create_template, list_templates},
+};
+use actix_web::web;
+
+/// Register all endpoints, relative to the path of public url
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api")
+            .service(login)
+            .service(submit)
+            .service(status)
+            .service(cancel)
+            .service(list_submissions)
+            .service(list_matches)
+            .service(get_match)
+            .service(share)
+            .service(unshare)
+            .service(create_link)
+            .service(revoke_link)
+            .service(create_template)
+            .service(list_templates),
+    )
+    .service(render_match)
+    .service(render_match_frame)
+    .service(render_job);
+}
@@ -1,5 +1,25 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    archive_fingerprints (id) {
+        id -> Int4,
+        archive_submission_id -> Int4,
+        hash -> Int8,
+    }
+}
+
+diesel::table! {
+    archive_submissions (id) {
+        id -> Int4,
+        submission_id -> Int4,
+        owner_user_id -> Int4,
+        language -> Text,
+        label -> Text,
+        fingerprint_version -> Int4,
+        config_digest -> Int8,
+    }
+}
+
 diesel::table! {
     blocks (id) {
         id -> Int4,
@@ -8,6 +28,47 @@ diesel::table! {
         left_line_to -> Int4,
         right_line_from -> Int4,
         right_line_to -> Int4,
+        left_file_id -> Int4,
+        right_file_id -> Int4,
+        left_column_from -> Int4,
+        left_column_to -> Int4,
+        right_column_from -> Int4,
+        right_column_to -> Int4,
+        left_gap -> Int4,
+        right_gap -> Int4,
+        rename_consistency -> Int4,
+    }
+}
+
+diesel::table! {
+    diagnostics (id) {
+        id -> Int4,
+        submission_id -> Int4,
+        file -> Text,
+        severity -> Text,
+        message -> Text,
+    }
+}
+
+diesel::table! {
+    evidence (id) {
+        id -> Int4,
+        match_id -> Int4,
+        kind -> Text,
+        text -> Text,
+        left_file_id -> Int4,
+        left_line -> Int4,
+        right_file_id -> Int4,
+        right_line -> Int4,
+    }
+}
+
+diesel::table! {
+    files (id) {
+        id -> Int4,
+        submission_id -> Int4,
+        name -> Text,
+        code -> Text,
     }
 }
 
@@ -16,6 +77,42 @@ diesel::table! {
         id -> Int4,
         creator_user_id -> Int4,
         slug -> Text,
+        config -> Text,
+        status -> Text,
+        stage -> Nullable<Text>,
+        pairs_done -> Int4,
+        pairs_total -> Int4,
+        error -> Nullable<Text>,
+        language -> Text,
+        template -> Nullable<Text>,
+        archive -> Nullable<Text>,
+        common_code -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    job_shares (id) {
+        id -> Int4,
+        job_id -> Int4,
+        user_id -> Nullable<Int4>,
+        role -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    job_template_files (id) {
+        id -> Int4,
+        job_id -> Int4,
+        name -> Text,
+        code -> Text,
+    }
+}
+
+diesel::table! {
+    job_templates (id) {
+        id -> Int4,
+        job_id -> Int4,
+        template_id -> Int4,
     }
 }
 
@@ -28,6 +125,35 @@ diesel::table! {
         right_submission_id -> Int4,
         right_match_rate -> Int4,
         lines_matched -> Int4,
+        left_verbatim_rate -> Int4,
+        right_verbatim_rate -> Int4,
+        left_token_rate -> Int4,
+        right_token_rate -> Int4,
+        similarity -> Int4,
+        left_containment -> Int4,
+        right_containment -> Int4,
+        longest_block -> Int4,
+        score -> Int8,
+    }
+}
+
+diesel::table! {
+    renames (id) {
+        id -> Int4,
+        block_id -> Int4,
+        left_name -> Text,
+        right_name -> Text,
+        count -> Int4,
+    }
+}
+
+diesel::table! {
+    share_links (id) {
+        id -> Int4,
+        job_id -> Int4,
+        token -> Text,
+        expires_at -> Timestamp,
+        revoked -> Bool,
     }
 }
 
@@ -36,10 +162,27 @@ diesel::table! {
         id -> Int4,
         job_id -> Int4,
         name -> Text,
+    }
+}
+
+diesel::table! {
+    template_files (id) {
+        id -> Int4,
+        template_id -> Int4,
+        name -> Text,
         code -> Text,
     }
 }
 
+diesel::table! {
+    templates (id) {
+        id -> Int4,
+        owner_user_id -> Int4,
+        name -> Text,
+        language -> Text,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -49,4 +192,31 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(blocks, jobs, matches, submissions, users,);
+diesel::table! {
+    user_roles (id) {
+        id -> Int4,
+        user_id -> Int4,
+        role -> Text,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    archive_fingerprints,
+    archive_submissions,
+    blocks,
+    diagnostics,
+    evidence,
+    files,
+    job_shares,
+    job_template_files,
+    job_templates,
+    jobs,
+    matches,
+    renames,
+    share_links,
+    submissions,
+    template_files,
+    templates,
+    user_roles,
+    users,
+);
@@ -1,4 +1,8 @@
-use crate::{common::err, db::DbPool, models::User};
+use crate::{
+    common::err,
+    db::{DbConnection, DbPool},
+    models::User,
+};
 use api::def::LoginRequest;
 
 use actix_session::Session;
@@ -42,6 +46,28 @@ pub fn verify(salt: &[u8], password: &str, hash: &[u8]) -> bool {
     .is_ok()
 }
 
+/// Find user id from login in request body, or from session if absent
+pub fn authenticate(
+    session: &Session,
+    conn: &mut DbConnection,
+    credentials: &Option<LoginRequest>,
+) -> Result<Option<i32>> {
+    use crate::schema::users::dsl;
+    if let Some(credentials) = credentials {
+        if let Ok(user) = dsl::users
+            .filter(dsl::user_name.eq(&credentials.user_name))
+            .first::<User>(conn)
+        {
+            if verify(&user.salt, &credentials.password, &user.password) {
+                return Ok(Some(user.id));
+            }
+        }
+        Ok(None)
+    } else {
+        Ok(session.get::<i32>("id")?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{hash, verify};
@@ -2,40 +2,115 @@ use crate::{
     common::{err, generate_uuid},
     db::DbConnection,
     db::DbPool,
-    models::{NewBlock, NewJob, NewMatch, NewSubmission, User},
-    session::verify,
-    work::work_blocking,
+    models::{NewFile, NewJob, NewJobTemplate, NewJobTemplateFile, NewSubmission},
+    queue::JobQueue,
+    session::authenticate,
+    template::check_templates,
 };
 
-use api::{def::SubmitRequest, env::ENV};
+use api::{
+    def::{JobState, SubmitRequest, SubmitResponse},
+    env::ENV,
+};
+use core::config::DetectionConfig;
 
 use actix_session::Session;
 use actix_web::{post, web, HttpResponse, Result};
-use diesel::{
-    prelude::*,
-    r2d2::{ConnectionManager, PooledConnection},
-};
+use diesel::prelude::*;
 use log::*;
 
-async fn work(
-    mut conn: PooledConnection<ConnectionManager<DbConnection>>,
-    req: SubmitRequest,
+/// Check that a user-provided config stays within bounds the server is willing to run
+fn check_config(config: &DetectionConfig) -> anyhow::Result<()> {
+    config.validate()?;
+    let bounds = [
+        (
+            "initial_search_length",
+            config.initial_search_length,
+            5,
+            500,
+        ),
+        ("minimum_match_length", config.minimum_match_length, 5, 500),
+        ("noise", config.noise, 5, 500),
+        ("guarantee", config.guarantee, 5, 1000),
+        (
+            "max_fingerprint_occurrences",
+            config.max_fingerprint_occurrences,
+            2,
+            1000,
+        ),
+        ("max_pairs", config.max_pairs, 1, 1000),
+        ("common_code_percent", config.common_code_percent, 0, 100),
+        ("max_gap", config.max_gap, 0, 100),
+        (
+            "max_evidence_occurrences",
+            config.max_evidence_occurrences,
+            0,
+            1000,
+        ),
+    ];
+    for (name, value, min, max) in bounds {
+        if value < min || value > max {
+            return Err(anyhow::anyhow!(
+                "{} must be in range [{}, {}], got {}",
+                name,
+                min,
+                max,
+                value
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Store job with its submissions, to be picked up by the queue
+fn create_job(
+    conn: &mut DbConnection,
+    req: &SubmitRequest,
     user_id: i32,
-) -> anyhow::Result<String> {
-    let work_req = req.clone();
-    let work = actix_web::web::block(move || work_blocking(work_req)).await??;
-    let slug = conn.transaction::<_, diesel::result::Error, _>(move |conn| {
-        // create new job
+) -> anyhow::Result<(i32, String)> {
+    let config = serde_json::to_string(&req.config)?;
+    Ok(conn.transaction::<_, diesel::result::Error, _>(|conn| {
         let slug = generate_uuid();
         let new_job = NewJob {
             creator_user_id: user_id,
             slug: slug.clone(),
+            config,
+            status: JobState::Queued.as_str().to_string(),
+            language: format!("{:?}", req.language),
+            template: req.template.clone(),
+            archive: req.archive.clone(),
         };
-        let job_ids: Vec<i32> = diesel::insert_into(crate::schema::jobs::table)
+        let job_id: i32 = diesel::insert_into(crate::schema::jobs::table)
             .values(new_job)
             .returning(crate::schema::jobs::dsl::id)
-            .get_results(conn)?;
-        let job_id = job_ids[0];
+            .get_result(conn)?;
+
+        // files of the template given with the job
+        let new_template_files: Vec<NewJobTemplateFile> = req
+            .template_files
+            .iter()
+            .map(|f| NewJobTemplateFile {
+                job_id,
+                name: f.name.clone(),
+                code: f.code.clone(),
+            })
+            .collect();
+        diesel::insert_into(crate::schema::job_template_files::table)
+            .values(new_template_files)
+            .execute(conn)?;
+
+        // templates from library, in given order
+        let new_job_templates: Vec<NewJobTemplate> = req
+            .templates
+            .iter()
+            .map(|template_id| NewJobTemplate {
+                job_id,
+                template_id: *template_id,
+            })
+            .collect();
+        diesel::insert_into(crate::schema::job_templates::table)
+            .values(new_job_templates)
+            .execute(conn)?;
 
         // insert submissions
         let new_submissions: Vec<NewSubmission> = req
@@ -44,7 +119,6 @@ async fn work(
             .map(|s| NewSubmission {
                 job_id,
                 name: s.name.clone(),
-                code: s.code.clone(),
             })
             .collect();
         let submission_ids: Vec<i32> = diesel::insert_into(crate::schema::submissions::table)
@@ -52,77 +126,50 @@ async fn work(
             .returning(crate::schema::submissions::dsl::id)
             .get_results(conn)?;
 
-        // insert matches
-        let new_matches: Vec<NewMatch> = work
-            .matches
+        // insert files, in submitted order
+        let new_files: Vec<NewFile> = req
+            .submissions
             .iter()
-            .map(|m| NewMatch {
-                job_id,
-                left_submission_id: submission_ids[m.left_submission_idx],
-                left_match_rate: m.left_match_rate,
-                right_submission_id: submission_ids[m.right_submission_idx],
-                right_match_rate: m.right_match_rate,
-                lines_matched: m.lines_matched as i32,
+            .zip(submission_ids.iter())
+            .flat_map(|(s, submission_id)| {
+                s.files.iter().map(|f| NewFile {
+                    submission_id: *submission_id,
+                    name: f.name.clone(),
+                    code: f.code.clone(),
+                })
             })
             .collect();
-        let match_ids: Vec<i32> = diesel::insert_into(crate::schema::matches::table)
-            .values(new_matches)
-            .returning(crate::schema::matches::dsl::id)
-            .get_results(conn)?;
-
-        for (match_id, m) in match_ids.iter().zip(work.matches.iter()) {
-            // insert blocks
-            let new_blocks: Vec<NewBlock> = m
-                .blocks
-                .iter()
-                .map(|b| NewBlock {
-                    match_id: *match_id,
-                    left_line_from: b.left_line_from as i32,
-                    left_line_to: b.left_line_to as i32,
-                    right_line_from: b.right_line_from as i32,
-                    right_line_to: b.right_line_to as i32,
-                })
-                .collect();
-            diesel::insert_into(crate::schema::blocks::table)
-                .values(new_blocks)
-                .execute(conn)?;
-        }
-        info!("Submission complete at {}", slug);
-
-        Ok(slug)
-    })?;
-    Ok(slug)
+        diesel::insert_into(crate::schema::files::table)
+            .values(new_files)
+            .execute(conn)?;
+        Ok((job_id, slug))
+    })?)
 }
 
 #[post("/submit")]
 pub async fn submit(
     session: Session,
     pool: web::Data<DbPool>,
+    queue: web::Data<JobQueue>,
     body: web::Json<SubmitRequest>,
 ) -> Result<HttpResponse> {
     let mut conn = pool.get().map_err(err)?;
-    use crate::schema::users::dsl;
-    let user_id;
-    if let Some(login) = &body.login {
-        if let Ok(user) = dsl::users
-            .filter(dsl::user_name.eq(&login.user_name))
-            .first::<User>(&mut conn)
-        {
-            if verify(&user.salt, &login.password, &user.password) {
-                user_id = user.id;
-            } else {
-                return Ok(HttpResponse::Ok().json(false));
-            }
-        } else {
-            return Ok(HttpResponse::Ok().json(false));
-        }
-    } else if let Some(id) = session.get::<i32>("id")? {
-        user_id = id;
-    } else {
-        return Ok(HttpResponse::Ok().json(false));
-    }
+    let user_id = match authenticate(&session, &mut conn, &body.login)? {
+        Some(user_id) => user_id,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
     info!("Got submission from {}", user_id);
-    let slug = work(conn, (*body).clone(), user_id).await.map_err(err)?;
+    if let Err(e) = check_config(&body.config) {
+        return Ok(HttpResponse::BadRequest().json(format!("Invalid config: {}", e)));
+    }
+    if let Some(e) =
+        check_templates(&mut conn, user_id, body.language, &body.templates).map_err(err)?
+    {
+        return Ok(HttpResponse::BadRequest().json(e));
+    }
+    let (job_id, slug) = create_job(&mut conn, &body, user_id).map_err(err)?;
+    queue.push(job_id);
+    info!("Job {} queued", slug);
     let url = format!("{}/results/{}/", ENV.public_url, slug);
-    Ok(HttpResponse::Ok().json(url))
+    Ok(HttpResponse::Ok().json(SubmitResponse { slug, url }))
 }
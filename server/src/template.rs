@@ -0,0 +1,175 @@
+use crate::{
+    common::err,
+    db::{DbConnection, DbPool},
+    models::{NewTemplate, NewTemplateFile, Template, TemplateFile},
+    session::authenticate,
+    work::parse_debug,
+};
+use api::def::{CreateTemplateRequest, ListTemplatesRequest, TemplateInfo};
+use core::{
+    lang::Language,
+    pipeline::{Source, SourceFile},
+};
+
+use actix_session::Session;
+use actix_web::{post, web, HttpResponse, Result};
+use diesel::prelude::*;
+use log::*;
+use std::collections::HashMap;
+
+/// Load files of templates, keyed by template id
+fn load_files(
+    conn: &mut DbConnection,
+    ids: &[i32],
+) -> QueryResult<HashMap<i32, Vec<TemplateFile>>> {
+    use crate::schema::template_files::dsl;
+    let mut res: HashMap<i32, Vec<TemplateFile>> = HashMap::new();
+    for file in dsl::template_files
+        .filter(dsl::template_id.eq_any(ids))
+        .order(dsl::id)
+        .load::<TemplateFile>(conn)?
+    {
+        res.entry(file.template_id).or_default().push(file);
+    }
+    Ok(res)
+}
+
+/// Check that templates exist in the library of user for language,
+/// returning the reason if not
+pub fn check_templates(
+    conn: &mut DbConnection,
+    user_id: i32,
+    language: Language,
+    ids: &[i32],
+) -> QueryResult<Option<String>> {
+    use crate::schema::templates::dsl;
+    let found: Vec<i32> = dsl::templates
+        .filter(dsl::id.eq_any(ids))
+        .filter(dsl::owner_user_id.eq(user_id))
+        .filter(dsl::language.eq(format!("{:?}", language)))
+        .select(dsl::id)
+        .load(conn)?;
+    Ok(ids
+        .iter()
+        .find(|id| !found.contains(id))
+        .map(|id| format!("Template {} not found for {:?}", id, language)))
+}
+
+/// Load templates used by job, in the order they were given
+pub fn load_job_templates(conn: &mut DbConnection, job_id: i32) -> QueryResult<Vec<Source>> {
+    use crate::schema::job_templates::dsl as job_templates;
+    use crate::schema::templates::dsl as templates;
+    let ids: Vec<i32> = job_templates::job_templates
+        .filter(job_templates::job_id.eq(job_id))
+        .order(job_templates::id)
+        .select(job_templates::template_id)
+        .load(conn)?;
+    let mut all: HashMap<i32, Template> = templates::templates
+        .filter(templates::id.eq_any(&ids))
+        .load::<Template>(conn)?
+        .into_iter()
+        .map(|t| (t.id, t))
+        .collect();
+    let mut files = load_files(conn, &ids)?;
+    Ok(ids
+        .iter()
+        .filter_map(|id| all.remove(id))
+        .map(|t| Source {
+            files: files
+                .remove(&t.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|f| SourceFile {
+                    name: f.name,
+                    code: f.code,
+                })
+                .collect(),
+            name: t.name,
+        })
+        .collect())
+}
+
+fn template_info(template: Template, files: Vec<TemplateFile>) -> anyhow::Result<TemplateInfo> {
+    Ok(TemplateInfo {
+        id: template.id,
+        name: template.name,
+        language: parse_debug(&template.language)?,
+        files: files.into_iter().map(|f| f.name).collect(),
+    })
+}
+
+#[post("/templates")]
+pub async fn create_template(
+    session: Session,
+    pool: web::Data<DbPool>,
+    body: web::Json<CreateTemplateRequest>,
+) -> Result<HttpResponse> {
+    let mut conn = pool.get().map_err(err)?;
+    let user_id = match authenticate(&session, &mut conn, &body.login)? {
+        Some(user_id) => user_id,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    if body.files.is_empty() {
+        return Ok(HttpResponse::BadRequest().json("Template has no files"));
+    }
+    let (template, files) = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let template: Template = diesel::insert_into(crate::schema::templates::table)
+                .values(NewTemplate {
+                    owner_user_id: user_id,
+                    name: body.name.clone(),
+                    language: format!("{:?}", body.language),
+                })
+                .get_result(conn)?;
+            let new_files: Vec<NewTemplateFile> = body
+                .files
+                .iter()
+                .map(|f| NewTemplateFile {
+                    template_id: template.id,
+                    name: f.name.clone(),
+                    code: f.code.clone(),
+                })
+                .collect();
+            diesel::insert_into(crate::schema::template_files::table)
+                .values(new_files)
+                .execute(conn)?;
+            let files = load_files(conn, &[template.id])?.remove(&template.id);
+            Ok((template, files.unwrap_or_default()))
+        })
+        .map_err(err)?;
+    info!(
+        "Template {} ({}) created by {}",
+        template.id, template.name, user_id
+    );
+    Ok(HttpResponse::Ok().json(template_info(template, files).map_err(err)?))
+}
+
+#[post("/templates/list")]
+pub async fn list_templates(
+    session: Session,
+    pool: web::Data<DbPool>,
+    body: web::Json<ListTemplatesRequest>,
+) -> Result<HttpResponse> {
+    use crate::schema::templates::dsl;
+    let mut conn = pool.get().map_err(err)?;
+    let user_id = match authenticate(&session, &mut conn, &body.login)? {
+        Some(user_id) => user_id,
+        None => return Ok(HttpResponse::Ok().json(false)),
+    };
+    let templates: Vec<Template> = dsl::templates
+        .filter(dsl::owner_user_id.eq(user_id))
+        .order(dsl::id)
+        .load(&mut conn)
+        .map_err(err)?;
+    let ids: Vec<i32> = templates.iter().map(|t| t.id).collect();
+    let mut files = load_files(&mut conn, &ids).map_err(err)?;
+    let res = templates
+        .into_iter()
+        .map(|t| {
+            let files = files.remove(&t.id).unwrap_or_default();
+            template_info(t, files)
+        })
+        .collect::<anyhow::Result<Vec<TemplateInfo>>>()
+        .map_err(err)?;
+    Ok(HttpResponse::Ok().json(res))
+}
@@ -1,166 +1,520 @@
-use anyhow::Context;
-use api::def::SubmitRequest;
+use crate::{
+    db::{DbConnection, DbPool},
+    models::{
+        ArchiveSubmission, File, Job, JobTemplateFile, NewArchiveFingerprint, NewArchiveSubmission,
+        NewBlock, NewDiagnostic, NewEvidence, NewMatch, NewRename, Submission,
+    },
+    template::load_job_templates,
+};
 use core::{
-    common::{all_fingerprint, fingerprint, Fingerprint},
-    lang::tokenize_str,
-    matching::{compute_matching_blocks_from_text, Block},
+    common::FINGERPRINT_VERSION,
+    config::DetectionConfig,
+    lang::Language,
+    pipeline::{
+        run_with_references, Progress, Reference, ReferenceStore, Report, Source, SourceFile,
+    },
 };
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use diesel::prelude::*;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
 
-use log::*;
-use std::collections::HashMap;
+// number of hashes in one archive query
+const LOOKUP_CHUNK_SIZE: usize = 10000;
 
-pub struct WorkResult {
-    pub req: SubmitRequest,
-    pub matches: Vec<Match>,
+// number of archived fingerprints in one insert
+const ARCHIVE_CHUNK_SIZE: usize = 10000;
+
+/// Archived submissions of previous jobs, stored in database
+///
+/// Archives are scoped to their owner and language. Fingerprints of other
+/// versions or computed under other parameters are not comparable and thus skipped.
+pub struct ArchiveStore<'a> {
+    // connections are taken per call, not held while the pipeline runs
+    pool: &'a DbPool,
+    owner_user_id: i32,
+    language: Language,
+    config_digest: i64,
+    // archive submission id => submission id
+    submission_ids: HashMap<i64, i32>,
+    // archive submission id => file ids in the order of loaded files
+    file_ids: HashMap<i64, Vec<i32>>,
 }
 
-pub struct Match {
-    pub left_submission_idx: usize,
-    pub left_match_rate: i32,
-    pub right_submission_idx: usize,
-    pub right_match_rate: i32,
-    pub lines_matched: usize,
-    pub blocks: Vec<Block>,
+impl<'a> ArchiveStore<'a> {
+    pub fn new(
+        pool: &'a DbPool,
+        owner_user_id: i32,
+        language: Language,
+        config: &DetectionConfig,
+    ) -> Self {
+        Self {
+            pool,
+            owner_user_id,
+            language,
+            config_digest: config.fingerprint_digest() as i64,
+            submission_ids: HashMap::new(),
+            file_ids: HashMap::new(),
+        }
+    }
 }
 
-pub fn work_blocking(req: SubmitRequest) -> anyhow::Result<WorkResult> {
-    // tokenize template
-    let template_tokens = if let Some(template) = &req.template {
-        tokenize_str(template, req.language)?
-    } else {
-        vec![]
-    };
+impl<'a> ReferenceStore for ArchiveStore<'a> {
+    fn lookup(&mut self, hashes: &[u64]) -> anyhow::Result<Vec<Reference>> {
+        use crate::schema::archive_fingerprints::dsl as fingerprints;
+        use crate::schema::archive_submissions::dsl as archive_submissions;
+        use crate::schema::submissions::dsl as submissions;
 
-    // tokenize sources
-    let mut all_tokens = vec![];
-    for submission in &req.submissions {
-        all_tokens.push(
-            tokenize_str(&submission.code, req.language)
-                .with_context(|| submission.name.clone())?,
-        );
-    }
-    info!("Tokenized {} files in submission", all_tokens.len());
-
-    let template_fingerprint = all_fingerprint(template_tokens.iter().map(|t| t.kind), 40);
-
-    let mut local_tokens = vec![];
-    let mut local_fingerprints = vec![];
-    let mut index: HashMap<u64, Vec<(Fingerprint, usize)>> = HashMap::new();
-    for (i, token) in all_tokens.iter().enumerate() {
-        let fingerprint = fingerprint(token.iter().map(|t| t.kind), 40, 80);
-        info!(
-            "{}: {} tokens, {} fingerprints",
-            req.submissions[i].name,
-            token.len(),
-            fingerprint.len()
-        );
-        // insert to index: fingerprint => f
-        for f in &fingerprint {
-            index.entry(f.hash).or_default().push((*f, i));
+        let conn = &mut self.pool.get()?;
+        let archived: Vec<ArchiveSubmission> = archive_submissions::archive_submissions
+            .filter(archive_submissions::owner_user_id.eq(self.owner_user_id))
+            .filter(archive_submissions::language.eq(format!("{:?}", self.language)))
+            .filter(archive_submissions::fingerprint_version.eq(FINGERPRINT_VERSION as i32))
+            .filter(archive_submissions::config_digest.eq(self.config_digest))
+            .load(conn)?;
+        if archived.is_empty() {
+            return Ok(vec![]);
         }
-        local_fingerprints.push(fingerprint);
-        local_tokens.push(token);
+        let archived_ids: HashSet<i32> = archived.iter().map(|a| a.id).collect();
+
+        // archive submission id => shared hashes
+        let mut shared: HashMap<i32, Vec<u64>> = HashMap::new();
+        for chunk in hashes.chunks(LOOKUP_CHUNK_SIZE) {
+            let chunk: Vec<i64> = chunk.iter().map(|h| *h as i64).collect();
+            let rows: Vec<(i32, i64)> = fingerprints::archive_fingerprints
+                .filter(fingerprints::hash.eq_any(chunk))
+                .select((fingerprints::archive_submission_id, fingerprints::hash))
+                .load(conn)?;
+            for (id, hash) in rows {
+                if archived_ids.contains(&id) {
+                    shared.entry(id).or_default().push(hash as u64);
+                }
+            }
+        }
+
+        let archived: Vec<&ArchiveSubmission> = archived
+            .iter()
+            .filter(|a| shared.contains_key(&a.id))
+            .collect();
+        let submission_ids: Vec<i32> = archived.iter().map(|a| a.submission_id).collect();
+        let names: HashMap<i32, String> = submissions::submissions
+            .filter(submissions::id.eq_any(submission_ids))
+            .load::<Submission>(conn)?
+            .into_iter()
+            .map(|s| (s.id, s.name))
+            .collect();
+
+        let mut res = vec![];
+        for a in archived {
+            self.submission_ids.insert(a.id as i64, a.submission_id);
+            res.push(Reference {
+                id: a.id as i64,
+                name: format!(
+                    "{} [{}]",
+                    names.get(&a.submission_id).cloned().unwrap_or_default(),
+                    a.label
+                ),
+                hashes: shared.remove(&a.id).unwrap_or_default(),
+            });
+        }
+        Ok(res)
     }
 
-    // exclude fingerprints in template
-    for f in &template_fingerprint {
-        index.remove(&f.hash);
+    fn load(&mut self, reference: &Reference) -> anyhow::Result<Source> {
+        use crate::schema::files::dsl;
+
+        let submission_id = self.submission_ids[&reference.id];
+        let files: Vec<File> = dsl::files
+            .filter(dsl::submission_id.eq(submission_id))
+            .order((dsl::name, dsl::id))
+            .load(&mut self.pool.get()?)?;
+        self.file_ids
+            .insert(reference.id, files.iter().map(|f| f.id).collect());
+        Ok(Source {
+            name: reference.name.clone(),
+            files: files
+                .into_iter()
+                .map(|f| SourceFile {
+                    name: f.name,
+                    code: f.code,
+                })
+                .collect(),
+        })
     }
+}
 
-    // create two dimensional matrix
-    let mut m = vec![0; all_tokens.len() * all_tokens.len()];
-    for hash in index.keys() {
-        let v = &index[hash];
-        if v.len() > 10 {
-            // too common, skip
-            continue;
-        }
+/// Job loaded from database, ready to run
+pub struct WorkRequest {
+    pub job_id: i32,
+    pub user_id: i32,
+    pub language: Language,
+    // template given with the job, followed by those from library
+    pub templates: Vec<Source>,
+    pub config: DetectionConfig,
+    // archive label
+    pub archive: Option<String>,
+    pub sources: Vec<Source>,
+    // submission id of each source
+    pub submission_ids: Vec<i32>,
+    // file ids of each source, in the order of its files
+    pub file_ids: Vec<Vec<i32>>,
+}
 
-        if v.len() > 5 {
-            debug!("Found {} entries:", v.len());
-            for (f, i) in v {
-                debug!(
-                    "{} offset {} L{} C{}",
-                    req.submissions[*i].name,
-                    f.offset,
-                    local_tokens[*i][f.offset].line,
-                    local_tokens[*i][f.offset].column,
-                );
-            }
+/// Parse a value stored by its Debug representation, e.g. Language and Stage
+pub fn parse_debug<T: DeserializeOwned>(s: &str) -> anyhow::Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(
+        s.to_string(),
+    ))?)
+}
+
+impl WorkRequest {
+    pub fn load(conn: &mut DbConnection, job: &Job) -> anyhow::Result<Self> {
+        use crate::schema::files::dsl as files;
+        use crate::schema::job_template_files::dsl as job_template_files;
+        use crate::schema::submissions::dsl as submissions;
+
+        let submissions: Vec<Submission> = submissions::submissions
+            .filter(submissions::job_id.eq(job.id))
+            .order(submissions::id)
+            .load(conn)?;
+        let mut sources = vec![];
+        let mut file_ids = vec![];
+        for submission in &submissions {
+            // files are inserted in submitted order
+            let files: Vec<File> = files::files
+                .filter(files::submission_id.eq(submission.id))
+                .order(files::id)
+                .load(conn)?;
+            file_ids.push(files.iter().map(|f| f.id).collect());
+            sources.push(Source {
+                name: submission.name.clone(),
+                files: files
+                    .into_iter()
+                    .map(|f| SourceFile {
+                        name: f.name,
+                        code: f.code,
+                    })
+                    .collect(),
+            });
         }
-        // add to matrix
-        for i in 0..v.len() {
-            for j in (i + 1)..v.len() {
-                if v[i].1 == v[j].1 {
-                    continue;
-                }
-                m[v[i].1 * all_tokens.len() + v[j].1] += 1;
-                m[v[j].1 * all_tokens.len() + v[i].1] += 1;
-            }
+        let mut templates = vec![];
+        if let Some(code) = &job.template {
+            templates.push(Source {
+                name: "template".to_string(),
+                files: vec![SourceFile {
+                    name: "template".to_string(),
+                    code: code.clone(),
+                }],
+            });
         }
+        let template_files: Vec<JobTemplateFile> = job_template_files::job_template_files
+            .filter(job_template_files::job_id.eq(job.id))
+            .order(job_template_files::id)
+            .load(conn)?;
+        if !template_files.is_empty() {
+            templates.push(Source {
+                name: "template".to_string(),
+                files: template_files
+                    .into_iter()
+                    .map(|f| SourceFile {
+                        name: f.name,
+                        code: f.code,
+                    })
+                    .collect(),
+            });
+        }
+        templates.extend(load_job_templates(conn, job.id)?);
+        Ok(Self {
+            job_id: job.id,
+            user_id: job.creator_user_id,
+            language: parse_debug(&job.language)?,
+            templates,
+            config: serde_json::from_str(&job.config)?,
+            archive: job.archive.clone(),
+            sources,
+            submission_ids: submissions.iter().map(|s| s.id).collect(),
+            file_ids,
+        })
     }
+}
 
-    // collect highest matches
-    let mut sorted_m: Vec<_> = m.iter().enumerate().collect();
-    sorted_m.sort_by_key(|(_, val)| **val);
-    let matches_res: Vec<anyhow::Result<Match>> = sorted_m
-        .par_iter()
-        .rev()
-        .take(200)
-        .filter_map(|(i, num_matches)| {
-            let left = i % all_tokens.len();
-            let right = i / all_tokens.len();
-            if left <= right {
-                // skip duplicate
-                return None;
-            }
-            let num_matches = **num_matches;
-            // show debug message
-            debug!(
-                "Possible plagarism: {} and {}: {} matches",
-                req.submissions[left].name, req.submissions[right].name, num_matches,
-            );
-
-            let blocks = match compute_matching_blocks_from_text(
-                &req.submissions[left].code,
-                &req.submissions[right].code,
-                req.language,
-                &req.template,
-            ) {
-                Ok(blocks) => blocks,
-                Err(err) => {
-                    return Some(Err(err));
-                }
-            };
+pub struct WorkResult {
+    pub report: Report,
+    // submission id of each reference
+    pub reference_submission_ids: Vec<i32>,
+    // file ids of each reference, empty if not loaded
+    pub reference_file_ids: Vec<Vec<i32>>,
+}
 
-            let mut left_matched_lines = 0;
-            let mut right_matched_lines = 0;
-            for block in &blocks {
-                left_matched_lines += block.left_line_to - block.left_line_from + 1;
-                right_matched_lines += block.right_line_to - block.right_line_from + 1;
-            }
-            let left_lines = req.submissions[left].code.lines().count();
-            let right_lines = req.submissions[right].code.lines().count();
-
-            Some(Ok(Match {
-                left_submission_idx: left,
-                left_match_rate: (left_matched_lines * 100 / left_lines) as i32,
-                right_submission_idx: right,
-                right_match_rate: (right_matched_lines * 100 / right_lines) as i32,
-                lines_matched: left_matched_lines + right_matched_lines,
-                blocks,
-            }))
+pub fn work_blocking(
+    req: &WorkRequest,
+    pool: &DbPool,
+    progress: &dyn Progress,
+) -> anyhow::Result<WorkResult> {
+    let mut store = ArchiveStore::new(pool, req.user_id, req.language, &req.config);
+    let report = run_with_references(
+        &req.sources,
+        &req.templates,
+        req.language,
+        &mut store,
+        progress,
+        &req.config,
+    )?;
+    let reference_submission_ids = report
+        .references
+        .iter()
+        .map(|r| store.submission_ids[&r.id])
+        .collect();
+    let reference_file_ids = report
+        .references
+        .iter()
+        .map(|r| store.file_ids.remove(&r.id).unwrap_or_default())
+        .collect();
+    Ok(WorkResult {
+        report,
+        reference_submission_ids,
+        reference_file_ids,
+    })
+}
+
+/// Store matches of finished job, and archive its submissions if requested
+pub fn store_result(
+    conn: &mut DbConnection,
+    req: &WorkRequest,
+    work: &WorkResult,
+) -> QueryResult<()> {
+    let job_id = req.job_id;
+
+    // indices of pairs run over submissions, then references
+    let n = req.submission_ids.len();
+    let submission_id = |i: usize| {
+        if i < n {
+            req.submission_ids[i]
+        } else {
+            work.reference_submission_ids[i - n]
+        }
+    };
+    let file_id = |i: usize, file: usize| {
+        if i < n {
+            req.file_ids[i][file]
+        } else {
+            work.reference_file_ids[i - n][file]
+        }
+    };
+
+    // insert problems of submissions
+    let new_diagnostics: Vec<NewDiagnostic> = work
+        .report
+        .diagnostics
+        .iter()
+        .map(|d| NewDiagnostic {
+            submission_id: req.submission_ids[d.submission],
+            file: d.file.clone(),
+            severity: format!("{:?}", d.severity),
+            message: d.message.clone(),
         })
         .collect();
+    diesel::insert_into(crate::schema::diagnostics::table)
+        .values(new_diagnostics)
+        .execute(conn)?;
 
-    let mut matches = vec![];
-    for m in matches_res {
-        matches.push(m?);
+    // record code shared by many submissions
+    if !work.report.common_code.is_empty() {
+        use crate::schema::jobs::dsl;
+        let common_code: Vec<&str> = work
+            .report
+            .common_code
+            .iter()
+            .map(|c| c.code.as_str())
+            .collect();
+        diesel::update(dsl::jobs.filter(dsl::id.eq(job_id)))
+            .set(dsl::common_code.eq(common_code.join("\n")))
+            .execute(conn)?;
     }
 
-    matches.sort_by_key(|m| m.lines_matched);
-    matches.reverse();
+    // insert matches
+    let new_matches: Vec<NewMatch> = work
+        .report
+        .pairs
+        .iter()
+        .map(|m| NewMatch {
+            job_id,
+            left_submission_id: submission_id(m.left),
+            left_match_rate: m.left_match_rate,
+            right_submission_id: submission_id(m.right),
+            right_match_rate: m.right_match_rate,
+            lines_matched: m.lines_matched as i32,
+            left_verbatim_rate: m.left_verbatim_rate,
+            right_verbatim_rate: m.right_verbatim_rate,
+            left_token_rate: m.left_token_rate,
+            right_token_rate: m.right_token_rate,
+            similarity: m.similarity,
+            left_containment: m.left_containment,
+            right_containment: m.right_containment,
+            longest_block: m.longest_block as i32,
+            score: i64::try_from(m.score).unwrap_or(i64::MAX),
+        })
+        .collect();
+    let match_ids: Vec<i32> = diesel::insert_into(crate::schema::matches::table)
+        .values(new_matches)
+        .returning(crate::schema::matches::dsl::id)
+        .get_results(conn)?;
 
-    Ok(WorkResult { req, matches })
+    for (match_id, m) in match_ids.iter().zip(work.report.pairs.iter()) {
+        // insert blocks
+        let new_blocks: Vec<NewBlock> = m
+            .blocks
+            .iter()
+            .map(|b| NewBlock {
+                match_id: *match_id,
+                left_line_from: b.left_line_from as i32,
+                left_line_to: b.left_line_to as i32,
+                right_line_from: b.right_line_from as i32,
+                right_line_to: b.right_line_to as i32,
+                left_file_id: file_id(m.left, b.left_file),
+                right_file_id: file_id(m.right, b.right_file),
+                left_column_from: b.left_column_from as i32,
+                left_column_to: b.left_column_to as i32,
+                right_column_from: b.right_column_from as i32,
+                right_column_to: b.right_column_to as i32,
+                left_gap: b.left_gap as i32,
+                right_gap: b.right_gap as i32,
+                rename_consistency: b.rename_consistency as i32,
+            })
+            .collect();
+        let block_ids: Vec<i32> = diesel::insert_into(crate::schema::blocks::table)
+            .values(new_blocks)
+            .returning(crate::schema::blocks::dsl::id)
+            .get_results(conn)?;
+
+        // insert renames of blocks
+        let new_renames: Vec<NewRename> = block_ids
+            .iter()
+            .zip(m.blocks.iter())
+            .flat_map(|(block_id, b)| {
+                b.renames.iter().map(|r| NewRename {
+                    block_id: *block_id,
+                    left_name: r.left.clone(),
+                    right_name: r.right.clone(),
+                    count: r.count as i32,
+                })
+            })
+            .collect();
+        diesel::insert_into(crate::schema::renames::table)
+            .values(new_renames)
+            .execute(conn)?;
+
+        // insert comments and literals found on both sides
+        let new_evidence: Vec<NewEvidence> = m
+            .evidence
+            .iter()
+            .map(|e| NewEvidence {
+                match_id: *match_id,
+                kind: format!("{:?}", e.kind),
+                text: e.text.clone(),
+                left_file_id: file_id(m.left, e.left_file),
+                left_line: e.left_line as i32,
+                right_file_id: file_id(m.right, e.right_file),
+                right_line: e.right_line as i32,
+            })
+            .collect();
+        diesel::insert_into(crate::schema::evidence::table)
+            .values(new_evidence)
+            .execute(conn)?;
+    }
+
+    // archive submissions for later jobs
+    if let Some(label) = &req.archive {
+        let new_archive_submissions: Vec<NewArchiveSubmission> = req
+            .submission_ids
+            .iter()
+            .map(|id| NewArchiveSubmission {
+                submission_id: *id,
+                owner_user_id: req.user_id,
+                language: format!("{:?}", req.language),
+                label: label.clone(),
+                fingerprint_version: FINGERPRINT_VERSION as i32,
+                config_digest: req.config.fingerprint_digest() as i64,
+            })
+            .collect();
+        let archive_submission_ids: Vec<i32> =
+            diesel::insert_into(crate::schema::archive_submissions::table)
+                .values(new_archive_submissions)
+                .returning(crate::schema::archive_submissions::dsl::id)
+                .get_results(conn)?;
+        let new_fingerprints: Vec<NewArchiveFingerprint> = archive_submission_ids
+            .iter()
+            .zip(work.report.hashes.iter())
+            .flat_map(|(id, hashes)| {
+                hashes.iter().map(|hash| NewArchiveFingerprint {
+                    archive_submission_id: *id,
+                    hash: *hash as i64,
+                })
+            })
+            .collect();
+        for chunk in new_fingerprints.chunks(ARCHIVE_CHUNK_SIZE) {
+            diesel::insert_into(crate::schema::archive_fingerprints::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::ArchiveStore;
+    use crate::{
+        common::generate_uuid,
+        db::connect,
+        models::{NewArchiveFingerprint, NewArchiveSubmission},
+    };
+    use core::{
+        common::FINGERPRINT_VERSION, config::DetectionConfig, lang::Language,
+        pipeline::ReferenceStore,
+    };
+    use diesel::prelude::*;
+
+    #[test]
+    fn test_archive_config() {
+        let path = std::env::temp_dir().join(format!("jieplag-{}.db", generate_uuid()));
+        let pool = connect(&path.display().to_string()).unwrap();
+        let conn = &mut pool.get().unwrap();
+
+        // archived by a job with other winnowing parameters
+        let archived = DetectionConfig {
+            noise: 30,
+            guarantee: 60,
+            ..Default::default()
+        };
+        let id: i32 = diesel::insert_into(crate::schema::archive_submissions::table)
+            .values(NewArchiveSubmission {
+                submission_id: 1,
+                owner_user_id: 1,
+                language: format!("{:?}", Language::Rust),
+                label: "2025".to_string(),
+                fingerprint_version: FINGERPRINT_VERSION as i32,
+                config_digest: archived.fingerprint_digest() as i64,
+            })
+            .returning(crate::schema::archive_submissions::dsl::id)
+            .get_result(conn)
+            .unwrap();
+        diesel::insert_into(crate::schema::archive_fingerprints::table)
+            .values(NewArchiveFingerprint {
+                archive_submission_id: id,
+                hash: 42,
+            })
+            .execute(conn)
+            .unwrap();
+
+        let lookup = |config: &DetectionConfig| {
+            ArchiveStore::new(&pool, 1, Language::Rust, config)
+                .lookup(&[42])
+                .unwrap()
+                .len()
+        };
+        assert_eq!(lookup(&archived), 1);
+        // hashes under other parameters only line up by chance
+        assert_eq!(lookup(&DetectionConfig::default()), 0);
+        std::fs::remove_file(&path).ok();
+    }
 }
@@ -0,0 +1,255 @@
+//! End-to-end test of the server on a temporary SQLite database
+#![cfg(feature = "sqlite")]
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::Key,
+    http::StatusCode,
+    test::{self, TestRequest},
+    web, App,
+};
+use api::def::{
+    CreateTemplateRequest, JobState, JobStatus, LoginRequest, MatchDetail, MatchList,
+    ShareLinkRequest, ShareLinkResponse, Submission, SubmissionInfo, SubmitRequest, SubmitResponse,
+    TemplateInfo,
+};
+use core::{
+    config::DetectionConfig,
+    evidence::EvidenceKind,
+    lang::Language,
+    pipeline::{Severity, SourceFile},
+};
+use diesel::RunQueryDsl;
+use server::{
+    common::generate_uuid, db::connect, models::NewUser, queue::JobQueue, routes::configure,
+    session::hash,
+};
+use std::time::Duration;
+
+const CODE: &str = "fn main() {\n    let mut sum = 0;\n    for i in 0..10 {\n        if i % 2 == 0 {\n            sum += i * i;\n        } else {\n            sum -= i;\n        }\n    }\n    println!(\"{}\", sum);\n}\n";
+const COMMENT: &str = "// works for teh samples\n";
+const OTHER: &str = "struct Point { x: i32, y: i32 }\nimpl Point {\n    fn norm(&self) -> i32 { self.x.abs() + self.y.abs() }\n}\n";
+
+fn submission(name: &str, code: &str) -> Submission {
+    Submission {
+        name: name.to_string(),
+        files: vec![SourceFile {
+            name: "main.rs".to_string(),
+            code: code.to_string(),
+        }],
+    }
+}
+
+fn login() -> Option<LoginRequest> {
+    Some(LoginRequest {
+        user_name: "teacher".to_string(),
+        password: "password".to_string(),
+    })
+}
+
+// not `#[actix_web::test]`, its expansion refers to std `core` shadowed by our crate
+#[test]
+fn test_submit() {
+    actix_web::rt::System::new().block_on(submit());
+}
+
+async fn submit() {
+    let path = std::env::temp_dir().join(format!("jieplag-{}.db", generate_uuid()));
+    std::env::set_var("DATABASE_URL", path.display().to_string());
+    std::env::set_var("COOKIE_SECRET", "secret");
+    std::env::set_var("PUBLIC_URL", "http://localhost:8765");
+
+    let pool = connect(&path.display().to_string()).unwrap();
+    let (salt, password) = hash("password").unwrap();
+    diesel::insert_into(server::schema::users::table)
+        .values(NewUser {
+            user_name: "teacher".to_string(),
+            salt: salt.to_vec(),
+            password: password.to_vec(),
+        })
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    let queue = JobQueue::start(pool.clone(), 1).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(queue))
+            .wrap(SessionMiddleware::new(
+                CookieSessionStore::default(),
+                Key::generate(),
+            ))
+            .configure(configure),
+    )
+    .await;
+
+    // store starter code in library
+    let req = TestRequest::post()
+        .uri("/api/templates")
+        .set_json(CreateTemplateRequest {
+            login: login(),
+            name: "starter".to_string(),
+            language: Language::Rust,
+            files: vec![SourceFile {
+                name: "point.rs".to_string(),
+                code: OTHER.to_string(),
+            }],
+        })
+        .to_request();
+    let template: TemplateInfo = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(template.files, ["point.rs"]);
+
+    let request = |templates: Vec<i32>| {
+        TestRequest::post()
+            .uri("/api/submit")
+            .set_json(SubmitRequest {
+                login: login(),
+                language: Language::Rust,
+                template: None,
+                template_files: vec![],
+                templates,
+                submissions: vec![
+                    submission("a", &format!("{}{}", CODE, COMMENT)),
+                    submission("b", &format!("{}{}", CODE.replace("sum", "total"), COMMENT)),
+                    // the broken line is skipped
+                    submission("c", &format!("{}¤\n", OTHER)),
+                ],
+                config: DetectionConfig {
+                    noise: 10,
+                    guarantee: 20,
+                    initial_search_length: 20,
+                    minimum_match_length: 10,
+                    verbatim: true,
+                    ..Default::default()
+                },
+                archive: None,
+            })
+            .to_request()
+    };
+
+    // templates not in the library of the user are rejected
+    assert_eq!(
+        test::call_service(&app, request(vec![template.id + 1]))
+            .await
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // submit and wait for job, its status is not public either
+    let res: SubmitResponse = test::call_and_read_body_json(&app, request(vec![template.id])).await;
+    let req = TestRequest::get()
+        .uri(&format!("/api/jobs/{}", res.slug))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    let req = TestRequest::post()
+        .uri(&format!("/api/jobs/{}/links", res.slug))
+        .set_json(ShareLinkRequest {
+            login: login(),
+            hours: 1,
+        })
+        .to_request();
+    let link: ShareLinkResponse = test::call_and_read_body_json(&app, req).await;
+    let mut status: JobStatus;
+    loop {
+        let req = TestRequest::get()
+            .uri(&format!("/api/jobs/{}?token={}", res.slug, link.token))
+            .to_request();
+        status = test::call_and_read_body_json(&app, req).await;
+        if status.state.finished() {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status.state, JobState::Done, "{:?}", status.error);
+
+    // results are not public
+    let req = TestRequest::get()
+        .uri(&format!("/api/jobs/{}/matches", res.slug))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    let req = TestRequest::get()
+        .uri(&format!("/results/{}/", res.slug))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    // but visible via share link
+    let req = TestRequest::get()
+        .uri(&format!(
+            "/api/jobs/{}/matches?sort=lines_matched&desc=true&token={}",
+            res.slug, link.token
+        ))
+        .to_request();
+    let matches: MatchList = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(matches.total, matches.matches.len());
+    let mut names = [
+        matches.matches[0].left_submission_name.as_str(),
+        matches.matches[0].right_submission_name.as_str(),
+    ];
+    names.sort();
+    assert_eq!(names, ["a", "b"]);
+    // only lines without the renamed variable are verbatim
+    let verbatim = matches.matches[0].left_verbatim_rate;
+    assert!(0 < verbatim && verbatim < matches.matches[0].left_match_rate);
+    assert!(matches.matches[0].similarity > 0);
+    assert!(matches.matches[0].score > 0);
+
+    // job page can be sorted by any metric
+    let req = TestRequest::get()
+        .uri(&format!(
+            "/results/{}/?sort=similarity&desc=true&token={}",
+            res.slug, link.token
+        ))
+        .to_request();
+    let page = test::call_and_read_body(&app, req).await;
+    let page = String::from_utf8_lossy(&page);
+    assert!(page.contains("?sort=similarity&amp;desc=false"), "{}", page);
+
+    let req = TestRequest::get()
+        .uri(&format!(
+            "/api/jobs/{}/matches/{}?token={}",
+            res.slug, matches.matches[0].index, link.token
+        ))
+        .to_request();
+    let detail: MatchDetail = test::call_and_read_body_json(&app, req).await;
+    assert!(!detail.blocks.is_empty());
+    // the renamed variable is reported on either side
+    let renames: Vec<(&str, &str)> = detail
+        .blocks
+        .iter()
+        .flat_map(|b| &b.renames)
+        .map(|r| (r.left.as_str(), r.right.as_str()))
+        .collect();
+    assert!(
+        renames.contains(&("sum", "total")) || renames.contains(&("total", "sum")),
+        "{:?}",
+        renames
+    );
+    assert_eq!(detail.evidence.len(), 1);
+    assert_eq!(detail.evidence[0].kind, EvidenceKind::Comment);
+    assert_eq!(detail.evidence[0].text, "works for teh samples");
+    assert_eq!(detail.evidence[0].left_line, 11);
+
+    let req = TestRequest::get()
+        .uri(&format!(
+            "/api/jobs/{}/submissions?token={}",
+            res.slug, link.token
+        ))
+        .to_request();
+    let submissions: Vec<SubmissionInfo> = test::call_and_read_body_json(&app, req).await;
+    let diagnostics: Vec<(&str, Severity)> = submissions
+        .iter()
+        .flat_map(|s| s.diagnostics.iter().map(|d| (s.name.as_str(), d.severity)))
+        .collect();
+    assert_eq!(diagnostics, [("c", Severity::Warning)]);
+
+    std::fs::remove_file(&path).ok();
+}